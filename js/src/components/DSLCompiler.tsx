@@ -1,7 +1,8 @@
-import React, { useState } from 'react';
+import React, { useState, useEffect } from 'react';
 import { Button, Col, FormControl, Row, Tab } from 'react-bootstrap';
 import { TextViewer } from './displays/textViewer';
-import { asm2dsl, dsl2machine } from '../utils/language/compilers';
+import { AssembleAsync } from '../utils/workerCommunication/messages';
+import { createWebworkerAsync, AllWorkers } from '../utils/workerCommunication/comm';
 import { isNullOrWhitespace } from '../utils/stringUtils';
 import { MyMonacoEditor } from './displays/monacoEditor/MyMonacoEditor';
 import { useGlobalDslWasmState } from '../state/globalState';
@@ -14,20 +15,34 @@ function useCompiler(text: string): [string, string, () => void] {
 	const [compiledText, setCompiledText] = useState<string>('');
 	const [compilerError, setCompilerError] = useState('');
 	const [_, setGlobalStateText] = useGlobalDslWasmState('dslCompiled');
-	const compile = () => {
-		try {
-			// setCompiledText(dsl2machine(asm2dsl(text)));
-			const _compiledText = asm2dsl(text);
-			setCompiledText(_compiledText);
+	const [workerId, setWorkerId] = useState<string | null>(null);
+
+	useEffect(
+		() => () => {
+			workerId && AllWorkers.killWorker(workerId);
+		},
+		[workerId]
+	);
+
+	// the rust assembler runs in a worker of its own, made on the first compile
+	const compileAsync = async () => {
+		const _workerId = workerId || await createWebworkerAsync();
+		setWorkerId(_workerId);
+		const result = await AssembleAsync(_workerId, text);
+		if (result === null) {
+			setCompilerError('The assembler did not respond');
+		}
+		else if (result.assembled) {
+			setCompiledText(result.text);
 			setCompilerError('Compiled successfully');
-			setGlobalStateText(_compiledText);
+			setGlobalStateText(result.text);
 		}
-		catch (e) {
-			setCompilerError(e.message);
+		else {
+			setCompilerError(result.text);
 		}
 	};
 
-	return [compiledText, compilerError, compile];
+	return [compiledText, compilerError, () => { compileAsync(); }];
 }
 
 export function DslCompiler({}: DslCompilerProps) {
@@ -1,26 +1,247 @@
 export enum SyscallsEnum {
 	/**
-	 * init new buffer with ID from bus (so JS can reference buffer with given ID)
-	 * [follow with syscall 2, syscall 3, and 6]
+	 * Buffers (handled in rust)
+	 */
+
+	/**
+	 * init new buffer, id of the buffer -> bus
+	 * [follow with syscall 2, syscall 3, and 4]
 	 * IDs are shared between inputs and outputs
 	 */
 	CreateBuffer = 1,
 
+	/**
+	 * bus holds a pointer to [id, value]
+	 * each property can only be set once
+	 */
 	SetBufferHead = 2,
 	SetBufferLength = 3,
 	SetBufferType = 4,
 
+	/**
+	 * bus holds the id of the buffer
+	 */
 	DeleteBuffer = 5,
 
 	/**
-	 * File stuff
+	 * Files (handled in rust)
+	 * Names and contents are one byte per word, names are zero terminated.
+	 * Read, Write, Seek and List return 0xFFFFFFFF if they fail.
 	 */
 
+	/**
+	 * [name address, flags], handle -> bus, 0 if it failed
+	 * flags: read = 1, write = 2, create = 4, truncate = 8, append = 16
+	 */
+	OpenFile = 9,
 
 	/**
-	 * Other
+	 * [handle, address, count], bytes read -> bus, 0 at the end of the file
+	 */
+	ReadFile = 10,
+
+	/**
+	 * [handle, address, count], bytes written -> bus
+	 */
+	WriteFile = 11,
+
+	/**
+	 * [handle, signed offset, from (start = 0, current = 1, end = 2)],
+	 * new position -> bus
+	 */
+	SeekFile = 12,
+
+	/**
+	 * bus holds the handle
+	 */
+	CloseFile = 13,
+
+	/**
+	 * [index, address, length], the name of the file at the index is
+	 * written zero terminated, its length -> bus
+	 */
+	ListFiles = 14,
+
+	/**
+	 * Time (handled in rust)
+	 * The clock counts milliseconds, either as the host reports them or one
+	 * tick per so many instructions (see r_SetClockMode).
+	 */
+
+	/**
+	 * sleep for the number of milliseconds in the bus
+	 * the processor waits in the Sleeping status until the time passed
 	 */
 	Sleep = 20,
 
+	/**
+	 * low 32 bits of the clock in milliseconds -> bus
+	 */
+	GetTime = 21,
+
+	/**
+	 * bus holds a pointer to [timer (0 to 7), period in milliseconds]
+	 * a period of 0 turns the timer off
+	 */
+	SetTimer = 22,
+
+	/**
+	 * bus holds a timer, number of times its period passed since the last
+	 * call -> bus
+	 */
+	TimerFired = 23,
+
+	/**
+	 * Random numbers (handled in rust)
+	 * The generator is seeded with 0 unless the program or the host seeds it,
+	 * the same seed always gives the same numbers.
+	 */
+
+	/**
+	 * bus holds a bound, a number from 0 up to the bound -> bus
+	 * a bound of 0 gives any 32 bit number
+	 */
+	Random = 24,
+
+	/**
+	 * bus holds the seed
+	 */
+	SeedRandom = 25,
+
+	/**
+	 * Other
+	 */
 	Alert = 30,
+
+	/**
+	 * Terminal (handled in rust)
+	 */
+
+	/**
+	 * write the low byte of the bus to the terminal output
+	 */
+	WriteChar = 31,
+
+	/**
+	 * bus holds the address of a zero terminated string, one byte per word
+	 */
+	WriteString = 32,
+
+	/**
+	 * bus holds a pointer to [buffer address, buffer length]
+	 * the next line is written to the buffer without its newline and zero
+	 * terminated, its length -> bus, or 0xFFFFFFFF at the end of the input
+	 * the processor waits (status WaitingForInput) until a line arrives
+	 */
+	ReadLine = 33,
+
+	/**
+	 * Memory (handled in rust)
+	 */
+
+	/**
+	 * allocate the number of words in the bus, address of the allocation -> bus
+	 * (0 if the allocation failed)
+	 */
+	Allocate = 40,
+
+	/**
+	 * free the allocation whose address is in the bus, buffers in it are deleted
+	 * double frees and unknown addresses return an error
+	 */
+	Free = 41,
+
+	/**
+	 * set permissions on a range of memory, bus holds a pointer to
+	 * [start, length, permissions] where permissions is a mask of
+	 * read = 1, write = 2, execute = 4 (0 makes a guard region),
+	 * the permissions the image declares can't be raised
+	 */
+	Protect = 42,
+
+	/**
+	 * Graphics (handled in rust)
+	 * All of these draw palette indices into the OUTPUT_SCREEN buffer and
+	 * clip against the screen size. The bus holds a pointer to the arguments,
+	 * coordinates are signed.
+	 */
+
+	/**
+	 * [x, y, width, height, color]
+	 */
+	FillRect = 50,
+
+	/**
+	 * [x0, y0, x1, y1, color]
+	 */
+	DrawLine = 51,
+
+	/**
+	 * [center x, center y, radius, color, filled (0 or 1)]
+	 */
+	DrawCircle = 52,
+
+	/**
+	 * [x, y, sprite address, width, height, transparent color]
+	 * the sprite is width * height color indices stored row by row
+	 */
+	Blit = 53,
+
+	/**
+	 * [dx, dy, fill color]
+	 */
+	Scroll = 54,
+
+	/**
+	 * [x, y, color, string address]
+	 * the string is zero terminated, one character per word, drawn with the
+	 * built in 5x7 font in 6x8 cells, '\n' starts a new line
+	 */
+	DrawText = 55,
+
+	/**
+	 * shows everything drawn since the last present and pauses the program
+	 * until the host takes the frame (status WaitingForFrame)
+	 * after the first present the host only ever sees presented frames
+	 */
+	Present = 56,
+
+	/**
+	 * Keyboard (handled in rust)
+	 */
+
+	/**
+	 * next key event -> bus, 0 if there is none
+	 * an event is the key code, with 1 << 16 set for a press
+	 */
+	PollKey = 60,
+
+	/**
+	 * bus holds a key code, 1 if the key is held -> bus, 0 otherwise
+	 */
+	KeyState = 61,
+
+	/**
+	 * bus holds a pointer to 4 words, the next pointer event is written
+	 * there as [kind, x, y, data], 1 -> bus if there was an event, 0 if not
+	 * kinds are move = 1, down = 2, up = 3, wheel = 4, data is the button
+	 * or the signed wheel delta, x and y are screen buffer pixels
+	 */
+	PollPointer = 62,
+
+	/**
+	 * bus holds a pointer to 3 words, [x, y, held buttons mask] is written there
+	 */
+	PointerState = 63,
+
+	/**
+	 * Audio (handled in rust)
+	 */
+
+	/**
+	 * bus holds a pointer to [address, count] of signed 16 bit samples,
+	 * one per word, they are queued for the PCM channel
+	 * number of samples that fit -> bus
+	 */
+	QueueSamples = 70,
 }
@@ -0,0 +1,53 @@
+import { InitializeWindowBarrel } from './windowBarrel';
+
+// how much audio is kept queued ahead of what is playing, in seconds
+const LEAD_TIME = 0.1;
+
+let context: AudioContext | null = null;
+// when the last queued samples end, in the context's time
+let queuedUntil = 0;
+
+/**
+ * Browsers only let an AudioContext start after a user gesture, so it is made
+ * the first time a program runs
+ */
+function GetAudioContext(): AudioContext {
+	if (context === null) {
+		context = new AudioContext();
+	}
+	return context;
+}
+
+export function GetAudioSampleRate() {
+	return GetAudioContext().sampleRate;
+}
+
+/**
+ * How many samples rust has to make to keep LEAD_TIME of audio queued
+ */
+export function GetSamplesNeeded() {
+	const ctx = GetAudioContext();
+	queuedUntil = Math.max(queuedUntil, ctx.currentTime);
+	const missing = LEAD_TIME - (queuedUntil - ctx.currentTime);
+	return Math.max(0, Math.floor(missing * ctx.sampleRate));
+}
+
+/**
+ * Plays mono samples between -1 and 1 right after the ones queued before
+ */
+export function PlaySamples(samples: Float32Array) {
+	if (samples.length === 0) return;
+	const ctx = GetAudioContext();
+	const buffer = ctx.createBuffer(1, samples.length, ctx.sampleRate);
+	buffer.copyToChannel(samples, 0);
+	const source = ctx.createBufferSource();
+	source.buffer = buffer;
+	source.connect(ctx.destination);
+	queuedUntil = Math.max(queuedUntil, ctx.currentTime);
+	source.start(queuedUntil);
+	queuedUntil += buffer.duration;
+}
+
+InitializeWindowBarrel('audioDriver', {
+	PlaySamples,
+});
@@ -160,12 +160,20 @@ export function DrawRectEmptyInnerWidth(x: Scalar, y: Scalar, width: Scalar, hei
 	ctx.rect(x + dx + 0.5, y + dy + 0.5, width + 1, height + 1);
 }
 
+/**
+ * Copies ready-made pixels onto the canvas, ignoring transforms and colors
+ */
+export function PutImage(image: ImageData) {
+	ctx.putImageData(image, dx, dy);
+}
+
 InitializeWindowBarrel('drawing', {
 	DrawCircle,
 	DrawRectEmptyOuterWidth,
 	DrawRectEmptyInnerWidth,
 	DrawRectSolid,
 	DrawSegment,
+	PutImage,
 	Begin,
 	Flush,
 	SetCanvasAsLayer,
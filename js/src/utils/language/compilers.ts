@@ -1,16 +1,11 @@
 import { toInt } from '../generalUtils';
-import { AsmCompiler } from './asmCompiler';
-import { instructions } from './dsla';
 
+// lines without a number take no address, like in listing.rs
 export function dsl2machine(text: string): number[] {
-	return text.split(/[\n\r]+/g).map(toInt).map(n => isNaN(n) ? 0 : n);
-}
-
-export function asm2dsl(text: string): string {
-
-	const compiler = new AsmCompiler(instructions);
-
-	return compiler.emit(text.split(/\n/g));
+	return text.split(/[\n\r]+/g)
+		.map(line => line.split('#')[0].trim())
+		.filter(line => line.length > 0)
+		.map(toInt);
 }
 
 export function js2asm(text: string): string {
@@ -54,7 +54,8 @@ export const _DslOpcodes = {
 
 	// Syscall: (i: i) => [21, i],
 
-	// 22
+	// exit code from the bus
+	Halt: () => [22],
 
 	Pause: () => [23],
 
@@ -78,16 +79,20 @@ export const _DslOpcodes = {
 
 	ShiftRight: () => [33],
 
+	EnableInterrupts: () => [34],
+
+	DisableInterrupts: () => [35],
+
+	ReturnFromInterrupt: () => [36],
+
+	SetInterruptVectorTable: (address: i) => [37, address],
+
 	// Syscalls
 
 	Alert: () => [
 		21, 
 		SyscallsEnum.Alert, 
 	],
-
-	// ---------------
-
-	Halt: () => [100],
 };
 
 // export const DslOpcodeComments: {[p in keyof typeof _DslOpcodes]: string} = {
@@ -26,18 +26,43 @@ export function PauseProgram() {
 
 }
 
+/**
+ * Runs the program until it stops, a program that presents a frame stops
+ * in WaitingForFrame until the host calls NextFrame.
+ */
 export function ResumeProgram() {
-	
+	RefreshBuffers();
+	Continue();
+	UpdateStatusCacheWithAuthoritative();
+	TriggerStatusEvent();
 }
 
 export function StepOverProgram() {
 	RefreshBuffers();
 	StepOver();
 	UpdateStatusCacheWithAuthoritative();
+	TriggerStatusEvent();
+}
+
+// Meant for private use only
+
+function TriggerStatusEvent() {
 	switch (status) {
 		case ProcessorStatus.Halted:
 			Trigger(Events.HALT);
 			break;
+		case ProcessorStatus.Faulted:
+			Trigger(Events.FAULT);
+			break;
+		case ProcessorStatus.WaitingForFrame:
+			Trigger(Events.FRAME);
+			break;
+		case ProcessorStatus.WaitingForInput:
+			Trigger(Events.INPUT);
+			break;
+		case ProcessorStatus.Sleeping:
+			Trigger(Events.SLEEP);
+			break;
 		case ProcessorStatus.Paused:
 			Trigger(Events.PAUSE);
 			break;
@@ -50,8 +75,6 @@ export function StepOverProgram() {
 	}
 }
 
-// Meant for private use only
-
 function UpdateStatusCacheWithAuthoritative() {
 	status = (() => {
 		switch (GetWasmExports().r_GetProcessorStatus()) {
@@ -65,6 +88,14 @@ function UpdateStatusCacheWithAuthoritative() {
 				return ProcessorStatus.Running;
 			case 4:
 				return ProcessorStatus.Empty;
+			case 5:
+				return ProcessorStatus.Faulted;
+			case 6:
+				return ProcessorStatus.WaitingForFrame;
+			case 7:
+				return ProcessorStatus.WaitingForInput;
+			case 8:
+				return ProcessorStatus.Sleeping;
 			default:
 				return ProcessorStatus.Unknown;
 		}
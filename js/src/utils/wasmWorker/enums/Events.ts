@@ -19,6 +19,26 @@ export enum Events {
      */
     HALT = "HALT",
 
+    /**
+     * When the program stops on a memory protection fault.
+     */
+    FAULT = "FAULT",
+
+    /**
+     * When the program presents a frame and waits for the next one.
+     */
+    FRAME = "FRAME",
+
+    /**
+     * When the program waits for terminal input.
+     */
+    INPUT = "INPUT",
+
+    /**
+     * When the program goes to sleep.
+     */
+    SLEEP = "SLEEP",
+
     /**
      * When the program continues execution from a paused state.
      */
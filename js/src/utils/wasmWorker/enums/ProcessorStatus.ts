@@ -25,6 +25,26 @@ export enum ProcessorStatus {
      */
     Empty,
 
+    /**
+     * The processor stopped on a memory protection fault and cannot be restarted.
+     */
+    Faulted,
+
+    /**
+     * The program presented a frame and waits for the host to start the next one.
+     */
+    WaitingForFrame,
+
+    /**
+     * The program is reading a line from the terminal and waits for the host to send one.
+     */
+    WaitingForInput,
+
+    /**
+     * The program is sleeping until its clock reached the wake up time.
+     */
+    Sleeping,
+
     /**
      * Status was not recognized
      */
@@ -1,6 +1,6 @@
 import { GetWasmExports } from './webAssembly';
 import { dsl2machine } from '../language/compilers';
-import { GetSyscallWithNumber, SyscallResult } from './syscalls';
+import { GetSyscallWithNumber, SyscallResult, RustBuffer } from './syscalls';
 
 let MEM_SIZE: number = -1;
 
@@ -204,13 +204,17 @@ export function syscall(code: number, param: number): SyscallResult {
 
 /**
  * Initializes the rust processor with the given text
- * @param text the program text
+ * @param text the program text, a numeric listing whose #[protect] lines set
+ * the permissions of the image
  */
 export function Initialize(text: string) {
 	let exports = GetWasmExports();
 	exports.r_Initialize();
 	UpdateMemoryBlockSize();
 	GetBlock(0).set(dsl2machine(text).slice(0, MEM_SIZE), 1);
+	const encoded = new TextEncoder().encode(text);
+	WriteTransferBuffer(encoded);
+	exports.r_ProtectListing(encoded.length);
 }
 
 /**
@@ -229,6 +233,277 @@ export function StepOver() {
 	GetWasmExports().r_StepOver();
 }
 
+/**
+ * Lets a program that presented a frame run again, returns false if the
+ * program wasn't waiting for a frame.
+ * Call once per displayed frame, the program can then be continued.
+ */
+export function NextFrame(): boolean {
+	return GetWasmExports().r_NextFrame();
+}
+
+/**
+ * Queues a key press or release for the program, returns false if the
+ * queue was full and the event was dropped.
+ * @param code KeyboardEvent.keyCode
+ */
+export function PushKeyEvent(code: number, down: boolean): boolean {
+	return GetWasmExports().r_PushKeyEvent(code, down);
+}
+
+/**
+ * Sends text to the program's terminal input, returns false if some of it
+ * didn't fit or the input was closed.
+ * A program waiting in ReadLine is paused again once a whole line arrived.
+ */
+export function PushTerminalInput(text: string): boolean {
+	const exports = GetWasmExports();
+	let pushed = true;
+	for (const byte of new TextEncoder().encode(text)) {
+		pushed = exports.r_PushTerminalInput(byte) && pushed;
+	}
+	return pushed;
+}
+
+/**
+ * No more input will come, ReadLine reports the end of the input once the
+ * rest was read.
+ */
+export function CloseTerminalInput() {
+	GetWasmExports().r_CloseTerminalInput();
+}
+
+/**
+ * Returns everything the program wrote to the terminal since the last call.
+ */
+export function TakeTerminalOutput(): string {
+	const exports = GetWasmExports();
+	const bytes = new Uint8Array(
+		exports.memory.buffer,
+		exports.r_GetTerminalOutputPointer(),
+		exports.r_GetTerminalOutputLength()
+	);
+	const text = new TextDecoder('utf8').decode(bytes);
+	exports.r_ClearTerminalOutput();
+	return text;
+}
+
+/**
+ * Makes the next count samples of the program's audio at the sample rate,
+ * ready to be copied into an AudioBuffer channel.
+ */
+export function SynthesizeAudio(count: number, sampleRate: number): Float32Array {
+	const exports = GetWasmExports();
+	if (exports.r_GetAudioSampleRate() !== sampleRate) {
+		exports.r_SetAudioSampleRate(sampleRate);
+	}
+	const made = exports.r_SynthesizeAudio(count);
+	const samples = new Int16Array(exports.memory.buffer, exports.r_GetAudioPointer(), made);
+	return Float32Array.from(samples, s => s / 32768);
+}
+
+/**
+ * Copies bytes into the transfer buffer rust uses for files.
+ */
+function WriteTransferBuffer(bytes: Uint8Array) {
+	const exports = GetWasmExports();
+	const pointer = exports.r_GetTransferBuffer(bytes.length);
+	new Uint8Array(exports.memory.buffer, pointer, bytes.length).set(bytes);
+}
+
+function ReadTransferBuffer(length: number): Uint8Array {
+	const exports = GetWasmExports();
+	const pointer = exports.r_GetTransferBuffer(length);
+	return new Uint8Array(exports.memory.buffer, pointer, length).slice();
+}
+
+/**
+ * Puts a file in the program's filesystem, replacing a file with the same name.
+ * Returns false if the name or the file is too long.
+ */
+export function PreloadFile(name: string, contents: Uint8Array): boolean {
+	const encodedName = new TextEncoder().encode(name);
+	const bytes = new Uint8Array(encodedName.length + contents.length);
+	bytes.set(encodedName);
+	bytes.set(contents, encodedName.length);
+	WriteTransferBuffer(bytes);
+	return GetWasmExports().r_PreloadFile(encodedName.length);
+}
+
+/**
+ * Returns a copy of a file from the program's filesystem, or null if there is no such file.
+ */
+export function ExtractFile(name: string): Uint8Array | null {
+	const encodedName = new TextEncoder().encode(name);
+	WriteTransferBuffer(encodedName);
+	const length = GetWasmExports().r_ExtractFile(encodedName.length);
+	if (length < 0) {
+		return null;
+	}
+	return ReadTransferBuffer(length);
+}
+
+export function DeleteFile(name: string): boolean {
+	const encodedName = new TextEncoder().encode(name);
+	WriteTransferBuffer(encodedName);
+	return GetWasmExports().r_DeleteFile(encodedName.length);
+}
+
+/**
+ * Names of all the files in the program's filesystem, in order.
+ */
+export function ListFiles(): string[] {
+	const exports = GetWasmExports();
+	const names: string[] = [];
+	for (let i = 0; i < exports.r_GetFileCount(); i ++) {
+		const length = exports.r_GetFileNameAt(i);
+		names.push(new TextDecoder('utf8').decode(ReadTransferBuffer(length)));
+	}
+	return names;
+}
+
+/**
+ * Assembles DSL assembly with the rust assembler. The text is the numeric
+ * listing if it assembled, otherwise the errors, one "line:column: message" per line.
+ * Files named in .include are read from the program's filesystem, see PreloadFile.
+ */
+export function Assemble(source: string): { assembled: boolean, text: string } {
+	const exports = GetWasmExports();
+	const encoded = new TextEncoder().encode(source);
+	WriteTransferBuffer(encoded);
+	const assembled = exports.r_Assemble(encoded.length);
+	const text = new TextDecoder('utf8').decode(ReadTransferBuffer(exports.r_GetTransferLength()));
+	return { assembled, text };
+}
+
+/**
+ * Assembles DSL assembly into an object file for Link, which can be put in the
+ * program's filesystem with PreloadFile. If it doesn't assemble, errors has the
+ * errors, one per line.
+ */
+export function AssembleObject(source: string): { object: Uint8Array | null, errors: string } {
+	const exports = GetWasmExports();
+	const encoded = new TextEncoder().encode(source);
+	WriteTransferBuffer(encoded);
+	const assembled = exports.r_AssembleObject(encoded.length);
+	const bytes = ReadTransferBuffer(exports.r_GetTransferLength());
+	if (!assembled) {
+		return { object: null, errors: new TextDecoder('utf8').decode(bytes) };
+	}
+	return { object: bytes, errors: '' };
+}
+
+/**
+ * Links files from the program's filesystem into one program, the first is the
+ * program. Files ending in .dsla are assembled, the others are object files.
+ * The text is the numeric listing if it linked, otherwise the errors, one per line.
+ */
+export function Link(names: string[]): { linked: boolean, text: string } {
+	const exports = GetWasmExports();
+	const encoded = new TextEncoder().encode(names.join('\n'));
+	WriteTransferBuffer(encoded);
+	const linked = exports.r_Link(encoded.length);
+	const text = new TextDecoder('utf8').decode(ReadTransferBuffer(exports.r_GetTransferLength()));
+	return { linked, text };
+}
+
+/**
+ * Puts the standard library in the program's filesystem: std.dslo to give Link
+ * after the program, and std-header.dsla with the files it includes, for
+ * programs to include.
+ */
+export function PreloadStdlib(): boolean {
+	return GetWasmExports().r_PreloadStdlib();
+}
+
+export enum ClockMode {
+	/**
+	 * the host moves the clock on with AdvanceClock
+	 */
+	Host = 0,
+	/**
+	 * every so many instructions make a millisecond, the same on every run
+	 */
+	Instructions = 1,
+}
+
+export function SetClockMode(mode: ClockMode, instructionsPerTick: number = 1000): boolean {
+	return GetWasmExports().r_SetClockMode(mode, instructionsPerTick);
+}
+
+/**
+ * Moves the program's clock on, a sleeping program is paused again once its
+ * time is up and can then be continued.
+ */
+export function AdvanceClock(milliseconds: number) {
+	GetWasmExports().r_AdvanceClock(Math.floor(milliseconds));
+}
+
+/**
+ * Milliseconds until a sleeping program wakes up, or null if it isn't sleeping.
+ */
+export function GetSleepRemaining(): number | null {
+	const remaining = GetWasmExports().r_GetSleepRemaining();
+	return remaining < 0 ? null : remaining;
+}
+
+export enum PointerEventKind {
+	Move = 1,
+	Down = 2,
+	Up = 3,
+	Wheel = 4,
+}
+
+/**
+ * Queues a pointer event for the program, returns false if it was dropped.
+ * The position is scaled from the displayed size of the screen to the
+ * screen buffer in rust.
+ * @param data the button for Down and Up, the wheel delta for Wheel
+ */
+export function PushPointerEvent(kind: PointerEventKind, x: number, y: number, data: number, displayWidth: number, displayHeight: number): boolean {
+	return GetWasmExports().r_PushPointerEvent(kind, Math.floor(x), Math.floor(y), data, displayWidth, displayHeight);
+}
+
+/**
+ * Interrupt lines raised by the devices in rust, the program can use the
+ * other lines up to 31 as it likes.
+ */
+export enum InterruptLine {
+	Timer = 0,
+	Keyboard = 1,
+	Frame = 2,
+	Pointer = 3,
+	Terminal = 4,
+}
+
+/**
+ * Raises an interrupt line, the program's handler for it runs once it has
+ * interrupts enabled. Returns false for an unknown line.
+ */
+export function RaiseInterrupt(line: InterruptLine | number): boolean {
+	return GetWasmExports().r_RaiseInterrupt(line);
+}
+
+/**
+ * Seeds the program's random numbers, the same seed gives the same run.
+ */
+export function SetRandomSeed(seed: number) {
+	GetWasmExports().r_SetRandomSeed(seed);
+}
+
+/**
+ * The random generator's state as [low, high] words, to restore it later
+ * with SetRandomState.
+ */
+export function GetRandomState(): [number, number] {
+	const exports = GetWasmExports();
+	return [exports.r_GetRandomState(0) >>> 0, exports.r_GetRandomState(1) >>> 0];
+}
+
+export function SetRandomState(state: [number, number]) {
+	GetWasmExports().r_SetRandomState(state[0] | 0, state[1] | 0);
+}
+
 /**
  * Returns the current instruction pointer of the rust processor.
  */
@@ -258,6 +533,150 @@ export function GetIsBreakpoint(b: number) {
 	return GetWasmExports().r_GetIsBreakpoint(b);
 }
 
+/**
+ * Loads a debug section, from ExportDebugInfo or dsl-asm --debug-info, that
+ * maps the addresses of the program to its source. Assemble and Link load the
+ * debug info of what they make by themselves.
+ */
+export function LoadDebugInfo(bytes: Uint8Array): boolean {
+	WriteTransferBuffer(bytes);
+	return GetWasmExports().r_LoadDebugInfo(bytes.length);
+}
+
+/**
+ * The debug section of the program, to save with its listing.
+ */
+export function ExportDebugInfo(): Uint8Array {
+	const length = GetWasmExports().r_ExportDebugInfo();
+	return ReadTransferBuffer(length);
+}
+
+export function ClearDebugInfo() {
+	GetWasmExports().r_ClearDebugInfo();
+}
+
+/**
+ * The source files of the program, SourceLocation.file is an index into them.
+ */
+export function GetDebugFiles(): string[] {
+	const exports = GetWasmExports();
+	const files: string[] = [];
+	const count = exports.r_GetDebugFileCount();
+	for (let i = 0; i < count; i++) {
+		const length = exports.r_GetDebugFileName(i);
+		files.push(new TextDecoder('utf8').decode(ReadTransferBuffer(length)));
+	}
+	return files;
+}
+
+export interface SourceLocation {
+	file: number,
+	line: number,
+	column: number,
+	length: number,
+	/** the first address made from the same source */
+	start: number,
+}
+
+/**
+ * Where the word at the address was assembled from, or null if it has no
+ * source, like the instruction pointer of a stopped program.
+ */
+export function GetSourceLocation(address: number): SourceLocation | null {
+	const exports = GetWasmExports();
+	if (exports.r_GetSourceLocation(address, 1) < 0) {
+		return null;
+	}
+	return {
+		file: exports.r_GetSourceLocation(address, 0),
+		line: exports.r_GetSourceLocation(address, 1),
+		column: exports.r_GetSourceLocation(address, 2),
+		length: exports.r_GetSourceLocation(address, 3),
+		start: exports.r_GetSourceLocation(address, 4),
+	};
+}
+
+/**
+ * The first address of code from the line of the file, or -1.
+ */
+export function GetLineAddress(file: number, line: number): number {
+	return GetWasmExports().r_GetLineAddress(file, line);
+}
+
+export enum SymbolKind {
+	Label = 0,
+	Unit = 1,
+	Array = 2,
+}
+
+export interface DebugSymbol {
+	name: string,
+	kind: SymbolKind,
+	address: number,
+	file: number,
+	line: number,
+	column: number,
+}
+
+function GetSymbol(index: number): DebugSymbol | null {
+	const exports = GetWasmExports();
+	const length = exports.r_GetSymbolName(index);
+	if (length < 0) {
+		return null;
+	}
+	return {
+		name: new TextDecoder('utf8').decode(ReadTransferBuffer(length)),
+		address: exports.r_GetSymbolInfo(index, 0),
+		kind: exports.r_GetSymbolInfo(index, 1) as SymbolKind,
+		file: exports.r_GetSymbolInfo(index, 2),
+		line: exports.r_GetSymbolInfo(index, 3),
+		column: exports.r_GetSymbolInfo(index, 4),
+	};
+}
+
+/**
+ * The labels and variables of the program.
+ */
+export function GetSymbols(): DebugSymbol[] {
+	const symbols: DebugSymbol[] = [];
+	const count = GetWasmExports().r_GetSymbolCount();
+	for (let i = 0; i < count; i++) {
+		symbols.push(GetSymbol(i) as DebugSymbol);
+	}
+	return symbols;
+}
+
+export function FindSymbol(name: string): DebugSymbol | null {
+	const encoded = new TextEncoder().encode(name);
+	WriteTransferBuffer(encoded);
+	return GetSymbol(GetWasmExports().r_FindSymbol(encoded.length));
+}
+
+/**
+ * The label the address is in, the closest one at or before it.
+ */
+export function GetLabelAt(address: number): DebugSymbol | null {
+	return GetSymbol(GetWasmExports().r_GetLabelAt(address));
+}
+
+/**
+ * Sets a breakpoint on the first code from the line of the file. Returns its
+ * address, or -1 if there is no code there.
+ */
+export function SetBreakpointAtLine(file: number, line: number): number {
+	return GetWasmExports().r_SetBreakpointAtLine(file, line);
+}
+
+/**
+ * Sets a breakpoint on a label. Returns its address, or -1 if there is no
+ * such label.
+ */
+export function SetBreakpointAtSymbol(name: string): number {
+	const encoded = new TextEncoder().encode(name);
+	WriteTransferBuffer(encoded);
+	return GetWasmExports().r_SetBreakpointAtSymbol(encoded.length);
+}
+
 /**
  * Gets the memory block size from rust
  */
@@ -273,6 +692,147 @@ export function GetWasmMemoryLocation(location: number): number {
 	return GetWasmExports().r_GetWasmMemoryLocation(location);
 }
 
+/**
+ * Returns the reason the most recent syscall handled in rust failed
+ */
+export function GetLastSyscallError(): number {
+	return GetWasmExports().r_GetLastSyscallError();
+}
+
+/**
+ * Returns every initialized buffer in the rust buffer registry
+ */
+export function GetRustBuffers(): RustBuffer[] {
+	const exports = GetWasmExports();
+	const buffers: RustBuffer[] = [];
+	const count = exports.r_GetBufferCount();
+	for (let i = 0; i < count; i++) {
+		const id = exports.r_GetBufferIdAt(i);
+		if (exports.r_IsBufferInitialized(id)) {
+			buffers.push({
+				id,
+				head: exports.r_GetBufferHead(id),
+				length: exports.r_GetBufferLength(id),
+				type: exports.r_GetBufferType(id),
+			});
+		}
+	}
+	return buffers;
+}
+
+export interface Frame {
+	width: number;
+	height: number;
+	/**
+	 * RGBA8 pixels, ready to be put in an ImageData
+	 */
+	pixels: ArrayBuffer;
+}
+
+/**
+ * Renders the screen buffer through the palette in rust and returns a copy
+ * of the pixels, or null if nothing changed since the last frame
+ */
+export function RenderFramebuffer(): Frame | null {
+	const exports = GetWasmExports();
+	if (!exports.r_RenderFramebuffer()) {
+		return null;
+	}
+	const width = exports.r_GetFramebufferWidth();
+	const height = exports.r_GetFramebufferHeight();
+	const pixels = new Uint8Array(
+		exports.memory.buffer,
+		exports.r_GetFramebufferPointer(),
+		width * height * 4
+	).slice().buffer;
+	return {
+		width,
+		height,
+		pixels,
+	};
+}
+
+export enum MemoryPermission {
+	None = 0,
+	Read = 1,
+	Write = 2,
+	Execute = 4,
+	All = 7,
+}
+
+/**
+ * Restricts what instructions may do to a range of memory.
+ * Later ranges take priority where they overlap. Like the #[protect] lines of
+ * a listing, the program's Protect syscall can't raise these permissions.
+ * @param start first address of the range
+ * @param length number of words in the range
+ * @param permissions mask of MemoryPermission values
+ */
+export function SetMemoryProtection(start: number, length: number, permissions: MemoryPermission) {
+	GetWasmExports().r_SetMemoryProtection(start, length, permissions);
+}
+
+export enum FaultKind {
+	None = 0,
+	Read = 1,
+	Write = 2,
+	Execute = 3,
+	Unmapped = 4,
+	InvalidReturn = 5,
+}
+
+/**
+ * Describes why the processor faulted
+ */
+export function GetFault() {
+	const exports = GetWasmExports();
+	return {
+		kind: exports.r_GetFaultKind() as FaultKind,
+		address: exports.r_GetFaultAddress(),
+		instruction: exports.r_GetFaultInstruction(),
+	};
+}
+
+export enum ExitReason {
+	/** still running, or never ran */
+	None = 0,
+	/** the Halt opcode, the code is the bus */
+	Halt = 1,
+	/** an opcode that doesn't exist, the code is the opcode */
+	InvalidOpcode = 2,
+	/** the code is the FaultKind, see GetFault */
+	Fault = 3,
+	/** the step budget ran out, the code is the number of steps */
+	Budget = 4,
+}
+
+/**
+ * Describes why and where the program stopped for good.
+ */
+export function GetExitInfo() {
+	const exports = GetWasmExports();
+	return {
+		reason: exports.r_GetExitInfo(0) as ExitReason,
+		code: exports.r_GetExitInfo(1),
+		address: exports.r_GetExitInfo(2),
+	};
+}
+
+/**
+ * The program halts with ExitReason.Budget once it ran this many
+ * instructions, 0 for no limit.
+ */
+export function SetStepBudget(steps: number) {
+	GetWasmExports().r_SetStepBudget(steps);
+}
+
+/**
+ * Number of instructions the program ran so far.
+ */
+export function GetStepCount(): number {
+	return GetWasmExports().r_GetStepCount() >>> 0;
+}
+
 // InitializeWindowBarrel('rustUtils', {
 // 	GetWasmMemoryLocation,
 // 	setMemoryLocation,
@@ -1,6 +1,6 @@
 import { Maybe, SMap, Either } from '../utilTypes';
 import * as _ from 'lodash';
-import { GetMemoryBuffer, GetWasmMemoryLocation } from './rustUtils';
+import { GetMemoryBuffer, GetWasmMemoryLocation, GetRustBuffers } from './rustUtils';
 import { contains } from '../generalUtils';
 import { SyscallsEnum } from '../SyscallsEnum';
 
@@ -11,22 +11,79 @@ import { SyscallsEnum } from '../SyscallsEnum';
 
 
 // syscalls:
-// 1 - Create a new buffer
-// 2 - initialize newest buffer start (param address)
-// 3 - initialize newest buffer length (param length)
-// 6 - initialize newest buffer type:
+// The buffer registry lives in rust, these only describe the interface.
+// 1 - Create a new buffer, the id of the buffer -> bus [rust]
+// 2 - initialize buffer start (param pointer to [id, address]) [rust]
+// 3 - initialize buffer length (param pointer to [id, length]) [rust]
+//      The range must be inside memory that exists.
+// 4 - initialize buffer type (param pointer to [id, type]) [rust]
 //      See below for types and uses of buffer types.
-//      All buffers are immutable and the JS will ignore attempts to change buffer
-//      properties.
-// 7 - clear buffer with ID from bus (JS drops buffer)
-// 9 - ready file with filename pointer (param address) 
-//      [follow with syscall 10]
-// 10 - load file contents into buffer with ID from bus (must be an input buffer)
-// 11 - submit a request to sleep (param ms time) [should follow up with pause]
+//      All buffers are immutable, setting a property twice returns an error.
+// 5 - delete buffer with id from bus (JS drops buffer) [rust]
+// Files live in a virtual filesystem in rust, the host preloads and extracts them.
+// Names and contents are one byte per word, names are zero terminated.
+// 9 - open file (param pointer to [name address, flags]), handle -> bus, 0 if it failed [rust]
+//      flags: read = 1, write = 2, create = 4, truncate = 8, append = 16
+// 10 - read (param pointer to [handle, address, count]), bytes read -> bus [rust]
+// 11 - write (param pointer to [handle, address, count]), bytes written -> bus [rust]
+// 12 - seek (param pointer to [handle, offset, from]), position -> bus [rust]
+//      from: start = 0, current = 1, end = 2
+// 13 - close the handle in the bus [rust]
+// 14 - file name at an index (param pointer to [index, address, length]), name length -> bus [rust]
+//      10, 11, 12 and 14 return 0xFFFFFFFF if they fail
+// 20 - sleep for the milliseconds in the bus, the processor waits in the Sleeping status [rust]
+// 21 - clock in milliseconds -> bus [rust]
+// 22 - set a periodic timer (param pointer to [timer, period]), period 0 turns it off [rust]
+// 23 - number of times the timer in the bus fired since the last call -> bus [rust]
+//      The host moves the clock on with r_AdvanceClock, or lets instructions drive it.
+// 24 - random number below the bound in the bus (0 for any 32 bit number) -> bus [rust]
+// 25 - seed the random numbers with the bus [rust]
+// Random: memory mapped at 0xFFFF6000 [rust]
+//      0 - reading takes the next random number, 1 - write to seed, reads the last seed
+// 31 - write the character in the bus to the terminal [rust]
+// 32 - write the zero terminated string at the address in the bus to the terminal [rust]
+// 33 - read a line (param pointer to [address, length]), length -> bus [rust]
+//      Waits for input if there is no whole line yet.
+// Terminal: memory mapped at 0xFFFF4000 [rust]
+//      0 - write a character, 1 - number of input bytes available,
+//      2 - reading takes the next input byte (0 if none), 3 - 1 once the input ended
+// 70 - queue PCM samples (param pointer to [address, count]), number queued -> bus [rust]
+// Audio: memory mapped at 0xFFFF5000 [rust]
+//      0 to 3 square, 4 to 7 triangle, 8 to 11 noise channel, each [frequency in Hz, volume 0 - 255, duty 0 - 255]
+//      12 - write a signed 16 bit PCM sample, 13 - free space in the PCM ring buffer (8192),
+//      14 - PCM volume 0 - 255
+// 40 - allocate memory (param word count), returns address or 0 [rust]
+// 41 - free memory (param address returned by 40) [rust]
+// 42 - set memory permissions (param pointer to [start, length, permissions]) [rust]
+// 50 to 55 - draw shapes and text into the screen buffer (param pointer to arguments, see SyscallsEnum) [rust]
+// 56 - present the frame, the program waits until the host takes it [rust]
+// 60 - next key event -> bus (key code | 1 << 16 for a press), 0 if none [rust]
+// 61 - whether the key code in the bus is held -> bus [rust]
+// Keyboard: memory mapped at 0xFFFF2000, key events are queued as they happen [rust]
+//      0 - number of queued events, 1 - reading takes the next event,
+//      2 - events dropped because the queue (64) was full, write to clear
+// 62 - next pointer event -> [kind, x, y, data] at the pointer in the bus, 1 if there was one [rust]
+// 63 - pointer state -> [x, y, buttons] at the pointer in the bus [rust]
+// Pointer: memory mapped at 0xFFFF3000, positions are in screen buffer pixels [rust]
+//      0 and 1 - x and y (signed), 2 - held buttons mask, 3 - wheel sum (write to clear),
+//      4 - number of queued events
+// Text mode: a 64x32 grid of character cells is memory mapped at 0xFFFF0000 [rust]
+//      0 - enabled (0 or 1), 1 and 2 - columns and rows that fit on the screen (read only)
+//      16 onwards - cells row by row, each is char | fg << 8 | bg << 16 | (1 << 24 to draw bg)
+//      The cells are drawn over the screen buffer.
+// Tiles and sprites: memory mapped at 0xFFFF1000, composited into the screen buffer every frame [rust]
+//      0 - enabled, 1 - tileset address, 2 - tile count, 3 - map address,
+//      4 and 5 - map width and height in tiles, 6 and 7 - scroll x and y (signed),
+//      8 - transparent color, 9 - backdrop color
+//      16 onwards - 64 sprites of [x, y, tile, flags],
+//      flags are visible = 1, flip x = 2, flip y = 4, behind the map = 8
+//      Tiles are 8x8 color indices in VM memory, the map wraps around.
 
 // Buffer types
 // All input buffer types update between frames
 /* 1 - [INPUT] Key input
+    Only reports keys held when the buffers are refreshed, prefer the
+    keyboard syscalls (60, 61) which don't miss quick taps.
     In each position, put the key code of the key to listen for.
     Do this BEFORE initalizing the buffer, as the JS will overrwrite values
     as soon as it is able.
@@ -35,6 +92,7 @@ import { SyscallsEnum } from '../SyscallsEnum';
         1 if the key is pressed
 */
 /* 2 - [INPUT] Terminal input
+    Kept for old programs, new ones should use the terminal syscalls (31 to 33).
     The buffer will be filled with the most recent text that
     was not consumed. The JS will follow this logic for filling
     this buffer:
@@ -92,11 +150,8 @@ import { SyscallsEnum } from '../SyscallsEnum';
 //#region Interfaces
 // -----------------------------------------------------------------------
 
-const buffers: SMap<Buffer> = {};
-
-let bufferCreateId = 0;
-
-const _zeroBuffer = new Uint32Array();
+// local copies of buffer contents, by buffer id
+const contents: SMap<Uint32Array> = {};
 
 export type SyscallFunction = (parameter: number) => SyscallResult;
 
@@ -114,16 +169,15 @@ export enum SyscallResult {
 	ERROR = 1,
 }
 
-interface Buffer {
+export interface RustBuffer {
 	length: number;
 	head: number;
 	id: number;
 	type: BufferType;
-	contents: Uint32Array;
+}
 
-	lengthInitialized: boolean;
-	headInitialized: boolean;
-	typeInitialized: boolean;
+interface Buffer extends RustBuffer {
+	contents: Uint32Array;
 }
 
 // -----------------------------------------------------------------------
@@ -131,12 +185,28 @@ interface Buffer {
 //#region Helper function
 // -----------------------------------------------------------------------
 
+/**
+ * All initialized buffers in the rust registry, with their local contents
+ */
+function GetBuffers(): Buffer[] {
+	return GetRustBuffers().map(buffer => {
+		let local = contents[buffer.id];
+		if (!local || local.length !== buffer.length) {
+			local = contents[buffer.id] = new Uint32Array(buffer.length);
+		}
+		return {
+			...buffer,
+			contents: local,
+		};
+	});
+}
+
 export function GetBufferOfType(type: BufferType) {
-	return Maybe(_.values(buffers).filter(b => b.type === type)[0]);
+	return Maybe(GetBuffers().filter(b => b.type === type)[0]);
 }
 
 export function GetBuffersOfType(type: BufferType) {
-	return _.values(buffers).filter(b => b.type === type);
+	return GetBuffers().filter(b => b.type === type);
 }
 
 function IsFromWasm(buffer: Buffer) {
@@ -160,77 +230,11 @@ function IsToWasm(buffer: Buffer) {
 	);
 }
 
-function IsBufferInitialized(buffer: Buffer) {
-	return buffer.headInitialized && buffer.lengthInitialized && buffer.typeInitialized;
-}
-
 // -----------------------------------------------------------------------
 //#endregion
 //#region Syscall functions
 // -----------------------------------------------------------------------
 
-function CreateBuffer(): SyscallResult {
-	bufferCreateId += 1;
-	buffers[bufferCreateId] = {
-		length: 0,
-		head: 0,
-		id: bufferCreateId,
-		type: BufferType.NONE,
-		contents: _zeroBuffer,
-		headInitialized: false,
-		lengthInitialized: false,
-		typeInitialized: false,
-	};
-	return SyscallResult.OK;
-}
-
-function SetBufferHead(head: number): SyscallResult {
-	return Maybe(buffers[bufferCreateId])
-		.filter(buffer => !buffer.headInitialized)
-		.match({
-			Just(buffer) {
-				buffer.head = head;
-				buffer.headInitialized = true;
-			},
-		})
-		.map(b => SyscallResult.OK)
-		.else(() => SyscallResult.ERROR)
-		.unwrap();
-}
-
-function SetBufferLength(length: number): SyscallResult {
-	return Maybe(buffers[bufferCreateId])
-		.filter(buffer => !buffer.lengthInitialized)
-		.match({
-			Just(buffer) {
-				buffer.length = length;
-				buffer.lengthInitialized = true;
-				buffer.contents = new Uint32Array(length);
-			},
-		})
-		.map(b => SyscallResult.OK)
-		.else(() => SyscallResult.ERROR)
-		.unwrap();
-}
-
-function SetBufferType(type: BufferType): SyscallResult {
-	return Maybe(buffers[bufferCreateId])
-		.filter(buffer => !buffer.typeInitialized)
-		.match({
-			Just(buffer) {
-				buffer.type = type;
-				buffer.typeInitialized = true;
-			},
-		})
-		.map(b => SyscallResult.OK)
-		.else(() => SyscallResult.ERROR)
-		.unwrap();
-}
-
-function Sleep(): SyscallResult {
-	return SyscallResult.ERROR;
-}
-
 function Alert(pointer: number) {
 	const buffer: number[] = [];
 	let data = GetWasmMemoryLocation(pointer);
@@ -249,11 +253,6 @@ function Alert(pointer: number) {
 // -----------------------------------------------------------------------
 
 const _allSyscalls: SMap<SyscallFunction> = {
-	CreateBuffer,
-	SetBufferHead,
-	SetBufferLength,
-	SetBufferType,
-	Sleep,
 	Alert,
 };
 
@@ -278,16 +277,14 @@ export function GetSyscallWithNumber(n: SyscallsEnum): SyscallFunction {
  * All input buffers will refresh their contents
  */
 function WriteAllBuffersToWasm() {
-	_.values(buffers)
+	GetBuffers()
 		.filter(IsToWasm)
-		.filter(IsBufferInitialized)
 		.map(_writeIntoWasm);
 }
 
 function ReadAllBuffersFromWasm() {
-	_.values(buffers)
+	GetBuffers()
 		.filter(IsFromWasm)
-		.filter(IsBufferInitialized)
 		.forEach(_readIntoLocal);
 }
 
@@ -302,9 +299,8 @@ function ReadAllBuffersFromWasm() {
  * @param text text to put into buffer
  */
 export function WriteToBuffer(bufferId: number, text: Int32Array) {
-	Maybe(buffers[bufferId])
+	Maybe(GetBuffers().filter(b => b.id === bufferId)[0])
 		.filter(IsToWasm)
-		.filter(IsBufferInitialized)
 		.map(buffer => buffer.contents.set(text.slice(0, buffer.length)));
 }
 
@@ -313,9 +309,8 @@ export function WriteToBuffer(bufferId: number, text: Int32Array) {
  * @param id buffer id
  */
 export function ReadFromBuffer(bufferId: number): Maybe<Uint32Array> {
-	return Maybe(buffers[bufferId])
+	return Maybe(GetBuffers().filter(b => b.id === bufferId)[0])
 		.filter(IsFromWasm)
-		.filter(IsBufferInitialized)
 		.map(buffer => buffer.contents);
 }
 
@@ -13,6 +13,74 @@ export interface WasmExports {
 	r_DisableBreakpoints: () => void;
 	r_GetMemoryBlockSize: () => number;
 	r_GetWasmMemoryLocation: (location: number) => number;
+	r_GetLastSyscallError: () => number;
+	r_GetBufferCount: () => number;
+	r_GetBufferIdAt: (index: number) => number;
+	r_FindBufferOfType: (type: number) => number;
+	r_IsBufferInitialized: (id: number) => boolean;
+	r_GetBufferHead: (id: number) => number;
+	r_GetBufferLength: (id: number) => number;
+	r_GetBufferType: (id: number) => number;
+	r_RenderFramebuffer: () => boolean;
+	r_IsFramebufferDirty: () => boolean;
+	r_GetFramebufferPointer: () => number;
+	r_GetFramebufferWidth: () => number;
+	r_GetFramebufferHeight: () => number;
+	r_NextFrame: () => boolean;
+	r_IsDoubleBuffered: () => boolean;
+	r_PushKeyEvent: (code: number, down: boolean) => boolean;
+	r_PushTerminalInput: (byte: number) => boolean;
+	r_CloseTerminalInput: () => void;
+	r_GetTerminalOutputPointer: () => number;
+	r_GetTerminalOutputLength: () => number;
+	r_ClearTerminalOutput: () => void;
+	r_SynthesizeAudio: (count: number) => number;
+	r_GetAudioPointer: () => number;
+	r_GetAudioSampleRate: () => number;
+	r_SetAudioSampleRate: (rate: number) => void;
+	r_GetTransferBuffer: (size: number) => number;
+	r_GetTransferLength: () => number;
+	r_PreloadFile: (nameLength: number) => boolean;
+	r_ExtractFile: (nameLength: number) => number;
+	r_DeleteFile: (nameLength: number) => boolean;
+	r_GetFileCount: () => number;
+	r_GetFileNameAt: (index: number) => number;
+	r_Assemble: (sourceLength: number) => boolean;
+	r_AssembleObject: (sourceLength: number) => boolean;
+	r_Link: (namesLength: number) => boolean;
+	r_PreloadStdlib: () => boolean;
+	r_LoadDebugInfo: (length: number) => boolean;
+	r_ExportDebugInfo: () => number;
+	r_ClearDebugInfo: () => void;
+	r_GetDebugFileCount: () => number;
+	r_GetDebugFileName: (index: number) => number;
+	r_GetSourceLocation: (address: number, field: number) => number;
+	r_GetLineAddress: (file: number, line: number) => number;
+	r_GetSymbolCount: () => number;
+	r_FindSymbol: (nameLength: number) => number;
+	r_GetLabelAt: (address: number) => number;
+	r_GetSymbolName: (index: number) => number;
+	r_GetSymbolInfo: (index: number, field: number) => number;
+	r_SetBreakpointAtLine: (file: number, line: number) => number;
+	r_SetBreakpointAtSymbol: (nameLength: number) => number;
+	r_AdvanceClock: (milliseconds: number) => void;
+	r_GetClockTicks: () => number;
+	r_GetSleepRemaining: () => number;
+	r_SetClockMode: (mode: number, instructionsPerTick: number) => boolean;
+	r_RaiseInterrupt: (line: number) => boolean;
+	r_SetRandomSeed: (seed: number) => void;
+	r_GetRandomState: (half: number) => number;
+	r_SetRandomState: (low: number, high: number) => void;
+	r_PushPointerEvent: (kind: number, x: number, y: number, data: number, displayWidth: number, displayHeight: number) => boolean;
+	r_SetMemoryProtection: (start: number, length: number, permissions: number) => void;
+	r_ProtectListing: (length: number) => boolean;
+	r_ClearMemoryProtection: () => void;
+	r_GetFaultKind: () => number;
+	r_GetFaultAddress: () => number;
+	r_GetFaultInstruction: () => number;
+	r_GetExitInfo: (field: number) => number;
+	r_SetStepBudget: (steps: number) => void;
+	r_GetStepCount: () => number;
 	memory: WebAssembly.Memory;
 }
 
@@ -1,10 +1,11 @@
 import { MainToWorker, WorkerToMain } from '../workerCommunication/formats';
 import { discriminantToHandler, stripKeyFromAll, getPropsOf } from '../utilTypes';
 import { loadWasmAsync } from './webAssembly';
-import { Initialize, GetBlock, GetInstructionPointer, SetBreakpoint, GetIsBreakpoint, RemoveBreakpoint } from './rustUtils';
+import { Initialize, GetBlock, GetInstructionPointer, SetBreakpoint, GetIsBreakpoint, RemoveBreakpoint, RenderFramebuffer, NextFrame, PushKeyEvent, PushPointerEvent, PointerEventKind, PushTerminalInput, TakeTerminalOutput, SynthesizeAudio, PreloadFile, ExtractFile, AdvanceClock, Assemble, PreloadStdlib } from './rustUtils';
 import { getWasmImports } from './wasmImports';
 import { GetBuffersOfType } from './syscalls';
-import { StepOverProgram, ResumeProgram } from './controlUtils';
+import { StepOverProgram, ResumeProgram, CheckStatus } from './controlUtils';
+import { ProcessorStatus } from './enums/ProcessorStatus';
 
 
 type wtm = stripKeyFromAll<getPropsOf<WorkerToMain, 'type'>, 'type'>;
@@ -27,17 +28,23 @@ const messageTypes: discriminantToHandler<MainToWorker, 'type', handler> = {
 		}
 	},
 	click(data, respond) {
+		PushPointerEvent(PointerEventKind.Down, data.x, data.y, 0, data.width, data.height);
+		PushPointerEvent(PointerEventKind.Up, data.x, data.y, 0, data.width, data.height);
+	},
+	pointer(data, respond) {
+		PushPointerEvent(data.kind, data.x, data.y, data.data, data.width, data.height);
 	},
 	keydown(data, respond) {
-
+		PushKeyEvent(data.code, true);
 	},
 	keyup(data, respond) {
-		
+		PushKeyEvent(data.code, false);
 	},
 	start(data, respond) {
 		ResumeProgram();
 		respond('stopped', {
 			stoppedOnLine: GetInstructionPointer(),
+			waitingForFrame: CheckStatus([ProcessorStatus.WaitingForFrame]),
 		});
 	},
 	stop(data, respond) {
@@ -48,10 +55,18 @@ const messageTypes: discriminantToHandler<MainToWorker, 'type', handler> = {
 			respond('initialized', {});
 		});
 	},
+	assemble(data, respond) {
+		loadWasmAsync('./wasm/dsl_wasm.wasm', getWasmImports()).then(() => {
+			// so programs can include the standard library
+			PreloadStdlib();
+			respond('assembled', Assemble(data.source));
+		});
+	},
 	step(data, respond) {
 		StepOverProgram();
 		respond('stopped', {
 			stoppedOnLine: GetInstructionPointer(),
+			waitingForFrame: CheckStatus([ProcessorStatus.WaitingForFrame]),
 		});
 	},
 	'request-buffer'(data, respond) {
@@ -65,6 +80,53 @@ const messageTypes: discriminantToHandler<MainToWorker, 'type', handler> = {
 			buffers
 		);
 	},
+	'request-frame'(data, respond) {
+		const frame = RenderFramebuffer();
+		// taking the frame is the vsync, a program waiting on Present can go on
+		NextFrame();
+		respond(
+			'frame',
+			{
+				frame,
+			},
+			frame ? [frame.pixels] : []
+		);
+	},
+	'terminal-input'(data, respond) {
+		PushTerminalInput(data.text);
+	},
+	'request-terminal-output'(data, respond) {
+		respond('terminal-output', {
+			text: TakeTerminalOutput(),
+		});
+	},
+	'request-audio'(data, respond) {
+		const samples = SynthesizeAudio(data.count, data.sampleRate).buffer;
+		respond(
+			'audio',
+			{
+				samples,
+			},
+			[samples]
+		);
+	},
+	'preload-file'(data, respond) {
+		PreloadFile(data.name, new Uint8Array(data.contents));
+	},
+	'extract-file'(data, respond) {
+		const file = ExtractFile(data.name);
+		const contents = file ? file.buffer : null;
+		respond(
+			'file',
+			{
+				contents,
+			},
+			contents ? [contents] : []
+		);
+	},
+	'advance-clock'(data, respond) {
+		AdvanceClock(data.milliseconds);
+	},
 	'get-block'(data, respond) {
 		const block = new Uint32Array(GetBlock(data.blockNum).getCombined()).buffer;
 		respond(
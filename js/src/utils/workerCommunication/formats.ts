@@ -1,4 +1,5 @@
 import { BufferType } from '../wasmWorker/syscalls';
+import { Frame, PointerEventKind } from '../wasmWorker/rustUtils';
 import { DiscriminateUnion, Omit } from '../utilTypes';
 
 export type MainToWorker = {
@@ -8,14 +9,31 @@ export type MainToWorker = {
 	line: number;
 } | {
 	type: 'click';
+	// relative to the canvas, which is width * height large on the page
 	x: number;
 	y: number;
+	width: number;
+	height: number;
+} | {
+	type: 'pointer';
+	kind: PointerEventKind;
+	// relative to the canvas, which is width * height large on the page
+	x: number;
+	y: number;
+	// the button, or the wheel delta
+	data: number;
+	width: number;
+	height: number;
 } | {
 	type: 'keydown';
 	key: string;
+	// KeyboardEvent.keyCode
+	code: number;
 } | {
 	type: 'keyup';
 	key: string;
+	// KeyboardEvent.keyCode
+	code: number;
 } | {
 	// start the machine
 	type: 'start',
@@ -33,9 +51,42 @@ export type MainToWorker = {
 	type: 'request-buffer',
 	bufferType: BufferType,
 	// responds with 'buffer-contents'
+} | {
+	type: 'request-frame',
+	// responds with 'frame'
+} | {
+	// text typed into the terminal, sent to the program as utf8
+	type: 'terminal-input',
+	text: string,
+} | {
+	type: 'request-terminal-output',
+	// responds with 'terminal-output'
+} | {
+	type: 'request-audio',
+	// number of samples at sampleRate
+	count: number,
+	sampleRate: number,
+	// responds with 'audio'
+} | {
+	// puts a file in the program's filesystem
+	type: 'preload-file',
+	name: string,
+	contents: ArrayBuffer,
+} | {
+	type: 'extract-file',
+	name: string,
+	// responds with 'file'
+} | {
+	// real time that passed, moves the program's clock on
+	type: 'advance-clock',
+	milliseconds: number,
 } | {
 	type: 'get-block',
 	blockNum: number,	
+} | {
+	// DSL assembly for the rust assembler, responds with 'assembled'
+	type: 'assemble',
+	source: string,
 };
 
 export type WorkerToMain = {
@@ -43,9 +94,27 @@ export type WorkerToMain = {
 } | {
 	type: 'stopped',
 	stoppedOnLine: number;
+	// the program presented a frame and goes on after the next 'request-frame'
+	waitingForFrame: boolean;
 } | {
 	type: 'buffer-contents',
 	buffers: ArrayBuffer[],
+} | {
+	type: 'frame',
+	// null if the screen did not change
+	frame: Frame | null,
+} | {
+	type: 'terminal-output',
+	// everything the program wrote since the last request
+	text: string,
+} | {
+	type: 'audio',
+	// Float32Array of mono samples between -1 and 1
+	samples: ArrayBuffer,
+} | {
+	type: 'file',
+	// null if there is no such file
+	contents: ArrayBuffer | null,
 } | {
 	type: 'initialized',
 } | {
@@ -59,4 +128,9 @@ export type WorkerToMain = {
 	status: boolean;
 } | {
 	type: 'pong',
+} | {
+	type: 'assembled',
+	assembled: boolean,
+	// the numeric listing, or the errors one per line
+	text: string,
 };
\ No newline at end of file
@@ -1,11 +1,28 @@
 import { AllWorkers } from './comm';
-import { BufferType } from '../wasmWorker/syscalls';
 import { InitializeWindowBarrel } from '../windowBarrel';
+import { DrawFrame } from '../screenDriver';
+import { GetAudioSampleRate, GetSamplesNeeded, PlaySamples } from '../audioDriver';
 
+// the frame is rendered by rust, see RenderFramebuffer
 async function UpdateBuffers(id: string) {
-	const screenBuffers = await AllWorkers.messageWorker(id, 'request-buffer', {
-		bufferType: BufferType.OUTPUT_SCREEN,
-	})('buffer-contents');
+	const result = await AllWorkers.messageWorker(id, 'request-frame', {
+	})('frame');
+	if (result && result.frame) {
+		DrawFrame(result.frame);
+	}
+}
+
+// the samples are made by rust, see SynthesizeAudio
+async function UpdateAudio(id: string) {
+	const count = GetSamplesNeeded();
+	if (count === 0) return;
+	const result = await AllWorkers.messageWorker(id, 'request-audio', {
+		count,
+		sampleRate: GetAudioSampleRate(),
+	})('audio');
+	if (result) {
+		PlaySamples(new Float32Array(result.samples));
+	}
 }
 
 export async function InitializeWasmAsync(id: string, text: string) {
@@ -22,13 +39,33 @@ export async function StepAsync(id: string) {
 	return result;
 }
 
+function NextAnimationFrameAsync() {
+	return new Promise<number>(resolve => window.requestAnimationFrame(resolve));
+}
+
+// a program that presents frames stops at every Present, requesting the
+// frame starts the next one and the program is continued once per display frame
 export async function RunAsync(id: string) {
-	const result = await AllWorkers.messageWorker(id, 'start', {
+	let result = await AllWorkers.messageWorker(id, 'start', {
 	})('stopped');
+	while (result && result.waitingForFrame) {
+		await NextAnimationFrameAsync();
+		await UpdateAudio(id);
+		await UpdateBuffers(id);
+		result = await AllWorkers.messageWorker(id, 'start', {
+		})('stopped');
+	}
 	await UpdateBuffers(id);
 	return result;
 }
 
+// assembled by the rust assembler, the same one dsl-asm uses
+export async function AssembleAsync(id: string, source: string) {
+	return await AllWorkers.messageWorker(id, 'assemble', {
+		source,
+	})('assembled');
+}
+
 export async function RequestBlockAsync(id: string, blockNum: number): Promise<number[] | null> {
 	const result = await AllWorkers.messageWorker(id, 'get-block', {
 		blockNum,
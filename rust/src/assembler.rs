@@ -0,0 +1,1857 @@
+// Assembler for DSL assembly, the language the editor highlights from
+// js/src/utils/language/dsla.ts. The browser's compile button assembles
+// with this too, through r_Assemble.
+//
+//	.data
+//	var message string "hello"
+//	var count number 3
+//	var table array 1 2 3
+//		4 5 6
+//	.text
+//	// comments take a whole line
+//	@loop
+//	addi count count 1
+//	beq count limit done
+//	goto loop
+//
+// A program starts with .data or .text and can switch between them. The
+// image is a jump over the variables, the variables, the code and a final
+// halt. Arrays and strings are zero terminated. Labels and variables are
+// absolute addresses, variables can be indexed with a constant or another
+// variable, like table[2] or table[i]. An array continues on the lines
+// after it.
+//
+// Besides the mnemonics of the TS assembler, a line of code can be any
+// opcode of opcodes.rs by name, followed by its parameters:
+//
+//	LoadImmmediateToBus 4
+//	SaveValueInBusToLocation args + 1
+//
+// Numbers, immediates and parameters are expressions of numbers, constants,
+// labels and variables with + - * and parentheses, like `loop + 3`. An
+// argument is one expression, so `a -3` is two arguments and `a - 3` or
+// `a-3` is one. A dash followed by a letter is part of a name, like in
+// the label @main-loop.
+//
+// The directives are:
+//
+//	.const NAME expression
+//	.macro name param1 param2 ... / .endmacro
+//	.if expression / .else / .endif
+//	.include "file"
+//	.export name
+//	.import name
+//	.protect .text | .data | variable permissions
+//
+// Constants can be used before they are defined, except in .if, where only
+// constants defined before it can be used. A macro is used like a mnemonic,
+// with one argument per parameter, and its lines are assembled with every
+// parameter replaced by the argument. An argument can't have spaces in it.
+// Included files are read relative to the file that includes them.
+// .protect gives the code, the variables or one variable permissions from
+// protection.rs, written as the letters r, w and x or - for none, like
+// `.protect .text rx`. The code includes the jump at the start and the
+// final halt.
+//
+// A program is assembled into an object, see object.rs, and linked on its
+// own. Labels and variables named in .export can be used by the objects it
+// is linked with, and names in .import come from them. Addresses can only be
+// added to or subtracted from, so the linker can move them.
+//
+// The machine code comes from the opcode table in opcodes.rs.
+
+use std::collections::HashMap;
+use std::fmt;
+use super::{storage, location};
+use opcodes::*;
+use host::ALERT;
+use object::{Object, Relocation, RelocationTarget, Export, link};
+use debuginfo::DebugInfo;
+use listing::{LOAD_ADDRESS, protection_annotation};
+use protection::{ImageRange, parse_permissions};
+
+// how deep macros and includes can nest
+const MAX_DEPTH: usize = 16;
+// expressions are parsed and evaluated recursively, so the operators and
+// parentheses of one are capped, which caps how deep it nests
+const MAX_OPERATORS: usize = 256;
+
+// 1 based, in characters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+	// the index of the file in Assembly::files
+	pub file: usize,
+	pub line: usize,
+	pub column: usize,
+	pub length: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+	// the name of the file the error is in, empty for a source without a name
+	pub file: String,
+	pub span: Span,
+	pub message: String,
+}
+
+impl fmt::Display for AsmError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if !self.file.is_empty() {
+			write!(f, "{}:", self.file)?;
+		}
+		return write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message);
+	}
+}
+
+// the same as VariableType in dslaHelpers.ts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VariableType {
+	// one word
+	Unit,
+	// zero terminated words, strings are arrays of characters
+	Array,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolKind {
+	Label,
+	Variable(VariableType),
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+	pub name: String,
+	pub kind: SymbolKind,
+	pub address: location,
+	// where it was declared
+	pub span: Span,
+}
+
+// debug info for one word of the image
+#[derive(Clone, Debug)]
+pub struct Word {
+	// the source the word was made from, None for words the assembler adds,
+	// words from a macro have the span of the line that used the macro
+	pub span: Option<Span>,
+	pub code: bool,
+	pub comment: String,
+}
+
+pub struct Assembly {
+	pub image: Vec<storage>,
+	// one per word of the image
+	pub words: Vec<Word>,
+	pub symbols: Vec<Symbol>,
+	// the source and the files it included, in the order they were read
+	pub files: Vec<String>,
+	// from .protect, for the loader to apply
+	pub protections: Vec<ImageRange>,
+}
+
+impl Assembly {
+	pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+		return self.symbols.iter().find(|symbol| symbol.name == name);
+	}
+
+	// the source map of the program, see debuginfo.rs
+	pub fn debug_info(&self) -> DebugInfo {
+		return DebugInfo::from_source(LOAD_ADDRESS, &self.words, &self.symbols, &self.files);
+	}
+
+	// the numeric listing format, see listing.rs
+	pub fn to_listing(&self) -> String {
+		let mut text = String::new();
+		for range in self.protections.iter() {
+			text.push_str(&protection_annotation(range));
+			text.push('\n');
+		}
+		for (value, word) in self.image.iter().zip(self.words.iter()) {
+			text.push_str(&value.to_string());
+			if word.code {
+				text.push_str(" #[code]");
+			}
+			if !word.comment.is_empty() {
+				text.push_str(" # ");
+				text.push_str(&word.comment);
+			}
+			text.push('\n');
+		}
+		return text;
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Param {
+	// a variable expression that is read
+	Source,
+	// a variable expression that is written
+	Dest,
+	Immediate,
+	Label,
+}
+
+struct Mnemonic {
+	name: &'static str,
+	params: &'static [(&'static str, Param)],
+}
+
+static MNEMONICS: [Mnemonic; 11] = [
+	Mnemonic { name: "add", params: &[("dest", Param::Dest), ("source1", Param::Source), ("source2", Param::Source)] },
+	Mnemonic { name: "addi", params: &[("dest", Param::Dest), ("source", Param::Source), ("imm", Param::Immediate)] },
+	Mnemonic { name: "loadi", params: &[("dest", Param::Dest), ("imm", Param::Immediate)] },
+	Mnemonic { name: "goto", params: &[("label", Param::Label)] },
+	Mnemonic { name: "beq", params: &[("source1", Param::Source), ("source2", Param::Source), ("label", Param::Label)] },
+	Mnemonic { name: "halt", params: &[] },
+	Mnemonic { name: "beqal", params: &[("source1", Param::Source), ("source2", Param::Source), ("label", Param::Label)] },
+	Mnemonic { name: "gotol", params: &[("label", Param::Label)] },
+	Mnemonic { name: "captureLink", params: &[("linkDestination", Param::Dest)] },
+	Mnemonic { name: "ret", params: &[("sourceVar", Param::Source)] },
+	Mnemonic { name: "alert", params: &[("ptr", Param::Source)] },
+];
+
+// comparison modes of opcode 29
+const COMPARE_EQUAL: storage = 0;
+
+#[derive(Clone, Debug)]
+enum Expr {
+	Number(storage),
+	// a constant, label or variable
+	Name(String, Span),
+	Negate(Box<Expr>),
+	Add(Box<Expr>, Box<Expr>),
+	Subtract(Box<Expr>, Box<Expr>),
+	Multiply(Box<Expr>, Box<Expr>),
+}
+
+enum Offset {
+	Constant(Expr),
+	Variable(String),
+}
+
+enum Arg {
+	Expr(Expr),
+	// name[offset]
+	Indexed {
+		name: String,
+		offset: Offset,
+	},
+}
+
+// an operand that is only known once everything is laid out
+enum Operand {
+	Value(storage),
+	Variable(String, Span),
+	Expr(Expr),
+}
+
+struct Op {
+	code: storage,
+	operands: Vec<Operand>,
+}
+
+impl Op {
+	fn new(code: storage, operands: Vec<Operand>) -> Op {
+		debug_assert_eq!(opcode(code).map(|op| op.parameters), Some(operands.len()));
+		Op {
+			code,
+			operands,
+		}
+	}
+
+	fn size(&self) -> usize {
+		return 1 + self.operands.len();
+	}
+}
+
+struct Instruction {
+	ops: Vec<Op>,
+	// None for the final halt
+	span: Option<Span>,
+	// the source, for the listing's comments
+	text: String,
+}
+
+impl Instruction {
+	fn size(&self) -> usize {
+		return self.ops.iter().map(|op| op.size()).sum();
+	}
+}
+
+struct Global {
+	name: String,
+	kind: VariableType,
+	values: Vec<Expr>,
+	span: Span,
+}
+
+struct Label {
+	name: String,
+	// the instruction the label is in front of
+	instruction: usize,
+	span: Span,
+}
+
+struct Constant {
+	value: Expr,
+	span: Span,
+}
+
+struct Macro {
+	params: Vec<String>,
+	// the file, line number and text of every line of the body
+	body: Vec<(usize, usize, String)>,
+	span: Span,
+}
+
+// a .macro whose .endmacro hasn't been reached
+struct MacroDefinition {
+	name: String,
+	definition: Macro,
+}
+
+// an .if whose .endif hasn't been reached
+struct Condition {
+	// the lines are assembled
+	active: bool,
+	// the lines of one of the branches are or were assembled, or the .if is
+	// inside a branch that isn't assembled
+	taken: bool,
+	seen_else: bool,
+	span: Span,
+}
+
+// a number, or an address that the linker moves
+#[derive(Clone, Copy)]
+struct Value {
+	value: storage,
+	target: Option<RelocationTarget>,
+}
+
+// the addresses of the variables and of the labels
+type Addresses<'b> = (&'b HashMap<String, location>, &'b HashMap<String, location>);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+	None,
+	Data,
+	Text,
+}
+
+struct Assembler<'a> {
+	section: Section,
+	globals: Vec<Global>,
+	labels: Vec<Label>,
+	instructions: Vec<Instruction>,
+	constants: HashMap<String, Constant>,
+	macros: HashMap<String, Macro>,
+	defining: Option<MacroDefinition>,
+	conditions: Vec<Condition>,
+	files: Vec<String>,
+	// the files being read, innermost last, to catch an include of itself
+	reading: Vec<usize>,
+	// the span of the outermost macro being expanded
+	expansion: Option<Span>,
+	depth: usize,
+	exports: Vec<(String, Span)>,
+	imports: Vec<(String, Span)>,
+	// .text, .data or a variable, and its permissions
+	protects: Vec<(String, storage, Span)>,
+	read_file: &'a mut dyn FnMut(&str) -> Result<String, String>,
+	errors: Vec<AsmError>,
+}
+
+// every error in the source is reported, not just the first
+pub fn assemble(source: &str) -> Result<Assembly, Vec<AsmError>> {
+	return assemble_with_includes("", source, &mut |_| Err("includes are not available".to_string()));
+}
+
+// the name is the name of the source, includes are read with read_file
+pub fn assemble_with_includes(name: &str, source: &str, read_file: &mut dyn FnMut(&str) -> Result<String, String>) -> Result<Assembly, Vec<AsmError>> {
+	let mut assembler = Assembler::new(read_file);
+	assembler.files.push(name.to_string());
+	assembler.source(0, source);
+	// nothing else is linked, so nothing can be imported
+	let imports: Vec<AsmError> = assembler.imports.iter()
+		.map(|&(ref name, span)| error(span, &format!("'{}' is imported, the program needs to be linked", name)))
+		.collect();
+	assembler.errors.extend(imports);
+	let object = assembler.finish()?;
+	let assembly = link(&[object]).expect("an object without imports links");
+	return Ok(assembly);
+}
+
+// an object for the linker, see object.rs
+pub fn assemble_object(name: &str, source: &str, read_file: &mut dyn FnMut(&str) -> Result<String, String>) -> Result<Object, Vec<AsmError>> {
+	let mut assembler = Assembler::new(read_file);
+	assembler.files.push(name.to_string());
+	assembler.source(0, source);
+	return assembler.finish();
+}
+
+impl<'a> Assembler<'a> {
+	fn new(read_file: &'a mut dyn FnMut(&str) -> Result<String, String>) -> Assembler<'a> {
+		let section = Section::None;
+		let globals = Vec::new();
+		let labels = Vec::new();
+		let instructions = Vec::new();
+		let constants = HashMap::new();
+		let macros = HashMap::new();
+		let defining = None;
+		let conditions = Vec::new();
+		let files = Vec::new();
+		let reading = Vec::new();
+		let expansion = None;
+		let depth = 0;
+		let exports = Vec::new();
+		let imports = Vec::new();
+		let protects = Vec::new();
+		let errors = Vec::new();
+		Assembler {
+			section,
+			globals,
+			labels,
+			instructions,
+			constants,
+			macros,
+			defining,
+			conditions,
+			files,
+			reading,
+			expansion,
+			depth,
+			exports,
+			imports,
+			protects,
+			read_file,
+			errors,
+		}
+	}
+
+	fn source(&mut self, file: usize, source: &str) {
+		self.reading.push(file);
+		let conditions = self.conditions.len();
+		for (index, line) in source.lines().enumerate() {
+			if let Err(error) = self.line(file, index + 1, line) {
+				self.errors.push(error);
+			}
+		}
+		// a file closes what it opens
+		if let Some(defining) = self.defining.take() {
+			self.errors.push(error(defining.definition.span, &format!("macro '{}' has no .endmacro", defining.name)));
+		}
+		while self.conditions.len() > conditions {
+			let condition = self.conditions.pop().unwrap();
+			self.errors.push(error(condition.span, ".if has no .endif"));
+		}
+		self.reading.pop();
+	}
+
+	fn line(&mut self, file: usize, line_number: usize, line: &str) -> Result<(), AsmError> {
+		let tokens = tokens(file, line_number, line);
+		let (first, first_span) = match tokens.first() {
+			Some(&(token, span)) => (token, span),
+			None => return Ok(()),
+		};
+		if first.starts_with("//") {
+			return Ok(());
+		}
+
+		if self.defining.is_some() {
+			return self.macro_line(file, line_number, line, first, first_span);
+		}
+		match first {
+			".if" | ".else" | ".endif" => {
+				return self.condition(line, &tokens);
+			},
+			_ => {},
+		}
+		if self.conditions.iter().any(|condition| !condition.active) {
+			return Ok(());
+		}
+
+		match (self.section, first) {
+			(_, ".text") | (_, ".data") => {
+				if let Some(&(_, span)) = tokens.get(1) {
+					return Err(error(span, "expected the end of the line"));
+				}
+				self.section = if first == ".text" { Section::Text } else { Section::Data };
+				return Ok(());
+			},
+			(_, ".const") => {
+				return self.declare_constant(line, &tokens);
+			},
+			(_, ".macro") => {
+				return self.declare_macro(&tokens);
+			},
+			(_, ".endmacro") => {
+				return Err(error(first_span, ".endmacro without .macro"));
+			},
+			(_, ".include") => {
+				return self.include(line, &tokens);
+			},
+			(_, ".export") | (_, ".import") => {
+				return self.declare_linked(&tokens);
+			},
+			(_, ".protect") => {
+				return self.declare_protection(&tokens);
+			},
+			(_, _) if first.starts_with('.') => {
+				return Err(error(first_span, &format!("unknown directive {}", first)));
+			},
+			(Section::None, _) => {
+				return Err(error(first_span, ".text or .data must be first in program"));
+			},
+			(Section::Data, "var") => {
+				return self.declare_global(line, &tokens);
+			},
+			(Section::Data, _) => {
+				return self.continue_global(line, &tokens);
+			},
+			(Section::Text, _) if first.starts_with('@') => {
+				if let Some(&(_, span)) = tokens.get(1) {
+					return Err(error(span, &format!("label '{}' must not have anything else on the same line", first)));
+				}
+				return self.declare_label(first, first_span);
+			},
+			(Section::Text, _) => {
+				return self.instruction(line, &tokens);
+			},
+		}
+	}
+
+	// .const NAME expression
+	fn declare_constant(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let &(name, name_span) = tokens.get(1).ok_or(error(span, "expected a name after .const"))?;
+		if !is_name(name) {
+			return Err(error(name_span, &format!("'{}' is not a valid constant name", name)));
+		}
+		if let Some(constant) = self.constants.get(name) {
+			return Err(error(name_span, &format!("already have a constant '{}' from line {}", name, constant.span.line)));
+		}
+		let &(_, value_span) = tokens.get(2).ok_or(error(name_span, &format!("expected a value for '{}'", name)))?;
+		let value = self.single_expression(line, value_span)?;
+		self.constants.insert(name.to_string(), Constant { value, span: name_span });
+		return Ok(());
+	}
+
+	// .if expression, .else, .endif
+	fn condition(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let (first, span) = tokens[0];
+		if first != ".if" {
+			if let Some(&(_, span)) = tokens.get(1) {
+				return Err(error(span, "expected the end of the line"));
+			}
+		}
+		match first {
+			".if" => {
+				let skipping = self.conditions.iter().any(|condition| !condition.active);
+				let value = if skipping {
+					Ok(0)
+				}
+				else {
+					tokens.get(1).ok_or(error(span, "expected a condition after .if"))
+						.and_then(|&(_, value_span)| self.single_expression(line, value_span))
+						.and_then(|condition| self.value(&condition, None, 0).map(|value| value.value))
+				};
+				// a broken condition still needs its .endif, neither branch is assembled
+				let active = value.as_ref().is_ok_and(|&value| value != 0);
+				self.conditions.push(Condition {
+					active,
+					taken: active || skipping || value.is_err(),
+					seen_else: false,
+					span,
+				});
+				value?;
+			},
+			".else" => {
+				let condition = self.conditions.last_mut().ok_or(error(span, ".else without .if"))?;
+				if condition.seen_else {
+					return Err(error(span, "the .if already has an .else"));
+				}
+				condition.seen_else = true;
+				condition.active = !condition.taken;
+				condition.taken = true;
+			},
+			_ => {
+				self.conditions.pop().ok_or(error(span, ".endif without .if"))?;
+			},
+		}
+		return Ok(());
+	}
+
+	// .macro name param1 param2 ...
+	fn declare_macro(&mut self, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let &(name, name_span) = tokens.get(1).ok_or(error(span, "expected a name after .macro"))?;
+		if !is_name(name) {
+			return Err(error(name_span, &format!("'{}' is not a valid macro name", name)));
+		}
+		if MNEMONICS.iter().any(|mnemonic| mnemonic.name == name) || opcode_named(name).is_some() {
+			return Err(error(name_span, &format!("'{}' is already an operation", name)));
+		}
+		if let Some(existing) = self.macros.get(name) {
+			return Err(error(name_span, &format!("already have a macro '{}' from line {}", name, existing.span.line)));
+		}
+		let mut params = Vec::new();
+		for &(param, param_span) in tokens[2 ..].iter() {
+			if !is_name(param) {
+				return Err(error(param_span, &format!("'{}' is not a valid parameter name", param)));
+			}
+			if params.iter().any(|existing| existing == param) {
+				return Err(error(param_span, &format!("already have a parameter '{}'", param)));
+			}
+			params.push(param.to_string());
+		}
+		self.defining = Some(MacroDefinition {
+			name: name.to_string(),
+			definition: Macro {
+				params,
+				body: Vec::new(),
+				span: name_span,
+			},
+		});
+		return Ok(());
+	}
+
+	// a line between .macro and .endmacro
+	fn macro_line(&mut self, file: usize, line_number: usize, line: &str, first: &str, span: Span) -> Result<(), AsmError> {
+		match first {
+			".endmacro" => {
+				let defining = self.defining.take().unwrap();
+				self.macros.insert(defining.name, defining.definition);
+			},
+			".macro" => {
+				return Err(error(span, "macros can't be defined inside a macro"));
+			},
+			_ => {
+				let defining = self.defining.as_mut().unwrap();
+				defining.definition.body.push((file, line_number, line.to_string()));
+			},
+		}
+		return Ok(());
+	}
+
+	// name arg1 arg2 ...
+	fn expand_macro(&mut self, name: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let (params, body) = {
+			let definition = &self.macros[name];
+			(definition.params.clone(), definition.body.clone())
+		};
+		let args: Vec<&str> = tokens[1 ..].iter().map(|&(arg, _)| arg).collect();
+		if args.len() != params.len() {
+			return Err(error(span, &format!("macro '{}' takes {} arguments but was given {}", name, params.len(), args.len())));
+		}
+		if self.depth == MAX_DEPTH {
+			return Err(error(span, "macros are nested too deep"));
+		}
+
+		let outermost = self.expansion.is_none();
+		if outermost {
+			self.expansion = Some(span);
+		}
+		self.depth += 1;
+		let conditions = self.conditions.len();
+		for (file, line_number, text) in body.iter() {
+			let text = substitute(text, &params, &args);
+			if let Err(mut inner) = self.line(*file, *line_number, &text) {
+				inner.message = format!("{} (in macro '{}' used on line {})", inner.message, name, span.line);
+				self.errors.push(inner);
+			}
+		}
+		self.depth -= 1;
+		if outermost {
+			self.expansion = None;
+		}
+		// a macro closes what it opens, like a file
+		while self.conditions.len() > conditions {
+			self.conditions.pop();
+			self.errors.push(error(span, &format!(".if in macro '{}' has no .endif", name)));
+		}
+		return Ok(());
+	}
+
+	// .export name or .import name
+	fn declare_linked(&mut self, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let (directive, span) = tokens[0];
+		let &(name, name_span) = tokens.get(1).ok_or(error(span, &format!("expected a name after {}", directive)))?;
+		if let Some(&(_, span)) = tokens.get(2) {
+			return Err(error(span, "expected the end of the line"));
+		}
+		// labels can be exported, they can have dashes
+		let valid = is_name(name) || (directive == ".export" && is_label_name(name));
+		if !valid {
+			return Err(error(name_span, &format!("'{}' is not a valid name", name)));
+		}
+		let names = if directive == ".export" { &mut self.exports } else { &mut self.imports };
+		if names.iter().any(|(existing, _)| existing == name) {
+			return Err(error(name_span, &format!("'{}' is already in {}", name, directive)));
+		}
+		names.push((name.to_string(), name_span));
+		return Ok(());
+	}
+
+	// .protect .text | .data | variable permissions
+	fn declare_protection(&mut self, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let &(target, target_span) = tokens.get(1).ok_or(error(span, "expected .text, .data or a variable after .protect"))?;
+		if target != ".text" && target != ".data" && !is_name(target) {
+			return Err(error(target_span, &format!("'{}' is not .text, .data or a variable", target)));
+		}
+		let &(text, permissions_span) = tokens.get(2).ok_or(error(target_span, "expected permissions, like rx"))?;
+		if let Some(&(_, span)) = tokens.get(3) {
+			return Err(error(span, "expected the end of the line"));
+		}
+		let permissions = parse_permissions(text)
+			.ok_or(error(permissions_span, &format!("'{}' is not permissions, expected the letters r, w and x or - for none", text)))?;
+		self.protects.push((target.to_string(), permissions, self.expansion.unwrap_or(target_span)));
+		return Ok(());
+	}
+
+	// .include "file"
+	fn include(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let &(_, path_span) = tokens.get(1).ok_or(error(span, "expected a file name in double quotes"))?;
+		let path = quoted(line, path_span).ok_or(error(path_span, "expected a file name in double quotes"))?;
+		let current = &self.files[span.file];
+		let name = match current.rfind('/') {
+			Some(end) if !path.starts_with('/') => format!("{}{}", &current[..= end], path),
+			_ => path,
+		};
+		if self.reading.iter().any(|&file| self.files[file] == name) {
+			return Err(error(path_span, &format!("'{}' includes itself", name)));
+		}
+		if self.reading.len() == MAX_DEPTH {
+			return Err(error(path_span, "includes are nested too deep"));
+		}
+		let source = (self.read_file)(&name).map_err(|message| error(path_span, &format!("can't include '{}': {}", name, message)))?;
+		self.files.push(name);
+		let file = self.files.len() - 1;
+		self.source(file, &source);
+		return Ok(());
+	}
+
+	// var <name> <string | number | array> <value>
+	fn declare_global(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let &(name, name_span) = tokens.get(1).ok_or(error(span, "expected a variable name after 'var'"))?;
+		if !is_name(name) {
+			return Err(error(name_span, &format!("'{}' is not a valid variable name", name)));
+		}
+		if self.globals.iter().any(|global| global.name == name) {
+			return Err(error(name_span, &format!("already have a variable '{}'", name)));
+		}
+		let &(kind, kind_span) = tokens.get(2).ok_or(error(name_span, "expected either string, number, or array for declaration type"))?;
+
+		let (kind, values) = match kind {
+			"string" => {
+				let value_span = tokens.get(3).map_or(kind_span, |&(_, span)| span);
+				let text = quoted(line, value_span).ok_or(error(value_span, "expected a string in double quotes"))?;
+				let values = text.chars().map(|c| Expr::Number(c as storage)).collect();
+				(VariableType::Array, values)
+			},
+			"number" => {
+				let &(_, value_span) = tokens.get(3).ok_or(error(kind_span, "expected a number"))?;
+				(VariableType::Unit, vec![self.single_expression(line, value_span)?])
+			},
+			"array" => {
+				let values = match tokens.get(3) {
+					Some(&(_, span)) => self.expressions(line, span)?,
+					None => Vec::new(),
+				};
+				(VariableType::Array, values)
+			},
+			_ => {
+				return Err(error(kind_span, "expected either string, number, or array for declaration type"));
+			},
+		};
+
+		self.globals.push(Global {
+			name: name.to_string(),
+			kind,
+			values,
+			span: self.expansion.unwrap_or(name_span),
+		});
+		return Ok(());
+	}
+
+	// more values for the last array
+	fn continue_global(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let span = tokens[0].1;
+		let values = self.expressions(line, span)?;
+		let global = match self.globals.last_mut() {
+			Some(global) => global,
+			None => return Err(error(span, "expected a variable declaration")),
+		};
+		if global.kind != VariableType::Array {
+			return Err(error(span, &format!("cannot continue '{}', it is not an array", global.name)));
+		}
+		global.values.extend(values);
+		return Ok(());
+	}
+
+	// @name
+	fn declare_label(&mut self, token: &str, span: Span) -> Result<(), AsmError> {
+		let name = &token[1 ..];
+		if !is_label_name(name) {
+			return Err(error(span, &format!("'{}' is not a valid label", token)));
+		}
+		if self.labels.iter().any(|label| label.name == name) {
+			return Err(error(span, &format!("already have a label '{}'", name)));
+		}
+		self.labels.push(Label {
+			name: name.to_string(),
+			instruction: self.instructions.len(),
+			span,
+		});
+		return Ok(());
+	}
+
+	// <mnemonic, opcode name or macro> <arguments>
+	fn instruction(&mut self, line: &str, tokens: &[(&str, Span)]) -> Result<(), AsmError> {
+		let (name, name_span) = tokens[0];
+		if self.macros.contains_key(name) {
+			return self.expand_macro(name, tokens);
+		}
+		let args = self.arguments(line, name_span.column - 1 + name.chars().count(), name_span)?;
+
+		let ops = if let Some(op) = opcode_named(name) {
+			if args.len() != op.parameters {
+				let at = args.get(op.parameters).map_or(name_span, |&(_, span)| span);
+				return Err(error(at, &format!("{} takes {} parameters but was given {}", name, op.parameters, args.len())));
+			}
+			let mut operands = Vec::new();
+			for (arg, span) in args.into_iter() {
+				match arg {
+					Arg::Expr(expr) => operands.push(Operand::Expr(expr)),
+					Arg::Indexed { .. } => return Err(error(span, "expected an expression")),
+				}
+			}
+			vec![Op::new(op.code, operands)]
+		}
+		else if let Some(mnemonic) = MNEMONICS.iter().find(|m| m.name == name) {
+			check_arguments(mnemonic, name_span, &args)?;
+			expand(mnemonic.name, args)
+		}
+		else {
+			return Err(error(name_span, &format!("invalid operation: {}", name)));
+		};
+
+		let text = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+		let span = Span {
+			column: name_span.column,
+			length: line.trim_end().chars().count() + 1 - name_span.column,
+			..name_span
+		};
+		self.instructions.push(Instruction {
+			ops,
+			span: Some(self.expansion.unwrap_or(span)),
+			text,
+		});
+		return Ok(());
+	}
+
+	fn finish(mut self) -> Result<Object, Vec<AsmError>> {
+		self.instructions.push(Instruction {
+			ops: expand("halt", Vec::new()),
+			span: None,
+			text: "halt".to_string(),
+		});
+
+		let mut image = Vec::new();
+		let mut words = Vec::new();
+		let mut symbols = Vec::new();
+
+		// the jump over the variables, its target is filled in below
+		let assembler_word = |code, comment: &str| Word {
+			span: None,
+			code,
+			comment: comment.to_string(),
+		};
+		image.extend_from_slice(&[LOAD_IMMEDIATE, 0, JUMP]);
+		words.push(assembler_word(true, "jump to the start of the program"));
+		words.push(assembler_word(false, ""));
+		words.push(assembler_word(true, ""));
+
+		// every address is known before any value is, from the start of the object
+		let mut variables = HashMap::new();
+		let jump = image.len() as location;
+		let mut address = jump;
+		for global in self.globals.iter() {
+			variables.insert(global.name.clone(), address);
+			symbols.push(Symbol {
+				name: global.name.clone(),
+				kind: SymbolKind::Variable(global.kind),
+				address,
+				span: global.span,
+			});
+			address += global.values.len() as location;
+			if global.kind == VariableType::Array {
+				address += 1;
+			}
+		}
+		let code_start = address;
+		image[1] = code_start;
+		let mut relocations = vec![Relocation { address: 1, target: RelocationTarget::Object }];
+		let mut starts = Vec::new();
+		for instruction in self.instructions.iter() {
+			starts.push(address);
+			address += instruction.size() as location;
+		}
+		let end = address;
+
+		// the ranges of .protect, the variables are between the jump at the start and the code
+		let mut protections = Vec::new();
+		let mut errors = Vec::new();
+		for &(ref target, permissions, span) in self.protects.iter() {
+			let ranges = match target.as_str() {
+				".text" => vec![(0, jump), (code_start, end - code_start)],
+				".data" => vec![(jump, code_start - jump)],
+				name => match self.globals.iter().find(|global| global.name == name) {
+					Some(global) => {
+						let terminator = if global.kind == VariableType::Array { 1 } else { 0 };
+						vec![(variables[name], global.values.len() as storage + terminator)]
+					},
+					None => {
+						errors.push(error(span, &format!("cannot find variable '{}' to protect", name)));
+						continue;
+					},
+				},
+			};
+			for (start, length) in ranges.into_iter().filter(|&(_, length)| length > 0) {
+				protections.push(ImageRange {
+					start,
+					length,
+					permissions,
+				});
+			}
+		}
+
+		let mut labels = HashMap::new();
+		for label in self.labels.iter() {
+			let address = starts[label.instruction];
+			labels.insert(label.name.clone(), address);
+			symbols.push(Symbol {
+				name: label.name.clone(),
+				kind: SymbolKind::Label,
+				address,
+				span: label.span,
+			});
+		}
+
+		for &(ref name, span) in self.imports.iter() {
+			if variables.contains_key(name) || labels.contains_key(name) || self.constants.contains_key(name) {
+				errors.push(error(span, &format!("'{}' is imported but also defined here", name)));
+			}
+		}
+		let mut exports = Vec::new();
+		for &(ref name, span) in self.exports.iter() {
+			match symbols.iter().find(|symbol| symbol.name == *name) {
+				Some(symbol) => exports.push(Export {
+					name: name.clone(),
+					kind: symbol.kind,
+					address: symbol.address,
+				}),
+				None => errors.push(error(span, &format!("cannot find a label or variable '{}' to export", name))),
+			}
+		}
+
+		// a value goes in the image, and in the relocations if it is an address
+		let mut push = |image: &mut Vec<storage>, value: Result<Value, AsmError>| {
+			match value {
+				Ok(value) => {
+					if let Some(target) = value.target {
+						relocations.push(Relocation { address: image.len() as location, target });
+					}
+					image.push(value.value);
+				},
+				Err(error) => {
+					errors.push(error);
+					image.push(0);
+				},
+			}
+		};
+		let addresses = Some((&variables, &labels));
+		for global in self.globals.iter() {
+			for (index, value) in global.values.iter().enumerate() {
+				let comment = if index == 0 { format!("{:?} {}", global.kind, global.name) } else { String::new() };
+				push(&mut image, self.value(value, addresses, 0));
+				words.push(Word {
+					span: Some(global.span),
+					code: false,
+					comment,
+				});
+			}
+			if global.kind == VariableType::Array {
+				let comment = if global.values.is_empty() { format!("{:?} {}", global.kind, global.name) } else { format!("null terminate {}", global.name) };
+				image.push(0);
+				words.push(Word {
+					span: Some(global.span),
+					code: false,
+					comment,
+				});
+			}
+		}
+
+		for (index, instruction) in self.instructions.iter().enumerate() {
+			let label_names: Vec<String> = self.labels.iter()
+				.filter(|label| label.instruction == index)
+				.map(|label| format!("@{}", label.name))
+				.collect();
+			for (op_index, op) in instruction.ops.iter().enumerate() {
+				let name = opcode(op.code).map_or("", |op| op.name);
+				let comment = if op_index == 0 {
+					let mut parts = label_names.clone();
+					parts.push(instruction.text.clone());
+					parts.push(name.to_string());
+					parts.join(" -- ")
+				}
+				else {
+					name.to_string()
+				};
+				image.push(op.code);
+				words.push(Word {
+					span: instruction.span,
+					code: true,
+					comment,
+				});
+				for operand in op.operands.iter() {
+					let value = match *operand {
+						Operand::Value(value) => Ok(Value { value, target: None }),
+						Operand::Variable(ref name, span) => self.variable(name, span, &variables),
+						Operand::Expr(ref expr) => self.value(expr, addresses, 0),
+					};
+					push(&mut image, value);
+					words.push(Word {
+						span: instruction.span,
+						code: false,
+						comment: String::new(),
+					});
+				}
+			}
+		}
+
+		self.errors.extend(errors);
+		if !self.errors.is_empty() {
+			self.errors.sort_by_key(|error| (error.span.file, error.span.line, error.span.column));
+			for error in self.errors.iter_mut() {
+				error.file = self.files[error.span.file].clone();
+			}
+			return Err(self.errors);
+		}
+		return Ok(Object {
+			image,
+			relocations,
+			exports,
+			imports: self.imports.into_iter().map(|(name, _)| name).collect(),
+			words,
+			symbols,
+			files: self.files,
+			protections,
+		});
+	}
+
+	// the address of a variable given to a mnemonic, it can be imported
+	fn variable(&self, name: &str, span: Span, variables: &HashMap<String, location>) -> Result<Value, AsmError> {
+		if let Some(&address) = variables.get(name) {
+			return Ok(Value { value: address, target: Some(RelocationTarget::Object) });
+		}
+		if let Some(index) = self.imports.iter().position(|(import, _)| import == name) {
+			return Ok(Value { value: 0, target: Some(RelocationTarget::Import(index)) });
+		}
+		return Err(error(span, &format!("cannot find variable '{}'", name)));
+	}
+
+	// without the addresses only constants can be used
+	fn value(&self, expr: &Expr, addresses: Option<Addresses>, depth: usize) -> Result<Value, AsmError> {
+		let number = |value| Ok(Value { value, target: None });
+		match *expr {
+			Expr::Number(value) => {
+				return number(value);
+			},
+			Expr::Name(ref name, span) => {
+				if let Some(constant) = self.constants.get(name) {
+					if depth == MAX_DEPTH {
+						return Err(error(span, &format!("constant '{}' is defined in terms of itself", name)));
+					}
+					return self.value(&constant.value, addresses, depth + 1);
+				}
+				let (variables, labels) = match addresses {
+					Some(addresses) => addresses,
+					None => return Err(error(span, &format!("'{}' is not a constant defined before here", name))),
+				};
+				if let Some(&address) = variables.get(name).or_else(|| labels.get(name)) {
+					return Ok(Value { value: address, target: Some(RelocationTarget::Object) });
+				}
+				if let Some(index) = self.imports.iter().position(|(import, _)| import == name) {
+					return Ok(Value { value: 0, target: Some(RelocationTarget::Import(index)) });
+				}
+				return Err(error(span, &format!("cannot find a constant, variable or label '{}'", name)));
+			},
+			Expr::Negate(ref value) => {
+				let value = self.value(value, addresses, depth)?;
+				if value.target.is_some() {
+					return Err(address_error(expr));
+				}
+				return number(value.value.wrapping_neg());
+			},
+			Expr::Add(ref a, ref b) => {
+				let (a, b) = (self.value(a, addresses, depth)?, self.value(b, addresses, depth)?);
+				if a.target.is_some() && b.target.is_some() {
+					return Err(address_error(expr));
+				}
+				return Ok(Value { value: a.value.wrapping_add(b.value), target: a.target.or(b.target) });
+			},
+			Expr::Subtract(ref a, ref b) => {
+				let (a, b) = (self.value(a, addresses, depth)?, self.value(b, addresses, depth)?);
+				// the distance between two addresses in the object doesn't move
+				let target = match (a.target, b.target) {
+					(target, None) => target,
+					(Some(RelocationTarget::Object), Some(RelocationTarget::Object)) => None,
+					_ => return Err(address_error(expr)),
+				};
+				return Ok(Value { value: a.value.wrapping_sub(b.value), target });
+			},
+			Expr::Multiply(ref a, ref b) => {
+				let (a, b) = (self.value(a, addresses, depth)?, self.value(b, addresses, depth)?);
+				if a.target.is_some() || b.target.is_some() {
+					return Err(address_error(expr));
+				}
+				return number(a.value.wrapping_mul(b.value));
+			},
+		}
+	}
+
+	// the expressions starting at the token, to the end of the line
+	fn expressions(&self, line: &str, start: Span) -> Result<Vec<Expr>, AsmError> {
+		let args = self.arguments(line, start.column - 1, start)?;
+		let mut values = Vec::new();
+		for (arg, span) in args.into_iter() {
+			match arg {
+				Arg::Expr(expr) => values.push(expr),
+				Arg::Indexed { .. } => return Err(error(span, "expected an expression")),
+			}
+		}
+		return Ok(values);
+	}
+
+	// the one expression starting at the token, which ends the line
+	fn single_expression(&self, line: &str, start: Span) -> Result<Expr, AsmError> {
+		let mut values = self.expressions(line, start)?;
+		if values.len() > 1 {
+			let args = self.arguments(line, start.column - 1, start)?;
+			return Err(error(args[1].1, "expected the end of the line"));
+		}
+		return Ok(values.remove(0));
+	}
+
+	// the arguments from the character index rest on, the span is for the line
+	fn arguments(&self, line: &str, rest: usize, at: Span) -> Result<Vec<(Arg, Span)>, AsmError> {
+		let mut parser = Parser {
+			chars: line.chars().collect(),
+			i: rest,
+			at,
+			operators: 0,
+		};
+		let mut args = Vec::new();
+		loop {
+			parser.skip_whitespace();
+			if parser.i == parser.chars.len() {
+				break;
+			}
+			let start = parser.i;
+			let arg = parser.argument(&self.constants)?;
+			if parser.i < parser.chars.len() && !parser.chars[parser.i].is_whitespace() {
+				let end = parser.chars[parser.i ..].iter().position(|c| c.is_whitespace()).map_or(parser.chars.len(), |n| parser.i + n);
+				let text: String = parser.chars[start .. end].iter().collect();
+				return Err(error(parser.span(start, end), &format!("could not get a parameter from '{}'", text)));
+			}
+			args.push((arg, parser.span(start, parser.i)));
+		}
+		return Ok(args);
+	}
+}
+
+fn check_arguments(mnemonic: &Mnemonic, name_span: Span, args: &[(Arg, Span)]) -> Result<(), AsmError> {
+	if args.len() < mnemonic.params.len() {
+		let missing: Vec<&str> = mnemonic.params[args.len() ..].iter().map(|&(name, _)| name).collect();
+		return Err(error(name_span, &format!("too few parameters provided, missing {}", missing.join(", "))));
+	}
+	if args.len() > mnemonic.params.len() {
+		return Err(error(args[mnemonic.params.len()].1, "too many parameters provided"));
+	}
+	for (&(ref arg, span), &(param_name, param)) in args.iter().zip(mnemonic.params.iter()) {
+		let fits = matches!((param, arg),
+			(Param::Immediate, &Arg::Expr(_))
+			| (Param::Label, &Arg::Expr(_))
+			| (Param::Source, &Arg::Expr(Expr::Name(..)))
+			| (Param::Dest, &Arg::Expr(Expr::Name(..)))
+			| (Param::Source, &Arg::Indexed { .. })
+			| (Param::Dest, &Arg::Indexed { .. }));
+		if !fits {
+			let expected = match param {
+				Param::Immediate => "an expression",
+				Param::Label => "a label",
+				Param::Source | Param::Dest => "a variable",
+			};
+			return Err(error(span, &format!("expected {} for {}", expected, param_name)));
+		}
+	}
+	return Ok(());
+}
+
+// the machine code for an instruction whose arguments were checked
+fn expand(mnemonic: &str, args: Vec<(Arg, Span)>) -> Vec<Op> {
+	let load = |index: usize| access(&args[index], LOAD, LOAD_CONSTANT_OFFSET, LOAD_VARIABLE_OFFSET);
+	let save = |index: usize| access(&args[index], SAVE, SAVE_CONSTANT_OFFSET, SAVE_VARIABLE_OFFSET);
+	// immediates and labels are both expressions
+	let value = |index: usize| match args[index].0 {
+		Arg::Expr(ref expr) => Operand::Expr(expr.clone()),
+		Arg::Indexed { .. } => Operand::Value(0),
+	};
+
+	match mnemonic {
+		"add" => {
+			return vec![
+				load(1),
+				Op::new(PUSH_TO_ALU, vec![]),
+				load(2),
+				Op::new(ADD, vec![]),
+				Op::new(HI_TO_BUS, vec![]),
+				save(0),
+			];
+		},
+		"addi" => {
+			return vec![
+				load(1),
+				Op::new(PUSH_TO_ALU, vec![]),
+				Op::new(LOAD_IMMEDIATE, vec![value(2)]),
+				Op::new(ADD, vec![]),
+				Op::new(HI_TO_BUS, vec![]),
+				save(0),
+			];
+		},
+		"loadi" => {
+			return vec![
+				Op::new(LOAD_IMMEDIATE, vec![value(1)]),
+				save(0),
+			];
+		},
+		"beq" => {
+			return vec![
+				load(0),
+				Op::new(PUSH_TO_ALU, vec![]),
+				load(1),
+				Op::new(COMPARE, vec![Operand::Value(COMPARE_EQUAL)]),
+				Op::new(BRANCH, vec![value(2)]),
+			];
+		},
+		"goto" => {
+			return vec![
+				Op::new(LOAD_IMMEDIATE, vec![value(0)]),
+				Op::new(JUMP, vec![]),
+			];
+		},
+		"beqal" => {
+			return vec![
+				load(0),
+				Op::new(PUSH_TO_ALU, vec![]),
+				load(1),
+				Op::new(COMPARE, vec![Operand::Value(COMPARE_EQUAL)]),
+				Op::new(BRANCH, vec![value(2)]),
+				Op::new(LINK, vec![]),
+			];
+		},
+		"gotol" => {
+			return vec![
+				Op::new(LOAD_IMMEDIATE, vec![value(0)]),
+				Op::new(JUMP, vec![]),
+				Op::new(GET_POSITION, vec![]),
+			];
+		},
+		"captureLink" => {
+			return vec![
+				save(0),
+			];
+		},
+		"ret" => {
+			return vec![
+				load(0),
+				Op::new(JUMP, vec![]),
+			];
+		},
+		"alert" => {
+			return vec![
+				load(0),
+				Op::new(SYSCALL, vec![Operand::Value(ALERT)]),
+			];
+		},
+		_ => {
+			return vec![
+				Op::new(HALT, vec![]),
+			];
+		},
+	}
+}
+
+// name, name[constant] or name[variable]
+fn access(arg: &(Arg, Span), direct: storage, constant: storage, variable: storage) -> Op {
+	let (ref arg, span) = *arg;
+	match *arg {
+		Arg::Expr(Expr::Name(ref name, _)) => {
+			return Op::new(direct, vec![Operand::Variable(name.clone(), span)]);
+		},
+		Arg::Indexed { ref name, offset: Offset::Constant(ref offset) } => {
+			return Op::new(constant, vec![Operand::Variable(name.clone(), span), Operand::Expr(offset.clone())]);
+		},
+		Arg::Indexed { ref name, offset: Offset::Variable(ref index) } => {
+			return Op::new(variable, vec![Operand::Variable(name.clone(), span), Operand::Variable(index.clone(), span)]);
+		},
+		Arg::Expr(_) => {
+			return Op::new(direct, vec![Operand::Value(0)]);
+		},
+	}
+}
+
+// an expression that does more to an address than add a number to it
+fn address_error(expr: &Expr) -> AsmError {
+	let mut expr = expr;
+	let span = loop {
+		match *expr {
+			Expr::Name(_, span) => break span,
+			Expr::Negate(ref value) => expr = value,
+			Expr::Add(ref a, ref b) | Expr::Subtract(ref a, ref b) | Expr::Multiply(ref a, ref b) => {
+				expr = if name_in(a) { a } else { b };
+			},
+			Expr::Number(_) => unreachable!("addresses come from names"),
+		}
+	};
+	return error(span, "an address can only have a number added to or subtracted from it");
+}
+
+fn name_in(expr: &Expr) -> bool {
+	return match *expr {
+		Expr::Number(_) => false,
+		Expr::Name(..) => true,
+		Expr::Negate(ref value) => name_in(value),
+		Expr::Add(ref a, ref b) | Expr::Subtract(ref a, ref b) | Expr::Multiply(ref a, ref b) => name_in(a) || name_in(b),
+	};
+}
+
+fn error(span: Span, message: &str) -> AsmError {
+	return AsmError {
+		file: String::new(),
+		span,
+		message: message.to_string(),
+	};
+}
+
+// whitespace separated tokens with their spans
+fn tokens(file: usize, line_number: usize, line: &str) -> Vec<(&str, Span)> {
+	let mut tokens = Vec::new();
+	let mut start = None;
+	let mut column = 0;
+	let span = |column, length| Span { file, line: line_number, column, length };
+	for (index, c) in line.char_indices() {
+		column += 1;
+		match (c.is_whitespace(), start) {
+			(false, None) => {
+				start = Some((index, column));
+			},
+			(true, Some((begin, begin_column))) => {
+				tokens.push((&line[begin .. index], span(begin_column, column - begin_column)));
+				start = None;
+			},
+			_ => {},
+		}
+	}
+	if let Some((begin, begin_column)) = start {
+		tokens.push((&line[begin ..], span(begin_column, column + 1 - begin_column)));
+	}
+	return tokens;
+}
+
+// everything between the first and the last quote, from the token on
+fn quoted(line: &str, start: Span) -> Option<String> {
+	let index = line.char_indices().nth(start.column - 1).map_or(line.len(), |(index, _)| index);
+	let text = line[index ..].trim_end();
+	if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+		return None;
+	}
+	return Some(text[1 .. text.len() - 1].to_string());
+}
+
+fn is_name(text: &str) -> bool {
+	let mut chars = text.chars();
+	return match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$'),
+		_ => false,
+	};
+}
+
+// the same as the label regex of dslaHelpers.ts
+fn is_label_name(name: &str) -> bool {
+	return name.len() >= 2 && !name.starts_with('-') && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '-');
+}
+
+// replaces every whole word that is a parameter with its argument
+fn substitute(text: &str, params: &[String], args: &[&str]) -> String {
+	let mut result = String::new();
+	let mut word = String::new();
+	for c in text.chars().chain(Some('\n')) {
+		if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+			word.push(c);
+			continue;
+		}
+		match params.iter().position(|param| *param == word) {
+			Some(index) => result.push_str(args[index]),
+			None => result.push_str(&word),
+		}
+		word.clear();
+		if c != '\n' {
+			result.push(c);
+		}
+	}
+	return result;
+}
+
+// reads arguments and expressions from a line
+struct Parser {
+	chars: Vec<char>,
+	i: usize,
+	// the file and line
+	at: Span,
+	// in the argument so far
+	operators: usize,
+}
+
+impl Parser {
+	fn span(&self, start: usize, end: usize) -> Span {
+		return Span {
+			column: start + 1,
+			length: end - start,
+			..self.at
+		};
+	}
+
+	fn peek(&self) -> Option<char> {
+		return self.chars.get(self.i).cloned();
+	}
+
+	fn skip_blanks(&mut self) {
+		while self.peek() == Some(' ') || self.peek() == Some('\t') {
+			self.i += 1;
+		}
+	}
+
+	fn count_operator(&mut self, start: usize) -> Result<(), AsmError> {
+		self.operators += 1;
+		if self.operators > MAX_OPERATORS {
+			return Err(error(self.span(start, self.i), &format!("an expression can have at most {} operators and parentheses", MAX_OPERATORS)));
+		}
+		return Ok(());
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek().is_some_and(|c| c.is_whitespace()) {
+			self.i += 1;
+		}
+	}
+
+	// an expression, or a variable with an offset
+	fn argument(&mut self, constants: &HashMap<String, Constant>) -> Result<Arg, AsmError> {
+		let start = self.i;
+		self.operators = 0;
+		if self.peek().is_some_and(is_name_start) {
+			let name = self.name();
+			if self.peek() == Some('[') {
+				self.i += 1;
+				self.skip_blanks();
+				let inner = self.i;
+				// a name that isn't a constant is an index variable
+				let mut offset = None;
+				if self.peek().is_some_and(is_name_start) {
+					let index = self.name();
+					self.skip_blanks();
+					if self.peek() == Some(']') && !constants.contains_key(&index) {
+						offset = Some(Offset::Variable(index));
+					}
+					else {
+						self.i = inner;
+					}
+				}
+				let offset = match offset {
+					Some(offset) => offset,
+					None => Offset::Constant(self.expression()?),
+				};
+				self.skip_blanks();
+				if self.peek() != Some(']') {
+					return Err(error(self.span(start, self.i), &format!("could not parse offset of '{}'", name)));
+				}
+				self.i += 1;
+				return Ok(Arg::Indexed { name, offset });
+			}
+			self.i = start;
+		}
+		return Ok(Arg::Expr(self.expression()?));
+	}
+
+	fn name(&mut self) -> String {
+		let start = self.i;
+		while let Some(c) = self.peek() {
+			// a dash followed by a letter is part of the name
+			let dash = c == '-' && self.chars.get(self.i + 1).is_some_and(|&c| is_name_start(c));
+			if !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || dash) {
+				break;
+			}
+			self.i += 1;
+		}
+		return self.chars[start .. self.i].iter().collect();
+	}
+
+	// the operator after a term, if the expression goes on
+	fn operator(&mut self) -> Option<char> {
+		let start = self.i;
+		self.skip_blanks();
+		let spaced = self.i > start;
+		let next = self.chars.get(self.i + 1).cloned();
+		let binary = match self.peek() {
+			Some('*') => true,
+			// with a space before it and not after, it starts the next argument
+			Some('+') | Some('-') => !spaced || next.is_none_or(|c| c.is_whitespace()),
+			_ => false,
+		};
+		if !binary {
+			self.i = start;
+			return None;
+		}
+		let op = self.peek();
+		self.i += 1;
+		self.skip_blanks();
+		return op;
+	}
+
+	fn expression(&mut self) -> Result<Expr, AsmError> {
+		let mut value = self.term()?;
+		loop {
+			let start = self.i;
+			let op = self.operator();
+			if let Some('+') | Some('-') = op {
+				self.count_operator(start)?;
+			}
+			match op {
+				Some('+') => value = Expr::Add(Box::new(value), Box::new(self.term()?)),
+				Some('-') => value = Expr::Subtract(Box::new(value), Box::new(self.term()?)),
+				Some(_) => {
+					// * is handled by term
+					self.i = start;
+					return Ok(value);
+				},
+				None => return Ok(value),
+			}
+		}
+	}
+
+	fn term(&mut self) -> Result<Expr, AsmError> {
+		let mut value = self.factor()?;
+		loop {
+			let start = self.i;
+			match self.operator() {
+				Some('*') => {
+					self.count_operator(start)?;
+					value = Expr::Multiply(Box::new(value), Box::new(self.factor()?));
+				},
+				_ => {
+					self.i = start;
+					return Ok(value);
+				},
+			}
+		}
+	}
+
+	fn factor(&mut self) -> Result<Expr, AsmError> {
+		let start = self.i;
+		if let Some('(') | Some('-') | Some('+') = self.peek() {
+			self.count_operator(start)?;
+		}
+		match self.peek() {
+			Some('(') => {
+				self.i += 1;
+				self.skip_blanks();
+				let value = self.expression()?;
+				self.skip_blanks();
+				if self.peek() != Some(')') {
+					return Err(error(self.span(start, self.i), "expected a closing parenthesis"));
+				}
+				self.i += 1;
+				return Ok(value);
+			},
+			Some('-') => {
+				self.i += 1;
+				return Ok(Expr::Negate(Box::new(self.factor()?)));
+			},
+			Some('+') => {
+				self.i += 1;
+				return self.factor();
+			},
+			Some(c) if c.is_ascii_digit() => {
+				while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+					self.i += 1;
+				}
+				let text: String = self.chars[start .. self.i].iter().collect();
+				return number(&text, self.span(start, self.i)).map(Expr::Number);
+			},
+			Some(c) if is_name_start(c) => {
+				let name = self.name();
+				return Ok(Expr::Name(name, self.span(start, self.i)));
+			},
+			_ => {
+				let end = self.chars[start ..].iter().position(|c| c.is_whitespace()).map_or(self.chars.len(), |n| start + n);
+				let text: String = self.chars[start .. end].iter().collect();
+				return Err(error(self.span(start, end.max(start + 1)), &format!("could not get a parameter from '{}'", text)));
+			},
+		}
+	}
+}
+
+fn is_name_start(c: char) -> bool {
+	return c.is_ascii_alphabetic() || c == '_' || c == '$';
+}
+
+// decimal, negative numbers are stored as two's complement
+fn number(text: &str, span: Span) -> Result<storage, AsmError> {
+	return match text.parse::<i64>() {
+		Ok(value) if value >= i32::MIN as i64 && value <= storage::MAX as i64 => Ok(value as storage),
+		_ => Err(error(span, &format!("'{}' is not a number", text))),
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::Machine;
+
+	fn first_error(source: &str) -> AsmError {
+		return match assemble(source) {
+			Ok(_) => panic!("assembled"),
+			Err(mut errors) => errors.remove(0),
+		};
+	}
+
+	#[test]
+	fn deep_expressions_are_refused() {
+		let too_many = format!("at most {} operators", MAX_OPERATORS);
+		let parentheses = format!(".const DEEP {}1{}", "(".repeat(100_000), ")".repeat(100_000));
+		assert!(first_error(&parentheses).message.contains(&too_many));
+		let negations = format!(".text\nLoadImmmediateToBus {}1", "-".repeat(100_000));
+		assert!(first_error(&negations).message.contains(&too_many));
+		let sum = format!(".text\nLoadImmmediateToBus 1{}", "+1".repeat(100_000));
+		let error = first_error(&sum);
+		assert!(error.message.contains(&too_many));
+		assert_eq!((error.span.line, error.span.column), (2, 22 + 2 * MAX_OPERATORS));
+	}
+
+	#[test]
+	fn expressions_up_to_the_cap_assemble() {
+		let nested = format!("{}1{}", "(".repeat(MAX_OPERATORS), ")".repeat(MAX_OPERATORS));
+		let source = format!(".const ONE {}\n.text\nLoadImmmediateToBus ONE{}", nested, "*1".repeat(MAX_OPERATORS));
+		let mut machine = Machine::new(&source);
+		machine.run();
+		assert_eq!(machine.processor.bus, 1);
+	}
+
+	#[test]
+	fn variables_are_laid_out_after_the_jump() {
+		let assembly = assemble(".data\nvar n number 5\nvar t array 1 2\nvar s string \"hi\"\n.text\n@top\ngoto top").unwrap();
+		assert_eq!(assembly.image, vec![
+			LOAD_IMMEDIATE, 11, JUMP,
+			5,
+			1, 2, 0,
+			'h' as storage, 'i' as storage, 0,
+			LOAD_IMMEDIATE, 11, JUMP,
+			HALT,
+		]);
+		assert_eq!(assembly.words.len(), assembly.image.len());
+	}
+
+	#[test]
+	fn symbols_have_addresses_kinds_and_spans() {
+		let assembly = assemble(".data\nvar n number 5\nvar t array 1 2\nvar s string \"hi\"\n.text\n@top\ngoto top").unwrap();
+		let symbols: Vec<(&str, SymbolKind, location)> = assembly.symbols.iter()
+			.map(|symbol| (symbol.name.as_str(), symbol.kind, symbol.address))
+			.collect();
+		assert_eq!(symbols, vec![
+			("n", SymbolKind::Variable(VariableType::Unit), 4),
+			("t", SymbolKind::Variable(VariableType::Array), 5),
+			("s", SymbolKind::Variable(VariableType::Array), 8),
+			("top", SymbolKind::Label, 11),
+		]);
+		assert_eq!(assembly.symbols[1].span, Span { file: 0, line: 3, column: 5, length: 1 });
+		assert_eq!(assembly.symbols[3].span, Span { file: 0, line: 6, column: 1, length: 4 });
+	}
+
+	#[test]
+	fn mnemonics_expand_to_opcodes() {
+		let assembly = assemble(".data\nvar a number 1\nvar b number 2\n.text\n@lp\nbeq a b lp\naddi a b 3").unwrap();
+		assert_eq!(assembly.image[5 ..].to_vec(), vec![
+			LOAD, 4, PUSH_TO_ALU, LOAD, 5, COMPARE, COMPARE_EQUAL, BRANCH, 6,
+			LOAD, 5, PUSH_TO_ALU, LOAD_IMMEDIATE, 3, ADD, HI_TO_BUS, SAVE, 4,
+			HALT,
+		]);
+	}
+
+	#[test]
+	fn indexed_variables_use_offset_opcodes() {
+		let assembly = assemble(".data\nvar t array 7 8 9\nvar i number 1\n.text\naddi t[2] t[i] 0").unwrap();
+		assert_eq!(assembly.image, vec![
+			LOAD_IMMEDIATE, 9, JUMP,
+			7, 8, 9, 0,
+			1,
+			LOAD_VARIABLE_OFFSET, 4, 8, PUSH_TO_ALU, LOAD_IMMEDIATE, 0, ADD, HI_TO_BUS, SAVE_CONSTANT_OFFSET, 4, 2,
+			HALT,
+		]);
+	}
+
+	#[test]
+	fn errors_point_at_the_source() {
+		let error = first_error("goto top");
+		assert_eq!(error.message, ".text or .data must be first in program");
+		assert_eq!((error.span.line, error.span.column, error.span.length), (1, 1, 4));
+
+		let error = first_error(".text\nbogus 1");
+		assert_eq!(error.message, "invalid operation: bogus");
+		assert_eq!((error.span.line, error.span.column, error.span.length), (2, 1, 5));
+
+		let error = first_error(".data\nvar x number 1\n.text\naddi x x");
+		assert_eq!(error.message, "too few parameters provided, missing imm");
+		assert_eq!((error.span.line, error.span.column), (4, 1));
+
+		let error = first_error(".text\n  goto nowhere");
+		assert_eq!(error.message, "cannot find a constant, variable or label 'nowhere'");
+		assert_eq!((error.span.line, error.span.column, error.span.length), (2, 8, 7));
+
+		let error = first_error(".data\nvar x number 1\nvar x number 2");
+		assert_eq!(error.message, "already have a variable 'x'");
+		assert_eq!((error.span.line, error.span.column), (3, 5));
+	}
+
+	#[test]
+	fn every_error_is_reported() {
+		let errors = assemble(".text\n@a\ngoto a\nbogus").err().unwrap();
+		let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+		assert_eq!(lines, vec![2, 3, 4]);
+	}
+
+	// the code after the leading jump, without the final halt
+	fn code(source: &str) -> Vec<storage> {
+		let image = assemble(source).unwrap_or_else(|errors| panic!("{}", errors[0])).image;
+		return image[3 .. image.len() - 1].to_vec();
+	}
+
+	fn include_error(source: &str) -> AsmError {
+		let mut read_file = |name: &str| match name {
+			"self.dsla" => Ok(".include \"self.dsla\"".to_string()),
+			name if name.starts_with("deep") => {
+				let depth: usize = name["deep".len() .. name.len() - ".dsla".len()].parse().unwrap();
+				Ok(format!(".include \"deep{}.dsla\"", depth + 1))
+			},
+			_ => Err("no such file".to_string()),
+		};
+		return match assemble_with_includes("main.dsla", source, &mut read_file) {
+			Ok(_) => panic!("assembled"),
+			Err(mut errors) => errors.remove(0),
+		};
+	}
+
+	#[test]
+	fn constants_can_be_used_before_they_are_defined() {
+		assert_eq!(code(".const TWO ONE + ONE\n.const ONE 1\n.text\nLoadImmmediateToBus TWO * 3"), vec![LOAD_IMMEDIATE, 6]);
+		let error = first_error(".const X 1\n.const X 2");
+		assert_eq!(error.message, "already have a constant 'X' from line 1");
+		assert_eq!((error.span.line, error.span.column), (2, 8));
+		assert_eq!(first_error(".const 1X 1").message, "'1X' is not a valid constant name");
+		assert_eq!(first_error(".const X").message, "expected a value for 'X'");
+	}
+
+	#[test]
+	fn constant_cycles_are_refused() {
+		let error = first_error(".const A B\n.const B A\n.text\nLoadImmmediateToBus A");
+		assert_eq!(error.message, "constant 'A' is defined in terms of itself");
+		let error = first_error(".const SELF SELF + 1\n.text\nLoadImmmediateToBus SELF");
+		assert_eq!(error.message, "constant 'SELF' is defined in terms of itself");
+	}
+
+	#[test]
+	fn conditions_pick_a_branch() {
+		let source = |flag: u32| format!(".const FLAG {}\n.text\n.if FLAG\nLoadImmmediateToBus 5\n.else\nLoadImmmediateToBus 6\n.endif", flag);
+		assert_eq!(code(&source(1)), vec![LOAD_IMMEDIATE, 5]);
+		assert_eq!(code(&source(0)), vec![LOAD_IMMEDIATE, 6]);
+		assert_eq!(code(".text\n.if 0\n.if 1\nHalt\n.endif\n.endif\nLoadImmmediateToBus 1"), vec![LOAD_IMMEDIATE, 1]);
+	}
+
+	#[test]
+	fn condition_errors() {
+		let error = first_error(".text\n.if LATER\n.endif\n.const LATER 1");
+		assert_eq!(error.message, "'LATER' is not a constant defined before here");
+		assert_eq!((error.span.line, error.span.column), (2, 5));
+		assert_eq!(first_error(".text\n.else").message, ".else without .if");
+		assert_eq!(first_error(".text\n.endif").message, ".endif without .if");
+		let error = first_error(".text\n.if 1\n.else\n.else\n.endif");
+		assert_eq!((error.message.as_str(), error.span.line), ("the .if already has an .else", 4));
+		let error = first_error(".text\n.if 1");
+		assert_eq!((error.message.as_str(), error.span.line), (".if has no .endif", 2));
+	}
+
+	#[test]
+	fn macros_replace_their_parameters() {
+		let source = ".macro store value into\nLoadImmmediateToBus value\nSaveValueInBusToLocation into\n.endmacro\n.data\nvar x number 0\n.text\nstore 7 x\nstore 8 x";
+		assert_eq!(code(source), vec![0, LOAD_IMMEDIATE, 7, SAVE, 4, LOAD_IMMEDIATE, 8, SAVE, 4]);
+		let assembly = assemble(source).unwrap();
+		// the words of a macro have the span of the line that used it
+		assert_eq!(assembly.words[8].span.map(|span| span.line), Some(9));
+	}
+
+	#[test]
+	fn macro_errors() {
+		let error = first_error(".macro once x\nLoadImmmediateToBus x\n.endmacro\n.text\nonce 7 8");
+		assert_eq!((error.message.as_str(), error.span.line), ("macro 'once' takes 1 arguments but was given 2", 5));
+		assert_eq!(first_error(".macro m\n.endmacro\n.macro m\n.endmacro").message, "already have a macro 'm' from line 1");
+		assert_eq!(first_error(".macro goto\n.endmacro").message, "'goto' is already an operation");
+		assert_eq!(first_error(".macro m\n").message, "macro 'm' has no .endmacro");
+		assert_eq!(first_error(".endmacro").message, ".endmacro without .macro");
+		assert_eq!(first_error(".macro a\n.macro b\n.endmacro\n.endmacro").message, "macros can't be defined inside a macro");
+	}
+
+	#[test]
+	fn recursive_macros_stop_at_the_depth_limit() {
+		let error = first_error(".macro forever\nforever\n.endmacro\n.text\nforever");
+		assert!(error.message.starts_with("macros are nested too deep"));
+	}
+
+	#[test]
+	fn includes_are_relative_to_the_file() {
+		let mut read_file = |name: &str| match name {
+			"lib/a.dsla" => Ok(".const A 4\n.include \"b.dsla\"".to_string()),
+			"lib/b.dsla" => Ok(".const B A + 1".to_string()),
+			_ => Err("no such file".to_string()),
+		};
+		let assembly = assemble_with_includes("main.dsla", ".text\n.include \"lib/a.dsla\"\nLoadImmmediateToBus B", &mut read_file).unwrap();
+		assert_eq!(assembly.image[3 .. 5].to_vec(), vec![LOAD_IMMEDIATE, 5]);
+		assert_eq!(assembly.files, vec!["main.dsla", "lib/a.dsla", "lib/b.dsla"]);
+	}
+
+	#[test]
+	fn include_errors() {
+		let error = include_error(".text\n.include \"main.dsla\"");
+		assert_eq!(error.message, "'main.dsla' includes itself");
+		assert_eq!((error.file.as_str(), error.span.line, error.span.column), ("main.dsla", 2, 10));
+		let error = include_error(".text\n.include \"self.dsla\"");
+		assert_eq!((error.message.as_str(), error.file.as_str()), ("'self.dsla' includes itself", "self.dsla"));
+		let error = include_error(".text\n.include \"deep1.dsla\"");
+		assert_eq!(error.message, "includes are nested too deep");
+		assert_eq!(error.span.file, MAX_DEPTH - 1);
+		assert_eq!(include_error(".text\n.include \"missing.dsla\"").message, "can't include 'missing.dsla': no such file");
+		assert_eq!(include_error(".text\n.include missing.dsla").message, "expected a file name in double quotes");
+	}
+}
@@ -0,0 +1,335 @@
+// Audio device.
+//
+// Three tone channels, square, triangle and noise, and a ring buffer of raw
+// PCM samples the program fills. The host asks for samples with
+// r_SynthesizeAudio and plays them through WebAudio.
+//
+// Synthesis only uses integer math and the noise is a linear feedback
+// shift register, so the same registers always give the same samples.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use super::{storage, location};
+use mmio::{Device, MMIO_BASE};
+
+pub const AUDIO_BASE: location = MMIO_BASE + 0x5000;
+
+pub const CHANNEL_SQUARE: usize = 0;
+pub const CHANNEL_TRIANGLE: usize = 1;
+pub const CHANNEL_NOISE: usize = 2;
+const CHANNEL_COUNT: usize = 3;
+
+// every channel has CHANNEL_WORDS registers, channel n starts at n * CHANNEL_WORDS
+pub const CHANNEL_WORDS: storage = 4;
+// in Hz, for the noise channel this is how often the noise changes
+pub const AUDIO_FREQUENCY: storage = 0;
+// 0 to 255, 0 silences the channel
+pub const AUDIO_VOLUME: storage = 1;
+// square channel only, how much of each period is high, 0 to 255 of 256
+pub const AUDIO_DUTY: storage = 2;
+
+// registers after the channels
+// writing queues a signed 16 bit sample
+pub const AUDIO_PCM_WRITE: storage = 12;
+// read only, number of samples that still fit in the ring buffer
+pub const AUDIO_PCM_FREE: storage = 13;
+// 0 to 255
+pub const AUDIO_PCM_VOLUME: storage = 14;
+
+pub const AUDIO_SIZE: storage = 15;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+pub const PCM_CAPACITY: usize = 8192;
+// most samples r_SynthesizeAudio makes at once
+pub const MAX_SYNTHESIZED: usize = 8192;
+
+const MAX_VOLUME: i32 = 255;
+// each of the four sources is at most this loud, so the mix never clips
+const SOURCE_AMPLITUDE: i32 = 8191;
+
+// the 15 bit noise register must never be all zeroes
+const NOISE_SEED: u16 = 1;
+
+#[derive(Clone, Copy)]
+struct Channel {
+	frequency: storage,
+	volume: storage,
+	duty: storage,
+	phase: u32,
+}
+
+impl Channel {
+	fn new() -> Channel {
+		Channel {
+			frequency: 0,
+			volume: 0,
+			duty: 128,
+			phase: 0,
+		}
+	}
+
+	fn is_silent(&self) -> bool {
+		return self.frequency == 0 || self.volume == 0;
+	}
+
+	// moves the phase on by one sample, returns true if it wrapped around
+	fn advance(&mut self, sample_rate: u32) -> bool {
+		let step = ((self.frequency as u64) << 32) / sample_rate as u64;
+		let (phase, wrapped) = self.phase.overflowing_add(step.min(u32::MAX as u64) as u32);
+		self.phase = phase;
+		return wrapped;
+	}
+
+	fn amplitude(&self) -> i32 {
+		return SOURCE_AMPLITUDE * self.volume.min(MAX_VOLUME as storage) as i32 / MAX_VOLUME;
+	}
+}
+
+pub struct Audio {
+	channels: [Channel; CHANNEL_COUNT],
+	noise: u16,
+	pcm: VecDeque<i16>,
+	pcm_volume: storage,
+	sample_rate: u32,
+	// the last samples made by r_SynthesizeAudio, for the host to read
+	output: Vec<i16>,
+}
+
+impl Audio {
+	pub fn new() -> Audio {
+		let channels = [Channel::new(); CHANNEL_COUNT];
+		let noise = NOISE_SEED;
+		let pcm = VecDeque::with_capacity(PCM_CAPACITY);
+		let pcm_volume = MAX_VOLUME as storage;
+		let sample_rate = DEFAULT_SAMPLE_RATE;
+		let output = Vec::new();
+		Audio {
+			channels,
+			noise,
+			pcm,
+			pcm_volume,
+			sample_rate,
+			output,
+		}
+	}
+
+	pub fn sample_rate(&self) -> u32 {
+		return self.sample_rate;
+	}
+
+	pub fn set_sample_rate(&mut self, rate: u32) {
+		if rate > 0 {
+			self.sample_rate = rate;
+		}
+	}
+
+	// returns false if the ring buffer is full
+	pub fn queue_sample(&mut self, sample: i16) -> bool {
+		if self.pcm.len() == PCM_CAPACITY {
+			return false;
+		}
+		self.pcm.push_back(sample);
+		return true;
+	}
+
+	pub fn pcm_free(&self) -> usize {
+		return PCM_CAPACITY - self.pcm.len();
+	}
+
+	pub fn output(&self) -> &[i16] {
+		return &self.output;
+	}
+
+	// replaces the output with the next count samples
+	pub fn synthesize(&mut self, count: usize) -> &[i16] {
+		let count = count.min(MAX_SYNTHESIZED);
+		self.output.clear();
+		for _ in 0 .. count {
+			let sample = self.next_sample();
+			self.output.push(sample);
+		}
+		return &self.output;
+	}
+
+	fn next_sample(&mut self) -> i16 {
+		let rate = self.sample_rate;
+		let mut mix = 0;
+
+		let square = &mut self.channels[CHANNEL_SQUARE];
+		if !square.is_silent() {
+			let high = (square.phase >> 24) < square.duty.min(255);
+			mix += if high { square.amplitude() } else { -square.amplitude() };
+			square.advance(rate);
+		}
+
+		let triangle = &mut self.channels[CHANNEL_TRIANGLE];
+		if !triangle.is_silent() {
+			// the top 16 bits of the phase go up and down once per period
+			let t = (triangle.phase >> 16) as i32;
+			let level = if t < 32768 { t * 2 - 32768 } else { (65535 - t) * 2 - 32767 };
+			mix += level * triangle.amplitude() / 32768;
+			triangle.advance(rate);
+		}
+
+		let noise = &mut self.channels[CHANNEL_NOISE];
+		if !noise.is_silent() {
+			mix += if self.noise & 1 == 0 { noise.amplitude() } else { -noise.amplitude() };
+			if noise.advance(rate) {
+				// 15 bit register, taps on bits 0 and 1
+				let bit = (self.noise ^ (self.noise >> 1)) & 1;
+				self.noise = (self.noise >> 1) | (bit << 14);
+			}
+		}
+
+		if let Some(sample) = self.pcm.pop_front() {
+			let volume = self.pcm_volume.min(MAX_VOLUME as storage) as i32;
+			mix += sample as i32 * volume / MAX_VOLUME / 4;
+		}
+
+		return mix.max(i16::MIN as i32).min(i16::MAX as i32) as i16;
+	}
+
+	fn channel_value(&self, offset: storage) -> Option<storage> {
+		let channel = self.channels.get((offset / CHANNEL_WORDS) as usize)?;
+		return match offset % CHANNEL_WORDS {
+			AUDIO_FREQUENCY => Some(channel.frequency),
+			AUDIO_VOLUME => Some(channel.volume),
+			AUDIO_DUTY => Some(channel.duty),
+			_ => None,
+		};
+	}
+
+	fn channel_register(&mut self, offset: storage) -> Option<&mut storage> {
+		let channel = (offset / CHANNEL_WORDS) as usize;
+		if channel >= CHANNEL_COUNT {
+			return None;
+		}
+		let channel = &mut self.channels[channel];
+		return match offset % CHANNEL_WORDS {
+			AUDIO_FREQUENCY => Some(&mut channel.frequency),
+			AUDIO_VOLUME => Some(&mut channel.volume),
+			AUDIO_DUTY => Some(&mut channel.duty),
+			_ => None,
+		};
+	}
+}
+
+impl Device for Audio {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			AUDIO_PCM_FREE => {
+				return self.pcm_free() as storage;
+			},
+			AUDIO_PCM_VOLUME => {
+				return self.pcm_volume;
+			},
+			_ => {
+				return self.channel_value(offset).unwrap_or(0);
+			},
+		}
+	}
+
+	fn write(&mut self, offset: storage, value: storage) {
+		match offset {
+			AUDIO_PCM_WRITE => {
+				self.queue_sample(value as i16);
+			},
+			AUDIO_PCM_VOLUME => {
+				self.pcm_volume = value;
+			},
+			_ => {
+				if let Some(register) = self.channel_register(offset) {
+					*register = value;
+				}
+			},
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FULL: i16 = SOURCE_AMPLITUDE as i16;
+
+	// one channel at full volume, frequency samples per second at rate
+	fn audio(channel: usize, frequency: storage, rate: u32) -> Audio {
+		let mut audio = Audio::new();
+		audio.set_sample_rate(rate);
+		let base = channel as storage * CHANNEL_WORDS;
+		audio.write(base + AUDIO_FREQUENCY, frequency);
+		audio.write(base + AUDIO_VOLUME, MAX_VOLUME as storage);
+		return audio;
+	}
+
+	#[test]
+	fn silence_without_channels() {
+		let mut audio = Audio::new();
+		assert_eq!(audio.synthesize(4), &[0, 0, 0, 0]);
+		assert_eq!(audio.synthesize(MAX_SYNTHESIZED + 1).len(), MAX_SYNTHESIZED);
+	}
+
+	#[test]
+	fn square_follows_the_duty_cycle() {
+		let mut audio = audio(CHANNEL_SQUARE, 1, 8);
+		assert_eq!(audio.synthesize(8), &[FULL, FULL, FULL, FULL, -FULL, -FULL, -FULL, -FULL]);
+		audio.write(AUDIO_DUTY, 64);
+		assert_eq!(audio.synthesize(8), &[FULL, FULL, -FULL, -FULL, -FULL, -FULL, -FULL, -FULL]);
+		// half volume
+		audio.write(AUDIO_VOLUME, 128);
+		assert_eq!(audio.synthesize(1), &[4111]);
+	}
+
+	#[test]
+	fn triangle_goes_up_and_down() {
+		let mut audio = audio(CHANNEL_TRIANGLE, 1, 4);
+		assert_eq!(audio.synthesize(8), &[-FULL, 0, FULL - 1, 0, -FULL, 0, FULL - 1, 0]);
+	}
+
+	#[test]
+	fn noise_is_the_same_every_time() {
+		// the noise changes every second sample
+		let mut audio = audio(CHANNEL_NOISE, 1, 2);
+		let samples = audio.synthesize(32).to_vec();
+		assert_eq!(samples[.. 2], [-FULL, -FULL]);
+		assert_eq!(samples[2 .. 30], [FULL; 28]);
+		assert_eq!(samples[30 .. 32], [-FULL, -FULL]);
+
+		let mut again = Audio::new();
+		again.set_sample_rate(2);
+		again.write(CHANNEL_NOISE as storage * CHANNEL_WORDS + AUDIO_FREQUENCY, 1);
+		again.write(CHANNEL_NOISE as storage * CHANNEL_WORDS + AUDIO_VOLUME, MAX_VOLUME as storage);
+		assert_eq!(again.synthesize(32), &samples[..]);
+	}
+
+	#[test]
+	fn pcm_is_mixed_with_the_channels() {
+		let mut audio = audio(CHANNEL_SQUARE, 1, 8);
+		for &sample in [4000i16, -4000, i16::MAX].iter() {
+			audio.write(AUDIO_PCM_WRITE, sample as storage);
+		}
+		assert_eq!(audio.peek(AUDIO_PCM_FREE), PCM_CAPACITY as storage - 3);
+		assert_eq!(audio.synthesize(4), &[FULL + 1000, FULL - 1000, FULL + 8191, FULL]);
+
+		audio.write(AUDIO_PCM_VOLUME, 128);
+		audio.write(AUDIO_VOLUME, 0);
+		audio.write(AUDIO_PCM_WRITE, 4000);
+		assert_eq!(audio.synthesize(2), &[501, 0]);
+	}
+
+	#[test]
+	fn a_full_ring_buffer_refuses_samples() {
+		let mut audio = Audio::new();
+		for _ in 0 .. PCM_CAPACITY {
+			assert!(audio.queue_sample(1));
+		}
+		assert!(!audio.queue_sample(1));
+		assert_eq!(audio.peek(AUDIO_PCM_FREE), 0);
+		audio.synthesize(1);
+		assert_eq!(audio.pcm_free(), 1);
+	}
+}
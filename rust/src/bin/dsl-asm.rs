@@ -0,0 +1,156 @@
+// Assembles .dsla files and links them with objects.
+//
+// With an output ending in .dslo the one source is assembled into an object
+// file, for a library that programs link with. Otherwise the sources and
+// objects are linked into a program, the first one is where it starts, and
+// written as a numeric .dsl listing or a binary image of little endian 32
+// bit words. --stdlib links the standard library after them. Objects keep
+// the debug info of their source, --debug-info writes the debug info of a
+// linked program, for dsl-run --debug-info and the browser.
+
+extern crate dsl_wasm;
+
+use std::env;
+use std::fs;
+use std::process;
+use dsl_wasm::native::{read_object, link_objects};
+use dsl_wasm::assembler::{Symbol, SymbolKind, VariableType};
+use dsl_wasm::stdlib;
+
+const USAGE: &str = "usage: dsl-asm [options] <source or object>... -o <output>
+
+options:
+  -o <file>    the output, .dslo for an object, .dsl for a listing and
+               anything else for a binary image
+  --stdlib     link with the standard library
+  --debug-info <file>
+               write the source map of the program, for dsl-run
+  --symbols    print the labels and variables with their addresses
+
+sources end in .dsla, anything else is read as an object";
+
+struct Options {
+	inputs: Vec<String>,
+	output: String,
+	stdlib: bool,
+	debug_info: Option<String>,
+	symbols: bool,
+}
+
+fn main() {
+	let options = match parse_options(env::args().skip(1).collect()) {
+		Ok(options) => options,
+		Err(message) => {
+			eprintln!("dsl-asm: {}\n\n{}", message, USAGE);
+			process::exit(2);
+		},
+	};
+	if let Err(message) = run(&options) {
+		for line in message.lines() {
+			eprintln!("dsl-asm: {}", line);
+		}
+		process::exit(1);
+	}
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+	let mut inputs = Vec::new();
+	let mut output = None;
+	let mut stdlib = false;
+	let mut debug_info = None;
+	let mut symbols = false;
+
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-o" => {
+				output = Some(args.next().ok_or(format!("{} needs a file", arg))?);
+			},
+			"--stdlib" => {
+				stdlib = true;
+			},
+			"--debug-info" => {
+				debug_info = Some(args.next().ok_or(format!("{} needs a file", arg))?);
+			},
+			"--symbols" => {
+				symbols = true;
+			},
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				process::exit(0);
+			},
+			_ if arg.starts_with('-') => {
+				return Err(format!("unknown option {}", arg));
+			},
+			_ => {
+				inputs.push(arg);
+			},
+		}
+	}
+
+	if inputs.is_empty() {
+		return Err("nothing to assemble".to_string());
+	}
+	let output = output.ok_or("no output given".to_string())?;
+	if output.ends_with(".dslo") && (inputs.len() != 1 || !inputs[0].ends_with(".dsla")) {
+		return Err("an object is made from one .dsla source".to_string());
+	}
+	if output.ends_with(".dslo") && stdlib {
+		return Err("objects are linked with the standard library, not made with it".to_string());
+	}
+	if output.ends_with(".dslo") && debug_info.is_some() {
+		return Err("objects have their debug info in them".to_string());
+	}
+	return Ok(Options {
+		inputs,
+		output,
+		stdlib,
+		debug_info,
+		symbols,
+	});
+}
+
+fn run(options: &Options) -> Result<(), String> {
+	let mut objects = Vec::new();
+	for input in options.inputs.iter() {
+		objects.push(read_object(input)?);
+	}
+	if options.stdlib {
+		objects.push(stdlib::object());
+	}
+
+	let (bytes, symbols) = if options.output.ends_with(".dslo") {
+		let object = objects.remove(0);
+		(object.to_bytes(), object.symbols)
+	}
+	else {
+		let assembly = link_objects(&objects)?;
+		let bytes = if options.output.ends_with(".dsl") {
+			assembly.to_listing().into_bytes()
+		}
+		else {
+			assembly.image.iter().flat_map(|word| word.to_le_bytes()).collect()
+		};
+		if let Some(ref path) = options.debug_info {
+			fs::write(path, assembly.debug_info().to_bytes()).map_err(|error| format!("{}: {}", path, error))?;
+		}
+		(bytes, assembly.symbols)
+	};
+
+	if options.symbols {
+		print_symbols(&symbols);
+	}
+	return fs::write(&options.output, bytes).map_err(|error| format!("{}: {}", options.output, error));
+}
+
+// addresses of objects are from the start of the object
+fn print_symbols(symbols: &[Symbol]) {
+	for symbol in symbols.iter() {
+		let kind = match symbol.kind {
+			SymbolKind::Label => "label",
+			SymbolKind::Variable(VariableType::Unit) => "unit",
+			SymbolKind::Variable(VariableType::Array) => "array",
+		};
+		println!("{:>8} {:<6} {}", symbol.address, kind, symbol.name);
+	}
+}
@@ -0,0 +1,317 @@
+// Runs a DSL program without the browser, for CI and grading scripts.
+//
+// The program is either a numeric .dsl listing like dsl/hello.dsl, DSL
+// assembly in a .dsla file, an object file from dsl-asm or a binary image of
+// little endian 32 bit words. Assembly and objects can be linked with more
+// objects with --link, and with the standard library with --stdlib. Alert
+// and the terminal print to stdout and ReadLine reads from stdin.
+//
+// The exit status is the program's exit code if it halted with the halt
+// opcode and the code is at most MAX_HALT_STATUS, otherwise 128 plus the
+// exit reason (see exit.rs), which is reported with the file, line and
+// label it stopped at if the program has debug info. Unix keeps only 8 bits
+// of a status, so larger exit codes are printed and the status is
+// LARGE_HALT_STATUS, they can't pass for success or for a fault.
+
+extern crate dsl_wasm;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use dsl_wasm::native::{Runner, ExitInfo, ExitReason, parse_image, read_object, assemble_file, read_object_file, link_objects, read_debug_info};
+use dsl_wasm::listing::Listing;
+use dsl_wasm::stdlib;
+
+const USAGE: &str = "usage: dsl-run [options] <program>
+
+options:
+  --steps <n>                   halt after n instructions
+  --seed <n>                    seed the random numbers
+  --listing                     read the program as a .dsl listing
+  --asm                         read the program as .dsla assembly
+  --object                      read the program as an object file
+  --link <file>                 link the program with a .dsla file or an object, can be repeated
+  --stdlib                      link the program with the standard library
+  --binary                      read the program as a binary image
+  --debug-info <file>           the debug info of a binary image, from dsl-asm
+  --dump-registers              print the registers when the program stops
+  --dump-memory <start:length>  print memory when the program stops, can be repeated
+
+programs ending in .dsl are read as listings, .dsla as assembly, .dslo as
+objects and anything else as binary images";
+
+// the statuses above are 128 plus an exit reason
+const MAX_HALT_STATUS: u32 = 127;
+// a program that halted with a code above MAX_HALT_STATUS
+const LARGE_HALT_STATUS: i32 = 255;
+
+enum Format {
+	Listing,
+	Assembly,
+	Object,
+	Binary,
+}
+
+struct Options {
+	path: String,
+	format: Option<Format>,
+	links: Vec<String>,
+	stdlib: bool,
+	debug_info: Option<String>,
+	steps: u64,
+	seed: Option<u32>,
+	dump_registers: bool,
+	dump_memory: Vec<(u32, u32)>,
+}
+
+fn main() {
+	let options = match parse_options(env::args().skip(1).collect()) {
+		Ok(options) => options,
+		Err(message) => {
+			eprintln!("dsl-run: {}\n\n{}", message, USAGE);
+			process::exit(2);
+		},
+	};
+
+	let mut runner = Runner::new();
+	if let Err(message) = load(&mut runner, &options) {
+		for line in message.lines() {
+			eprintln!("dsl-run: {}", line);
+		}
+		process::exit(1);
+	}
+	runner.set_step_limit(options.steps);
+	if let Some(seed) = options.seed {
+		runner.set_seed(seed);
+	}
+
+	let stdin = io::stdin();
+	let stdout = io::stdout();
+	let exit = runner.run(&mut stdin.lock(), &mut stdout.lock())
+		.and_then(|exit| dump(&mut runner, &options).map(|_| exit));
+	match exit {
+		Ok(exit) => {
+			process::exit(exit_status(&runner, &exit));
+		},
+		Err(error) => {
+			eprintln!("dsl-run: {}", error);
+			process::exit(1);
+		},
+	}
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+	let mut path = None;
+	let mut format = None;
+	let mut links = Vec::new();
+	let mut stdlib = false;
+	let mut debug_info = None;
+	let mut steps = 0;
+	let mut seed = None;
+	let mut dump_registers = false;
+	let mut dump_memory = Vec::new();
+
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--steps" => {
+				steps = parse_number(args.next(), &arg)? as u64;
+			},
+			"--seed" => {
+				seed = Some(parse_number(args.next(), &arg)?);
+			},
+			"--listing" => {
+				format = Some(Format::Listing);
+			},
+			"--asm" => {
+				format = Some(Format::Assembly);
+			},
+			"--object" => {
+				format = Some(Format::Object);
+			},
+			"--link" => {
+				links.push(args.next().ok_or(format!("{} needs a file", arg))?);
+			},
+			"--stdlib" => {
+				stdlib = true;
+			},
+			"--binary" => {
+				format = Some(Format::Binary);
+			},
+			"--debug-info" => {
+				debug_info = Some(args.next().ok_or(format!("{} needs a file", arg))?);
+			},
+			"--dump-registers" => {
+				dump_registers = true;
+			},
+			"--dump-memory" => {
+				let range = args.next().ok_or(format!("{} needs a start:length range", arg))?;
+				let mut parts = range.splitn(2, ':');
+				let start = parse_number(parts.next().map(String::from), &arg)?;
+				let length = parse_number(parts.next().map(String::from), &arg)?;
+				dump_memory.push((start, length));
+			},
+			"-h" | "--help" => {
+				println!("{}", USAGE);
+				process::exit(0);
+			},
+			_ if arg.starts_with('-') => {
+				return Err(format!("unknown option {}", arg));
+			},
+			_ => {
+				if path.is_some() {
+					return Err("only one program can be run".to_string());
+				}
+				path = Some(arg);
+			},
+		}
+	}
+
+	let path = path.ok_or("no program given".to_string())?;
+	return Ok(Options {
+		path,
+		format,
+		links,
+		stdlib,
+		debug_info,
+		steps,
+		seed,
+		dump_registers,
+		dump_memory,
+	});
+}
+
+fn parse_number(arg: Option<String>, option: &str) -> Result<u32, String> {
+	let arg = arg.ok_or(format!("{} needs a number", option))?;
+	return arg.parse::<u32>().map_err(|_| format!("{} needs a number, not '{}'", option, arg));
+}
+
+fn load(runner: &mut Runner, options: &Options) -> Result<(), String> {
+	let format = match options.format {
+		Some(Format::Listing) => Format::Listing,
+		Some(Format::Assembly) => Format::Assembly,
+		Some(Format::Object) => Format::Object,
+		Some(Format::Binary) => Format::Binary,
+		None if options.path.ends_with(".dsl") => Format::Listing,
+		None if options.path.ends_with(".dsla") => Format::Assembly,
+		None if options.path.ends_with(".dslo") => Format::Object,
+		None => Format::Binary,
+	};
+	match format {
+		Format::Assembly | Format::Object => {
+			if options.debug_info.is_some() {
+				return Err("assembly and objects have their own debug info".to_string());
+			}
+			let program = match format {
+				Format::Assembly => assemble_file(&options.path)?,
+				_ => read_object_file(&options.path)?,
+			};
+			let mut objects = vec![program];
+			for path in options.links.iter() {
+				objects.push(read_object(path)?);
+			}
+			if options.stdlib {
+				objects.push(stdlib::object());
+			}
+			runner.load_assembly(link_objects(&objects)?);
+			return Ok(());
+		},
+		_ if !options.links.is_empty() || options.stdlib => {
+			return Err("only assembly and objects can be linked".to_string());
+		},
+		Format::Listing if options.debug_info.is_some() => {
+			return Err("a listing has its own debug info".to_string());
+		},
+		_ => {},
+	}
+
+	let bytes = fs::read(&options.path).map_err(|error| format!("{}: {}", options.path, error))?;
+	match format {
+		Format::Listing => {
+			let text = String::from_utf8(bytes).map_err(|_| format!("{}: the listing is not utf8", options.path))?;
+			let listing = Listing::parse(&text).map_err(|error| format!("{}: {}", options.path, error))?;
+			runner.load_listing(listing);
+		},
+		Format::Binary => {
+			runner.load(&parse_image(&bytes).map_err(|error| format!("{}: {}", options.path, error))?);
+			if let Some(ref path) = options.debug_info {
+				runner.load_debug_info(read_debug_info(path)?);
+			}
+		},
+		Format::Assembly | Format::Object => {},
+	}
+	return Ok(());
+}
+
+// dumps go to stderr so they don't mix with the program's output
+fn dump(runner: &mut Runner, options: &Options) -> io::Result<()> {
+	let stderr = io::stderr();
+	let mut stderr = stderr.lock();
+	if options.dump_registers {
+		runner.write_registers(&mut stderr)?;
+	}
+	for &(start, length) in options.dump_memory.iter() {
+		runner.write_memory(&mut stderr, start, length)?;
+	}
+	return stderr.flush();
+}
+
+fn exit_status(runner: &Runner, exit: &ExitInfo) -> i32 {
+	let reason = match exit.reason {
+		ExitReason::Halt if exit.code <= MAX_HALT_STATUS => {
+			return exit.code as i32;
+		},
+		ExitReason::Halt => {
+			eprintln!("dsl-run: halted with exit code {}", exit.code);
+			return LARGE_HALT_STATUS;
+		},
+		ExitReason::None => "stopped",
+		ExitReason::InvalidOpcode => "invalid opcode",
+		ExitReason::Fault => "fault",
+		ExitReason::Budget => "ran out of steps",
+	};
+	match runner.debug_info().describe(exit.address) {
+		Some(source) => eprintln!("dsl-run: {} at {}, {} (code {})", reason, exit.address, source, exit.code),
+		None => eprintln!("dsl-run: {} at {} (code {})", reason, exit.address, exit.code),
+	}
+	return 128 + exit.reason as i32;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn exit(reason: ExitReason, code: u32) -> ExitInfo {
+		return ExitInfo {
+			reason,
+			code,
+			address: 1,
+		};
+	}
+
+	#[test]
+	fn halt_codes_are_the_status() {
+		let runner = Runner::new();
+		assert_eq!(exit_status(&runner, &exit(ExitReason::Halt, 0)), 0);
+		assert_eq!(exit_status(&runner, &exit(ExitReason::Halt, 3)), 3);
+		assert_eq!(exit_status(&runner, &exit(ExitReason::Halt, MAX_HALT_STATUS)), 127);
+	}
+
+	#[test]
+	fn large_halt_codes_are_not_success_or_a_stop() {
+		let runner = Runner::new();
+		for &code in [128, 131, 256, 0xFFFF_FFFF].iter() {
+			assert_eq!(exit_status(&runner, &exit(ExitReason::Halt, code)), LARGE_HALT_STATUS);
+		}
+	}
+
+	#[test]
+	fn stops_are_128_plus_the_reason() {
+		let runner = Runner::new();
+		assert_eq!(exit_status(&runner, &exit(ExitReason::InvalidOpcode, 99)), 130);
+		assert_eq!(exit_status(&runner, &exit(ExitReason::Fault, 1)), 131);
+		assert_eq!(exit_status(&runner, &exit(ExitReason::Budget, 1000)), 132);
+		assert_eq!(exit_status(&runner, &exit(ExitReason::None, 0)), 128);
+	}
+}
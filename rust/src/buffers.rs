@@ -0,0 +1,261 @@
+// Registry of the buffers a program shares with the host.
+//
+// A buffer is a range of VM memory with a type that tells the host what to
+// do with it (see js/src/utils/wasmWorker/syscalls.ts for the types).
+// Every buffer has an explicit id, handed out by CreateBuffer, and its
+// head, length and type can each be set once. Freeing the allocation a
+// buffer is in deletes the buffer.
+
+use super::{storage, location};
+use syscalls::SyscallError;
+
+pub const BUFFER_INPUT_KEY: storage = 1;
+pub const BUFFER_INPUT_TERMINAL: storage = 2;
+pub const BUFFER_OUTPUT_PALETTE: storage = 3;
+pub const BUFFER_OUTPUT_SCREEN: storage = 4;
+pub const BUFFER_OUTPUT_SCREEN_SIZE: storage = 5;
+pub const BUFFER_NONE: storage = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Buffer {
+	pub id: storage,
+	pub head: location,
+	pub length: storage,
+	pub kind: storage,
+
+	head_set: bool,
+	length_set: bool,
+	kind_set: bool,
+}
+
+impl Buffer {
+	fn new(id: storage) -> Buffer {
+		Buffer {
+			id,
+			head: 0,
+			length: 0,
+			kind: BUFFER_NONE,
+			head_set: false,
+			length_set: false,
+			kind_set: false,
+		}
+	}
+
+	// the host ignores buffers until all three properties are set
+	pub fn is_initialized(&self) -> bool {
+		return self.head_set && self.length_set && self.kind_set;
+	}
+
+	pub fn contains(&self, address: location) -> bool {
+		return self.is_initialized() && address >= self.head && address - self.head < self.length;
+	}
+
+	// a buffer without a length, or an empty one, only covers its head
+	fn overlaps(&self, start: location, length: storage) -> bool {
+		if !self.head_set {
+			return false;
+		}
+		let covered = if self.length_set { self.length.max(1) } else { 1 };
+		let end = start as u64 + length as u64;
+		return (self.head as u64) < end && (start as u64) < self.head as u64 + covered as u64;
+	}
+}
+
+pub struct BufferRegistry {
+	buffers: Vec<Buffer>,
+	last_id: storage,
+}
+
+impl BufferRegistry {
+	pub fn new() -> BufferRegistry {
+		let buffers = Vec::new();
+		let last_id = 0;
+		BufferRegistry {
+			buffers,
+			last_id,
+		}
+	}
+
+	// ids start at 1 and are never reused, so 0 is never a valid id
+	pub fn create(&mut self) -> storage {
+		self.last_id += 1;
+		let id = self.last_id;
+		self.buffers.push(Buffer::new(id));
+		return id;
+	}
+
+	pub fn delete(&mut self, id: storage) -> Result<(), SyscallError> {
+		let index = self.index_of(id)?;
+		self.buffers.remove(index);
+		return Ok(());
+	}
+
+	// deletes the buffers over the range, true if there were any
+	pub fn release(&mut self, start: location, length: storage) -> bool {
+		let count = self.buffers.len();
+		self.buffers.retain(|b| !b.overlaps(start, length));
+		return self.buffers.len() != count;
+	}
+
+	// mapped is the number of addresses backed by memory blocks
+	pub fn set_head(&mut self, id: storage, head: location, mapped: usize) -> Result<(), SyscallError> {
+		let index = self.index_of(id)?;
+		let mut buffer = self.buffers[index];
+		if buffer.head_set {
+			return Err(SyscallError::BufferAlreadySet);
+		}
+		buffer.head = head;
+		buffer.head_set = true;
+		check_range(&buffer, mapped)?;
+		self.buffers[index] = buffer;
+		return Ok(());
+	}
+
+	pub fn set_length(&mut self, id: storage, length: storage, mapped: usize) -> Result<(), SyscallError> {
+		let index = self.index_of(id)?;
+		let mut buffer = self.buffers[index];
+		if buffer.length_set {
+			return Err(SyscallError::BufferAlreadySet);
+		}
+		buffer.length = length;
+		buffer.length_set = true;
+		check_range(&buffer, mapped)?;
+		self.buffers[index] = buffer;
+		return Ok(());
+	}
+
+	pub fn set_type(&mut self, id: storage, kind: storage) -> Result<(), SyscallError> {
+		let index = self.index_of(id)?;
+		if self.buffers[index].kind_set {
+			return Err(SyscallError::BufferAlreadySet);
+		}
+		match kind {
+			BUFFER_INPUT_KEY | BUFFER_INPUT_TERMINAL | BUFFER_OUTPUT_PALETTE |
+			BUFFER_OUTPUT_SCREEN | BUFFER_OUTPUT_SCREEN_SIZE => {},
+			_ => return Err(SyscallError::InvalidBufferType),
+		}
+		self.buffers[index].kind = kind;
+		self.buffers[index].kind_set = true;
+		return Ok(());
+	}
+
+	pub fn get(&self, id: storage) -> Option<&Buffer> {
+		return self.buffers.iter().find(|b| b.id == id);
+	}
+
+	// the oldest initialized buffer of the type
+	pub fn of_type(&self, kind: storage) -> Option<&Buffer> {
+		return self.buffers.iter().find(|b| b.is_initialized() && b.kind == kind);
+	}
+
+	pub fn len(&self) -> usize {
+		return self.buffers.len();
+	}
+
+	pub fn at(&self, index: usize) -> Option<&Buffer> {
+		return self.buffers.get(index);
+	}
+
+	fn index_of(&self, id: storage) -> Result<usize, SyscallError> {
+		return self.buffers.iter()
+			.position(|b| b.id == id)
+			.ok_or(SyscallError::UnknownBuffer);
+	}
+}
+
+// once both the head and the length are known the whole range has to be
+// backed by memory, otherwise the host would read past the end of it
+fn check_range(buffer: &Buffer, mapped: usize) -> Result<(), SyscallError> {
+	if buffer.head_set && buffer.length_set && buffer.head as usize + buffer.length as usize > mapped {
+		return Err(SyscallError::InvalidRange);
+	}
+	return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::Machine;
+
+	const MAPPED: usize = 100;
+
+	// a screen buffer over 10 .. 20
+	fn registry() -> (BufferRegistry, storage) {
+		let mut buffers = BufferRegistry::new();
+		let id = buffers.create();
+		buffers.set_head(id, 10, MAPPED).unwrap();
+		buffers.set_length(id, 10, MAPPED).unwrap();
+		buffers.set_type(id, BUFFER_OUTPUT_SCREEN).unwrap();
+		return (buffers, id);
+	}
+
+	#[test]
+	fn buffers_are_set_once() {
+		let (mut buffers, id) = registry();
+		let buffer = *buffers.get(id).unwrap();
+		assert!(buffer.is_initialized());
+		assert_eq!((buffer.head, buffer.length, buffer.kind), (10, 10, BUFFER_OUTPUT_SCREEN));
+		assert!(buffer.contains(19) && !buffer.contains(20));
+		assert_eq!(buffers.set_head(id, 0, MAPPED), Err(SyscallError::BufferAlreadySet));
+		assert_eq!(buffers.set_length(id, 1, MAPPED), Err(SyscallError::BufferAlreadySet));
+		assert_eq!(buffers.set_type(id, BUFFER_INPUT_KEY), Err(SyscallError::BufferAlreadySet));
+		assert_eq!(buffers.of_type(BUFFER_OUTPUT_SCREEN).map(|b| b.id), Some(id));
+	}
+
+	#[test]
+	fn ids_are_not_reused() {
+		let (mut buffers, id) = registry();
+		buffers.delete(id).unwrap();
+		assert_eq!(buffers.delete(id), Err(SyscallError::UnknownBuffer));
+		assert_eq!(buffers.set_head(id, 0, MAPPED), Err(SyscallError::UnknownBuffer));
+		assert_eq!(buffers.create(), id + 1);
+		assert_eq!(buffers.len(), 1);
+	}
+
+	#[test]
+	fn buffers_must_be_backed_by_memory() {
+		let mut buffers = BufferRegistry::new();
+		let id = buffers.create();
+		buffers.set_length(id, 10, MAPPED).unwrap();
+		assert_eq!(buffers.set_head(id, MAPPED as location - 5, MAPPED), Err(SyscallError::InvalidRange));
+		// a failed set can be tried again
+		assert_eq!(buffers.set_head(id, MAPPED as location - 10, MAPPED), Ok(()));
+		let other = buffers.create();
+		assert_eq!(buffers.set_type(other, 7), Err(SyscallError::InvalidBufferType));
+	}
+
+	#[test]
+	fn released_memory_deletes_its_buffers() {
+		let (mut buffers, id) = registry();
+		let other = buffers.create();
+		buffers.set_head(other, 30, MAPPED).unwrap();
+		assert!(!buffers.release(20, 10));
+		assert!(buffers.release(19, 1));
+		assert!(buffers.get(id).is_none());
+		assert!(buffers.release(30, 1));
+		assert_eq!(buffers.len(), 0);
+	}
+
+	#[test]
+	fn free_deletes_buffers_in_the_allocation() {
+		let mut machine = Machine::new("
+.data
+var args array 0 0
+.text
+LoadImmmediateToBus 8
+Syscall 40
+SaveValueInBusToLocation args + 1
+Syscall 1
+SaveValueInBusToLocation args
+LoadImmmediateToBus args
+Syscall 2
+Pause
+LoadValueAtAddressIntoBus args + 1
+Syscall 41
+");
+		machine.run();
+		assert_eq!(machine.processor.buffers.len(), 1);
+		machine.run();
+		assert_eq!(machine.processor.buffers.len(), 0);
+	}
+}
@@ -0,0 +1,249 @@
+// Clock and timer device.
+//
+// The clock counts ticks of one millisecond. By default the host drives it
+// with r_AdvanceClock as real time passes. In instruction mode every
+// instructions_per_tick retired instructions make a tick instead, so a
+// program sees the same times on every run no matter how fast it runs.
+//
+// Periodic timers count how often their period passed, programs poll them
+// with the TimerFired syscall. Every time one fires the timer interrupt line
+// is raised as well.
+
+use super::storage;
+
+pub const CLOCK_HOST: storage = 0;
+pub const CLOCK_INSTRUCTIONS: storage = 1;
+
+pub const DEFAULT_INSTRUCTIONS_PER_TICK: u32 = 1000;
+pub const TIMER_COUNT: usize = 8;
+
+#[derive(Clone, Copy)]
+struct Timer {
+	// 0 when the timer is off
+	period: u64,
+	next: u64,
+	fired: u64,
+}
+
+impl Timer {
+	fn new() -> Timer {
+		Timer {
+			period: 0,
+			next: 0,
+			fired: 0,
+		}
+	}
+}
+
+pub struct Clock {
+	ticks: u64,
+	mode: storage,
+	instructions_per_tick: u32,
+	instructions: u32,
+	timers: [Timer; TIMER_COUNT],
+	// the tick a sleeping program wakes up at
+	wake_at: Option<u64>,
+}
+
+impl Clock {
+	pub fn new() -> Clock {
+		let ticks = 0;
+		let mode = CLOCK_HOST;
+		let instructions_per_tick = DEFAULT_INSTRUCTIONS_PER_TICK;
+		let instructions = 0;
+		let timers = [Timer::new(); TIMER_COUNT];
+		let wake_at = None;
+		Clock {
+			ticks,
+			mode,
+			instructions_per_tick,
+			instructions,
+			timers,
+			wake_at,
+		}
+	}
+
+	pub fn ticks(&self) -> u64 {
+		return self.ticks;
+	}
+
+	pub fn is_instruction_driven(&self) -> bool {
+		return self.mode == CLOCK_INSTRUCTIONS;
+	}
+
+	// returns false for an unknown mode
+	pub fn set_mode(&mut self, mode: storage, instructions_per_tick: u32) -> bool {
+		if mode != CLOCK_HOST && mode != CLOCK_INSTRUCTIONS {
+			return false;
+		}
+		self.mode = mode;
+		self.instructions_per_tick = instructions_per_tick.max(1);
+		self.instructions = 0;
+		return true;
+	}
+
+	// returns true if a timer fired
+	pub fn advance(&mut self, ticks: u64) -> bool {
+		self.ticks = self.ticks.saturating_add(ticks);
+		let now = self.ticks;
+		let mut fired = false;
+		for timer in self.timers.iter_mut() {
+			if timer.period != 0 && timer.next <= now {
+				let periods = (now - timer.next) / timer.period + 1;
+				timer.fired = timer.fired.saturating_add(periods);
+				timer.next += periods * timer.period;
+				fired = true;
+			}
+		}
+		return fired;
+	}
+
+	// called for every instruction the processor retires
+	// returns true if a timer fired
+	pub fn retire(&mut self) -> bool {
+		if self.mode != CLOCK_INSTRUCTIONS {
+			return false;
+		}
+		self.instructions += 1;
+		if self.instructions >= self.instructions_per_tick {
+			self.instructions = 0;
+			return self.advance(1);
+		}
+		return false;
+	}
+
+	// a period of 0 turns the timer off
+	// returns false for an unknown timer
+	pub fn set_timer(&mut self, timer: usize, period: u64) -> bool {
+		if timer >= TIMER_COUNT {
+			return false;
+		}
+		self.timers[timer] = Timer {
+			period,
+			next: self.ticks.saturating_add(period),
+			fired: 0,
+		};
+		return true;
+	}
+
+	// how often the period passed since the last call
+	pub fn take_fired(&mut self, timer: usize) -> Option<u64> {
+		let timer = self.timers.get_mut(timer)?;
+		let fired = timer.fired;
+		timer.fired = 0;
+		return Some(fired);
+	}
+
+	pub fn sleep_until(&mut self, tick: u64) {
+		self.wake_at = Some(tick);
+	}
+
+	// ticks left until a sleeping program wakes up
+	pub fn sleep_remaining(&self) -> Option<u64> {
+		return self.wake_at.map(|tick| tick.saturating_sub(self.ticks));
+	}
+
+	// returns true once, when the wake up tick was reached
+	pub fn take_wake(&mut self) -> bool {
+		match self.wake_at {
+			Some(tick) if tick <= self.ticks => {
+				self.wake_at = None;
+				return true;
+			},
+			_ => {
+				return false;
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use exit::ExitReason;
+	use testing::Machine;
+	use ProcessorStatus;
+
+	#[test]
+	fn timers_count_every_period_that_passed() {
+		let mut clock = Clock::new();
+		assert!(clock.set_timer(0, 10));
+		assert!(!clock.advance(9));
+		assert_eq!(clock.take_fired(0), Some(0));
+		assert!(clock.advance(1));
+		assert_eq!(clock.take_fired(0), Some(1));
+		// three whole periods and part of the next in one go
+		assert!(clock.advance(35));
+		assert_eq!(clock.take_fired(0), Some(3));
+		assert!(clock.advance(5));
+		assert_eq!(clock.take_fired(0), Some(1));
+		assert_eq!(clock.take_fired(0), Some(0));
+		assert_eq!(clock.ticks(), 50);
+	}
+
+	#[test]
+	fn timers_turn_off_with_a_period_of_0() {
+		let mut clock = Clock::new();
+		clock.set_timer(3, 5);
+		clock.advance(5);
+		assert!(clock.set_timer(3, 0));
+		assert!(!clock.advance(100));
+		// turning it off forgets how often it fired
+		assert_eq!(clock.take_fired(3), Some(0));
+		assert!(!clock.set_timer(TIMER_COUNT, 5));
+		assert_eq!(clock.take_fired(TIMER_COUNT), None);
+	}
+
+	#[test]
+	fn instructions_make_ticks_in_instruction_mode() {
+		let mut clock = Clock::new();
+		assert!(!clock.retire());
+		assert_eq!(clock.ticks(), 0);
+
+		assert!(clock.set_mode(CLOCK_INSTRUCTIONS, 3));
+		assert!(clock.is_instruction_driven());
+		clock.set_timer(0, 2);
+		assert!(!clock.retire());
+		assert!(!clock.retire());
+		assert!(!clock.retire());
+		assert_eq!(clock.ticks(), 1);
+		for _ in 0 .. 3 {
+			clock.retire();
+		}
+		assert_eq!((clock.ticks(), clock.take_fired(0)), (2, Some(1)));
+
+		assert!(!clock.set_mode(2, 3));
+		assert!(clock.set_mode(CLOCK_HOST, 0));
+		assert!(!clock.is_instruction_driven());
+	}
+
+	#[test]
+	fn wakes_are_taken_once() {
+		let mut clock = Clock::new();
+		assert!(!clock.take_wake());
+		assert_eq!(clock.sleep_remaining(), None);
+		clock.sleep_until(10);
+		clock.advance(4);
+		assert_eq!(clock.sleep_remaining(), Some(6));
+		assert!(!clock.take_wake());
+		clock.advance(10);
+		assert_eq!(clock.sleep_remaining(), Some(0));
+		assert!(clock.take_wake());
+		assert!(!clock.take_wake());
+		assert_eq!(clock.sleep_remaining(), None);
+	}
+
+	#[test]
+	fn sleeping_programs_resume_when_the_host_advances_the_clock() {
+		let mut machine = Machine::new(".text\nLoadImmmediateToBus 50\nSyscall 20\nLoadImmmediateToBus 7\nHalt");
+		machine.run();
+		assert!(matches!(machine.processor.status, ProcessorStatus::Sleeping));
+		machine.processor.advance_clock(49);
+		assert!(matches!(machine.processor.status, ProcessorStatus::Sleeping));
+		machine.processor.advance_clock(1);
+		assert!(matches!(machine.processor.status, ProcessorStatus::Paused));
+		machine.run();
+		assert_eq!((machine.processor.exit.reason, machine.processor.exit.code), (ExitReason::Halt, 7));
+		assert_eq!(machine.processor.clock.ticks(), 50);
+	}
+}
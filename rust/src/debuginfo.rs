@@ -0,0 +1,401 @@
+// Source maps for a program: which file, line and column every address was
+// assembled from, and where its labels and variables are.
+//
+// Consecutive words made from the same span are one range, so an instruction
+// and its parameters, or all the words of a macro, map to the line that made
+// them. A range of code starts with an opcode and holds whole instructions.
+// A breakpoint on a line goes on the first word of code from it.
+//
+// A debug section is little endian 32 bit words:
+//
+//	DEBUG_MAGIC DEBUG_VERSION
+//	count, per file: name
+//	count, per range: start, length, flags, file, line, column, span length
+//	count, per symbol: address, kind, file, line, column, span length, name
+//
+// Names are written like in object.rs, symbol kinds are its export kinds and
+// the flags are RANGE_CODE or 0. Object files end with the same counts and
+// lists, without the magic and version.
+
+use super::{storage, location};
+use assembler::{Span, Symbol, SymbolKind, Word};
+use object::{Reader, push_name, kind_word, word_kind};
+use opcodes::opcode;
+
+// "DSLD"
+pub const DEBUG_MAGIC: storage = 0x444C_5344;
+pub const DEBUG_VERSION: storage = 1;
+
+// the range is code, not data
+pub const RANGE_CODE: storage = 1;
+
+// fields of r_GetSourceLocation
+pub const SOURCE_FILE: storage = 0;
+pub const SOURCE_LINE: storage = 1;
+pub const SOURCE_COLUMN: storage = 2;
+pub const SOURCE_LENGTH: storage = 3;
+pub const SOURCE_START: storage = 4;
+
+// fields of r_GetSymbolInfo
+pub const SYMBOL_ADDRESS: storage = 0;
+pub const SYMBOL_KIND: storage = 1;
+pub const SYMBOL_FILE: storage = 2;
+pub const SYMBOL_LINE: storage = 3;
+pub const SYMBOL_COLUMN: storage = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceRange {
+	pub start: location,
+	// in words
+	pub length: storage,
+	// instructions, not variables
+	pub code: bool,
+	pub span: Span,
+}
+
+impl SourceRange {
+	pub fn contains(&self, address: location) -> bool {
+		return address >= self.start && address - self.start < self.length;
+	}
+
+	pub fn field(&self, field: storage) -> Option<storage> {
+		match field {
+			SOURCE_FILE => {
+				return Some(self.span.file as storage);
+			},
+			SOURCE_LINE => {
+				return Some(self.span.line as storage);
+			},
+			SOURCE_COLUMN => {
+				return Some(self.span.column as storage);
+			},
+			SOURCE_LENGTH => {
+				return Some(self.span.length as storage);
+			},
+			SOURCE_START => {
+				return Some(self.start);
+			},
+			_ => {
+				return None;
+			},
+		}
+	}
+}
+
+pub struct DebugInfo {
+	pub files: Vec<String>,
+	// by address, they don't overlap
+	pub ranges: Vec<SourceRange>,
+	pub symbols: Vec<Symbol>,
+}
+
+impl Default for DebugInfo {
+	fn default() -> DebugInfo {
+		return DebugInfo::new();
+	}
+}
+
+impl DebugInfo {
+	pub fn new() -> DebugInfo {
+		let files = Vec::new();
+		let ranges = Vec::new();
+		let symbols = Vec::new();
+		DebugInfo {
+			files,
+			ranges,
+			symbols,
+		}
+	}
+
+	// from the debug info of each word, the first word is at start, the
+	// symbols already have their addresses
+	pub fn from_source(start: location, words: &[Word], symbols: &[Symbol], files: &[String]) -> DebugInfo {
+		let mut ranges: Vec<SourceRange> = Vec::new();
+		for (index, word) in words.iter().enumerate() {
+			let span = match word.span {
+				Some(span) => span,
+				None => continue,
+			};
+			let address = start + index as location;
+			// the parameters of an instruction aren't code words, but they are
+			// in its range, variables come before all code
+			if let Some(last) = ranges.last_mut() {
+				if last.span == span && (last.code || !word.code) && last.start + last.length == address {
+					last.length += 1;
+					continue;
+				}
+			}
+			ranges.push(SourceRange {
+				start: address,
+				length: 1,
+				code: word.code,
+				span,
+			});
+		}
+		return DebugInfo {
+			files: files.to_vec(),
+			ranges,
+			symbols: symbols.to_vec(),
+		};
+	}
+
+	// the debug info of each word of the image at start, the other way
+	// around, the opcodes of the code are found from the opcode table
+	pub fn to_source(&self, start: location, image: &[storage]) -> Vec<Word> {
+		let mut words = vec![Word { span: None, code: false, comment: String::new() }; image.len()];
+		for range in self.ranges.iter() {
+			let mut next_opcode = range.start;
+			for address in range.start .. range.start + range.length {
+				let index = match address.checked_sub(start) {
+					Some(index) if (index as usize) < words.len() => index as usize,
+					_ => continue,
+				};
+				words[index].span = Some(range.span);
+				if range.code && address == next_opcode {
+					words[index].code = true;
+					next_opcode += 1 + opcode(image[index]).map_or(0, |op| op.parameters as location);
+				}
+			}
+		}
+		return words;
+	}
+
+	pub fn range_at(&self, address: location) -> Option<&SourceRange> {
+		let index = match self.ranges.binary_search_by_key(&address, |range| range.start) {
+			Ok(index) => index,
+			Err(0) => return None,
+			Err(index) => index - 1,
+		};
+		return Some(&self.ranges[index]).filter(|range| range.contains(address));
+	}
+
+	pub fn file_index(&self, name: &str) -> Option<usize> {
+		return self.files.iter().position(|file| file == name);
+	}
+
+	// the first word of code from the line, or from the next line with code
+	// on it, so a breakpoint on a comment stops at the code after it
+	pub fn line_address(&self, file: usize, line: usize) -> Option<location> {
+		return self.ranges.iter()
+			.filter(|range| range.code && range.span.file == file && range.span.line >= line)
+			.min_by_key(|range| (range.span.line, range.start))
+			.map(|range| range.start);
+	}
+
+	pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+		return self.symbols.iter().find(|symbol| symbol.name == name);
+	}
+
+	// the label with the name, variables can't have breakpoints
+	pub fn label(&self, name: &str) -> Option<&Symbol> {
+		return self.symbol(name).filter(|symbol| symbol.kind == SymbolKind::Label);
+	}
+
+	// the closest label at or before the address, the routine a stop is in
+	pub fn label_at(&self, address: location) -> Option<&Symbol> {
+		return self.symbols.iter()
+			.filter(|symbol| symbol.kind == SymbolKind::Label && symbol.address <= address)
+			.max_by_key(|symbol| symbol.address);
+	}
+
+	// file:line:column, with the label and how far past it the address is,
+	// a column of 0 is left out
+	pub fn describe(&self, address: location) -> Option<String> {
+		let range = self.range_at(address)?;
+		let mut text = match self.files.get(range.span.file) {
+			Some(file) if !file.is_empty() => format!("{}:{}", file, range.span.line),
+			_ => format!("line {}", range.span.line),
+		};
+		if range.span.column != 0 {
+			text.push_str(&format!(":{}", range.span.column));
+		}
+		if let Some(label) = self.label_at(address) {
+			match address - label.address {
+				0 => text.push_str(&format!(" ({})", label.name)),
+				offset => text.push_str(&format!(" ({}+{})", label.name, offset)),
+			}
+		}
+		return Some(text);
+	}
+
+	pub fn to_words(&self) -> Vec<storage> {
+		let mut words = vec![DEBUG_MAGIC, DEBUG_VERSION];
+		self.write(&mut words);
+		return words;
+	}
+
+	pub fn from_words(words: &[storage]) -> Result<DebugInfo, String> {
+		let mut reader = Reader::new(words);
+		if reader.next()? != DEBUG_MAGIC {
+			return Err("not a debug section".to_string());
+		}
+		let version = reader.next()?;
+		if version != DEBUG_VERSION {
+			return Err(format!("debug section version {} is not supported", version));
+		}
+		let info = DebugInfo::read(&mut reader)?;
+		reader.end()?;
+		return Ok(info);
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		return self.to_words().iter().flat_map(|word| word.to_le_bytes()).collect();
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<DebugInfo, String> {
+		if !bytes.len().is_multiple_of(4) {
+			return Err(format!("debug section is {} bytes long, not a whole number of words", bytes.len()));
+		}
+		let words: Vec<storage> = bytes.chunks(4)
+			.map(|word| storage::from_le_bytes([word[0], word[1], word[2], word[3]]))
+			.collect();
+		return DebugInfo::from_words(&words);
+	}
+
+	// the counts and lists, without the magic and version
+	pub(crate) fn write(&self, words: &mut Vec<storage>) {
+		words.push(self.files.len() as storage);
+		for file in self.files.iter() {
+			push_name(words, file);
+		}
+		words.push(self.ranges.len() as storage);
+		for range in self.ranges.iter() {
+			let flags = if range.code { RANGE_CODE } else { 0 };
+			words.extend_from_slice(&[range.start, range.length, flags]);
+			push_span(words, range.span);
+		}
+		words.push(self.symbols.len() as storage);
+		for symbol in self.symbols.iter() {
+			words.extend_from_slice(&[symbol.address, kind_word(symbol.kind)]);
+			push_span(words, symbol.span);
+			push_name(words, &symbol.name);
+		}
+	}
+
+	pub(crate) fn read(reader: &mut Reader) -> Result<DebugInfo, String> {
+		let mut files = Vec::new();
+		for _ in 0 .. reader.next()? {
+			files.push(reader.name()?);
+		}
+		let mut ranges: Vec<SourceRange> = Vec::new();
+		for _ in 0 .. reader.next()? {
+			let start = reader.next()?;
+			let length = reader.next()?;
+			let code = match reader.next()? {
+				0 => false,
+				RANGE_CODE => true,
+				flags => return Err(format!("{} are not range flags", flags)),
+			};
+			let span = read_span(reader, files.len())?;
+			if let Some(last) = ranges.last() {
+				if start < last.start + last.length {
+					return Err(format!("range at {} overlaps the one before it", start));
+				}
+			}
+			if length == 0 || start.checked_add(length).is_none() {
+				return Err(format!("range at {} has a length of {}", start, length));
+			}
+			ranges.push(SourceRange {
+				start,
+				length,
+				code,
+				span,
+			});
+		}
+		let mut symbols = Vec::new();
+		for _ in 0 .. reader.next()? {
+			let address = reader.next()?;
+			let kind = word_kind(reader.next()?)?;
+			let span = read_span(reader, files.len())?;
+			let name = reader.name()?;
+			symbols.push(Symbol {
+				name,
+				kind,
+				address,
+				span,
+			});
+		}
+		return Ok(DebugInfo {
+			files,
+			ranges,
+			symbols,
+		});
+	}
+}
+
+fn push_span(words: &mut Vec<storage>, span: Span) {
+	words.extend_from_slice(&[span.file as storage, span.line as storage, span.column as storage, span.length as storage]);
+}
+
+fn read_span(reader: &mut Reader, files: usize) -> Result<Span, String> {
+	let file = reader.next()? as usize;
+	if file >= files {
+		return Err(format!("file {} but there are only {}", file, files));
+	}
+	let line = reader.next()? as usize;
+	let column = reader.next()? as usize;
+	let length = reader.next()? as usize;
+	return Ok(Span {
+		file,
+		line,
+		column,
+		length,
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::assemble;
+
+	const SOURCE: &str = ".data\nvar count number 3\n.text\n@start\n// a comment\nLoadImmmediateToBus 5\nHalt";
+
+	#[test]
+	fn instructions_map_to_their_lines() {
+		let assembly = assemble(SOURCE).unwrap();
+		let debug = assembly.debug_info();
+		let start = assembly.symbol("start").unwrap().address;
+		let range = debug.range_at(start + 1).unwrap();
+		assert_eq!((range.start, range.length, range.code, range.span.line), (start, 2, true, 6));
+		assert_eq!(debug.range_at(start + 2).unwrap().span.line, 7);
+		assert!(debug.range_at(start + 3).is_none());
+		let count = assembly.symbol("count").unwrap().address;
+		assert!(!debug.range_at(count).unwrap().code);
+	}
+
+	#[test]
+	fn line_breakpoints_go_on_the_next_code() {
+		let debug = assemble(SOURCE).unwrap().debug_info();
+		let start = debug.symbol("start").unwrap().address;
+		// the label and the comment have no code, the variable is not code
+		assert_eq!(debug.line_address(0, 2), Some(start));
+		assert_eq!(debug.line_address(0, 5), Some(start));
+		assert_eq!(debug.line_address(0, 6), Some(start));
+		assert_eq!(debug.line_address(0, 7), Some(start + 2));
+		assert_eq!(debug.line_address(0, 8), None);
+		assert_eq!(debug.line_address(1, 1), None);
+	}
+
+	#[test]
+	fn symbol_breakpoints_are_labels() {
+		let debug = assemble(SOURCE).unwrap().debug_info();
+		let start = debug.symbol("start").unwrap().address;
+		assert_eq!(debug.label("start").map(|label| label.address), Some(start));
+		assert!(debug.symbol("count").is_some());
+		assert!(debug.label("count").is_none());
+		assert!(debug.label("missing").is_none());
+		assert_eq!(debug.label_at(start + 2).unwrap().name, "start");
+		assert_eq!(debug.describe(start).unwrap(), "line 6:1 (start)");
+		assert_eq!(debug.describe(start + 2).unwrap(), "line 7:1 (start+2)");
+	}
+
+	#[test]
+	fn lookups_survive_the_debug_section() {
+		let debug = assemble(SOURCE).unwrap().debug_info();
+		let read = DebugInfo::from_bytes(&debug.to_bytes()).unwrap();
+		assert_eq!(read.ranges, debug.ranges);
+		assert_eq!(read.line_address(0, 5), debug.line_address(0, 5));
+		assert_eq!(read.label("start").unwrap().address, debug.label("start").unwrap().address);
+		assert!(DebugInfo::from_bytes(&[0, 0, 0, 0]).is_err());
+	}
+}
@@ -0,0 +1,478 @@
+// Drawing primitives for the OUTPUT_SCREEN buffer.
+//
+// Programs pass coordinates as signed integers so shapes may hang off any
+// edge of the screen. Everything is clipped against the screen size and
+// the length of the screen buffer, so a bad shape can never write outside
+// of the buffer.
+
+use super::{Processor, storage, location, bits_to_i32};
+use buffers::BUFFER_OUTPUT_SCREEN;
+use syscalls::SyscallError;
+use font::{self, CELL_WIDTH, CELL_HEIGHT, GLYPH_WIDTH, GLYPH_HEIGHT};
+
+// coordinates and sizes further out than this are rejected, so a shape can
+// never take more than a few hundred thousand steps to draw
+pub const MAX_COORDINATE: i32 = 1 << 16;
+
+// longest string DrawText accepts, so a missing terminator fails quickly
+pub const MAX_TEXT_LENGTH: usize = 4096;
+
+// where the screen buffer lives, looked up once per syscall
+#[derive(Clone, Copy)]
+pub struct Screen {
+	pub head: location,
+	pub length: usize,
+	pub width: i32,
+	pub height: i32,
+}
+
+impl Screen {
+	// address of the pixel, None if it is clipped
+	pub fn address(&self, x: i32, y: i32) -> Option<location> {
+		if x < 0 || y < 0 || x >= self.width || y >= self.height {
+			return None;
+		}
+		let index = y as usize * self.width as usize + x as usize;
+		if index >= self.length {
+			return None;
+		}
+		return Some(self.head + index as location);
+	}
+}
+
+// a width * height block of color indices in VM memory, stored row by row
+#[derive(Clone, Copy)]
+pub struct Sprite {
+	pub address: location,
+	pub width: i32,
+	pub height: i32,
+	// pixels with this color index are skipped
+	pub transparent: storage,
+}
+
+impl Processor {
+	pub fn screen(&mut self) -> Result<Screen, SyscallError> {
+		let (head, length) = match self.buffers.of_type(BUFFER_OUTPUT_SCREEN) {
+			Some(buffer) => (buffer.head, buffer.length as usize),
+			None => return Err(SyscallError::NoScreen),
+		};
+		let (width, height) = self.screen_size();
+		return Ok(Screen {
+			head,
+			length,
+			width: width as i32,
+			height: height as i32,
+		});
+	}
+
+	pub fn plot(&mut self, screen: &Screen, x: i32, y: i32, color: storage) -> Option<()> {
+		if let Some(address) = screen.address(x, y) {
+			self.write_memory(address, color)?;
+		}
+		return Some(());
+	}
+
+	pub fn fill_rect(&mut self, screen: &Screen, x: i32, y: i32, width: i32, height: i32, color: storage) -> Option<()> {
+		// clip first so huge rectangles don't loop over pixels that can't be seen
+		let left = x.max(0);
+		let top = y.max(0);
+		let right = x.saturating_add(width).min(screen.width);
+		let bottom = y.saturating_add(height).min(screen.height);
+		for py in top .. bottom {
+			for px in left .. right {
+				self.plot(screen, px, py, color)?;
+			}
+		}
+		return Some(());
+	}
+
+	// Bresenham, both end points are drawn
+	pub fn draw_line(&mut self, screen: &Screen, x0: i32, y0: i32, x1: i32, y1: i32, color: storage) -> Option<()> {
+		let dx = (x1 as i64 - x0 as i64).abs();
+		let dy = -(y1 as i64 - y0 as i64).abs();
+		let sx = if x0 < x1 { 1 } else { -1 };
+		let sy = if y0 < y1 { 1 } else { -1 };
+		let mut error = dx + dy;
+		let (mut x, mut y) = (x0, y0);
+		loop {
+			self.plot(screen, x, y, color)?;
+			if x == x1 && y == y1 {
+				return Some(());
+			}
+			let e2 = 2 * error;
+			if e2 >= dy {
+				error += dy;
+				x += sx;
+			}
+			if e2 <= dx {
+				error += dx;
+				y += sy;
+			}
+		}
+	}
+
+	// midpoint circle
+	pub fn draw_circle(&mut self, screen: &Screen, cx: i32, cy: i32, radius: i32, color: storage, filled: bool) -> Option<()> {
+		if radius < 0 {
+			return Some(());
+		}
+		let mut x = radius;
+		let mut y = 0;
+		let mut error = 1 - radius;
+		while x >= y {
+			if filled {
+				self.fill_rect(screen, cx - x, cy + y, 2 * x + 1, 1, color)?;
+				self.fill_rect(screen, cx - x, cy - y, 2 * x + 1, 1, color)?;
+				self.fill_rect(screen, cx - y, cy + x, 2 * y + 1, 1, color)?;
+				self.fill_rect(screen, cx - y, cy - x, 2 * y + 1, 1, color)?;
+			}
+			else {
+				for &(px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)].iter() {
+					self.plot(screen, cx + px, cy + py, color)?;
+				}
+			}
+			y += 1;
+			if error < 0 {
+				error += 2 * y + 1;
+			}
+			else {
+				x -= 1;
+				error += 2 * (y - x) + 1;
+			}
+		}
+		return Some(());
+	}
+
+	pub fn blit(&mut self, screen: &Screen, x: i32, y: i32, sprite: &Sprite) -> Option<()> {
+		// only the part of the sprite that lands on the screen is read
+		for sy in (-y).max(0) .. sprite.height.min(screen.height - y) {
+			for sx in (-x).max(0) .. sprite.width.min(screen.width - x) {
+				// width and height can both be MAX_COORDINATE, too big for an i32
+				let offset = sy as i64 * sprite.width as i64 + sx as i64;
+				let color = self.peek_memory(sprite.address.wrapping_add(offset as location))?;
+				if color != sprite.transparent {
+					self.plot(screen, x + sx, y + sy, color)?;
+				}
+			}
+		}
+		return Some(());
+	}
+
+	// only the set pixels of each glyph are drawn, the background shows through
+	pub fn draw_text(&mut self, screen: &Screen, x: i32, y: i32, text: &[storage], color: storage) -> Option<()> {
+		let (mut cx, mut cy) = (x, y);
+		for &character in text.iter() {
+			if character == '\n' as storage {
+				cx = x;
+				cy = cy.saturating_add(CELL_HEIGHT as i32);
+				continue;
+			}
+			for gy in 0 .. GLYPH_HEIGHT {
+				for gx in 0 .. GLYPH_WIDTH {
+					if font::is_set(character, gx, gy) {
+						self.plot(screen, cx.saturating_add(gx as i32), cy.saturating_add(gy as i32), color)?;
+					}
+				}
+			}
+			cx = cx.saturating_add(CELL_WIDTH as i32);
+		}
+		return Some(());
+	}
+
+	// moves the picture by (dx, dy) and fills what was uncovered
+	pub fn scroll(&mut self, screen: &Screen, dx: i32, dy: i32, fill: storage) -> Option<()> {
+		// walk against the direction of the move so nothing is overwritten before it is copied
+		let xs: Vec<i32> = if dx > 0 { (0 .. screen.width).rev().collect() } else { (0 .. screen.width).collect() };
+		let ys: Vec<i32> = if dy > 0 { (0 .. screen.height).rev().collect() } else { (0 .. screen.height).collect() };
+		for &y in ys.iter() {
+			for &x in xs.iter() {
+				let color = match screen.address(x.wrapping_sub(dx), y.wrapping_sub(dy)) {
+					Some(from) => self.peek_memory(from)?,
+					None => fill,
+				};
+				self.plot(screen, x, y, color)?;
+			}
+		}
+		return Some(());
+	}
+}
+
+// syscall arguments are raw words, coordinates are signed
+pub fn signed(value: storage) -> i32 {
+	return bits_to_i32(value);
+}
+
+pub fn in_range(values: &[i32]) -> bool {
+	return values.iter().all(|v| v.unsigned_abs() <= MAX_COORDINATE as u32);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use buffers::BUFFER_OUTPUT_SCREEN_SIZE;
+	use protection::FaultKind;
+	use syscalls::{SYSCALL_OK, SYSCALL_ERROR};
+	use testing::Machine;
+
+	const SCREEN: location = 200;
+	const SPRITE: location = 300;
+
+	// a 4 by 4 screen and a 3 by 3 sprite of the colors 1 to 9
+	fn machine() -> Machine {
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(100, &[4, 4], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(SCREEN, &[0; 16], BUFFER_OUTPUT_SCREEN);
+		for i in 0 .. 9 {
+			machine.processor._set_memory_loc(SPRITE + i, i + 1);
+		}
+		return machine;
+	}
+
+	fn sprite(width: i32, height: i32) -> Sprite {
+		return Sprite {
+			address: SPRITE,
+			width,
+			height,
+			transparent: 0,
+		};
+	}
+
+	fn row(machine: &Machine, y: location) -> Vec<storage> {
+		return (0 .. 4).map(|x| machine.processor.peek(SCREEN + y * 4 + x)).collect();
+	}
+
+	#[test]
+	fn blit_clips_at_the_edges() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		assert_eq!(machine.processor.blit(&screen, -1, 2, &sprite(3, 3)), Some(()));
+		assert_eq!(row(&machine, 1), vec![0, 0, 0, 0]);
+		assert_eq!(row(&machine, 2), vec![2, 3, 0, 0]);
+		assert_eq!(row(&machine, 3), vec![5, 6, 0, 0]);
+	}
+
+	#[test]
+	fn huge_sprites_do_not_overflow() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		// the first visible row starts 65000 * 65536 words into the sprite,
+		// far past the end of memory
+		let sprite = sprite(MAX_COORDINATE, MAX_COORDINATE);
+		assert_eq!(machine.processor.blit(&screen, 0, -65000, &sprite), None);
+		assert_eq!(machine.processor.fault.unwrap().kind, FaultKind::Unmapped);
+		assert_eq!(row(&machine, 0), vec![0, 0, 0, 0]);
+	}
+
+	fn pixels(machine: &Machine) -> Vec<Vec<storage>> {
+		return (0 .. 4).map(|y| row(machine, y)).collect();
+	}
+
+	// the screen counts 1 to 16 row by row
+	fn numbered() -> Machine {
+		let mut machine = machine();
+		for i in 0 .. 16 {
+			machine.processor._set_memory_loc(SCREEN + i, i + 1);
+		}
+		return machine;
+	}
+
+	#[test]
+	fn fill_rect_clips_at_the_edges() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		assert_eq!(machine.processor.fill_rect(&screen, 1, 1, 2, 2, 7), Some(()));
+		assert_eq!(machine.processor.fill_rect(&screen, -2, -2, 3, 3, 5), Some(()));
+		assert_eq!(machine.processor.fill_rect(&screen, 3, -MAX_COORDINATE, MAX_COORDINATE, i32::MAX, 4), Some(()));
+		assert_eq!(pixels(&machine), vec![
+			vec![5, 0, 0, 4],
+			vec![0, 7, 7, 4],
+			vec![0, 7, 7, 4],
+			vec![0, 0, 0, 4],
+		]);
+	}
+
+	#[test]
+	fn empty_rects_draw_nothing() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.fill_rect(&screen, 1, 1, 0, 2, 7);
+		machine.processor.fill_rect(&screen, 1, 1, -2, -2, 7);
+		machine.processor.fill_rect(&screen, 4, 0, 2, 2, 7);
+		assert!(pixels(&machine).iter().all(|row| row.iter().all(|&pixel| pixel == 0)));
+	}
+
+	#[test]
+	fn lines_draw_both_ends() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.draw_line(&screen, 3, 0, 0, 0, 1);
+		machine.processor.draw_line(&screen, 0, 1, 3, 3, 2);
+		machine.processor.draw_line(&screen, 3, 3, 3, 3, 3);
+		assert_eq!(pixels(&machine), vec![
+			vec![1, 1, 1, 1],
+			vec![2, 0, 0, 0],
+			vec![0, 2, 2, 0],
+			vec![0, 0, 0, 3],
+		]);
+	}
+
+	#[test]
+	fn lines_clip_at_the_edges() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		assert_eq!(machine.processor.draw_line(&screen, -2, -2, 5, 5, 1), Some(()));
+		assert_eq!(machine.processor.draw_line(&screen, -MAX_COORDINATE, 3, MAX_COORDINATE, 3, 2), Some(()));
+		assert_eq!(pixels(&machine), vec![
+			vec![1, 0, 0, 0],
+			vec![0, 1, 0, 0],
+			vec![0, 0, 1, 0],
+			vec![2, 2, 2, 2],
+		]);
+	}
+
+	#[test]
+	fn circles_are_outlined_or_filled() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.draw_circle(&screen, 1, 1, 1, 6, false);
+		assert_eq!(pixels(&machine), vec![
+			vec![0, 6, 0, 0],
+			vec![6, 0, 6, 0],
+			vec![0, 6, 0, 0],
+			vec![0, 0, 0, 0],
+		]);
+		machine.processor.draw_circle(&screen, 3, 3, 1, 8, true);
+		assert_eq!(row(&machine, 2), vec![0, 6, 0, 8]);
+		assert_eq!(row(&machine, 3), vec![0, 0, 8, 8]);
+	}
+
+	#[test]
+	fn circles_clip_and_skip_negative_radii() {
+		let mut machine = machine();
+		let screen = machine.processor.screen().unwrap();
+		assert_eq!(machine.processor.draw_circle(&screen, -1, -1, 2, 5, true), Some(()));
+		assert_eq!(machine.processor.draw_circle(&screen, 2, 2, -1, 9, true), Some(()));
+		assert_eq!(machine.processor.draw_circle(&screen, -2, 1, 2, 4, false), Some(()));
+		// the outline's right edge is the only part on the screen
+		assert_eq!(pixels(&machine), vec![
+			vec![4, 5, 0, 0],
+			vec![4, 0, 0, 0],
+			vec![4, 0, 0, 0],
+			vec![0, 0, 0, 0],
+		]);
+	}
+
+	#[test]
+	fn scroll_moves_the_picture_and_fills_the_rest() {
+		let mut machine = numbered();
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.scroll(&screen, 1, -2, 0);
+		assert_eq!(pixels(&machine), vec![
+			vec![0, 9, 10, 11],
+			vec![0, 13, 14, 15],
+			vec![0, 0, 0, 0],
+			vec![0, 0, 0, 0],
+		]);
+
+		let mut machine = numbered();
+		machine.processor.scroll(&screen, -1, 1, 20);
+		assert_eq!(pixels(&machine), vec![
+			vec![20, 20, 20, 20],
+			vec![2, 3, 4, 20],
+			vec![6, 7, 8, 20],
+			vec![10, 11, 12, 20],
+		]);
+	}
+
+	#[test]
+	fn scrolling_off_the_screen_fills_it() {
+		let mut machine = numbered();
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.scroll(&screen, i32::MIN, 0, 3);
+		assert!(pixels(&machine).iter().all(|row| row.iter().all(|&pixel| pixel == 3)));
+		let mut machine = numbered();
+		machine.processor.scroll(&screen, 0, 0, 3);
+		assert_eq!(row(&machine, 3), vec![13, 14, 15, 16]);
+	}
+
+	// past the variables of the programs, the longest is a 4097 word string
+	const TEXT_SIZE: location = 10_000;
+	const TEXT_SCREEN: location = 10_010;
+	const TEXT_WIDTH: i32 = 2 * CELL_WIDTH as i32;
+	const TEXT_HEIGHT: i32 = 2 * CELL_HEIGHT as i32;
+
+	// two cells wide and two high
+	fn text_machine(source: &str) -> Machine {
+		let mut machine = Machine::new(source);
+		machine.buffer(TEXT_SIZE, &[TEXT_WIDTH as storage, TEXT_HEIGHT as storage], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(TEXT_SCREEN, &[0; (TEXT_WIDTH * TEXT_HEIGHT) as usize], BUFFER_OUTPUT_SCREEN);
+		return machine;
+	}
+
+	fn text_pixel(machine: &Machine, x: i32, y: i32) -> storage {
+		return machine.processor.peek(TEXT_SCREEN + (y * TEXT_WIDTH + x) as location);
+	}
+
+	fn characters(text: &str) -> Vec<storage> {
+		return text.chars().map(|c| c as storage).collect();
+	}
+
+	#[test]
+	fn text_draws_the_set_pixels_of_each_glyph() {
+		let mut machine = text_machine(".text\nHalt");
+		let screen = machine.processor.screen().unwrap();
+		assert_eq!(machine.processor.draw_text(&screen, 0, 0, &characters("!A"), 3), Some(()));
+		for y in 0 .. TEXT_HEIGHT {
+			for x in 0 .. TEXT_WIDTH {
+				let character = if x < CELL_WIDTH as i32 { '!' } else { 'A' } as storage;
+				let set = font::is_set(character, x as usize % CELL_WIDTH, y as usize);
+				assert_eq!(text_pixel(&machine, x, y), if set { 3 } else { 0 }, "pixel {}, {}", x, y);
+			}
+		}
+	}
+
+	#[test]
+	fn newlines_go_back_to_the_start_one_cell_down() {
+		let mut machine = text_machine(".text\nHalt");
+		let screen = machine.processor.screen().unwrap();
+		machine.processor.draw_text(&screen, 1, 0, &characters("!\n!"), 5);
+		// the middle column of "!" is 2 pixels in
+		assert_eq!(text_pixel(&machine, 3, 0), 5);
+		assert_eq!(text_pixel(&machine, 3, CELL_HEIGHT as i32), 5);
+		assert_eq!(text_pixel(&machine, 3 + CELL_WIDTH as i32, 0), 0);
+		// text hanging off the screen is clipped
+		assert_eq!(machine.processor.draw_text(&screen, -3, TEXT_HEIGHT - 1, &characters("!!!"), 6), Some(()));
+		assert_eq!(text_pixel(&machine, 5, TEXT_HEIGHT - 1), 6);
+	}
+
+	#[test]
+	fn draw_text_syscall() {
+		let mut machine = text_machine("
+.data
+var text string \"!\"
+var args array 6 8 4 text
+.text
+LoadImmmediateToBus args
+Syscall 55
+Halt
+");
+		machine.run();
+		assert_eq!(machine.processor.bus, SYSCALL_OK);
+		assert_eq!(text_pixel(&machine, 8, 8), 4);
+		assert_eq!(text_pixel(&machine, 8, 13), 0);
+	}
+
+	#[test]
+	fn draw_text_refuses_long_text() {
+		let source = |length: usize| format!(".data\nvar text string \"{}\"\nvar args array 0 0 4 text\n.text\nLoadImmmediateToBus args\nSyscall 55\nHalt", "!".repeat(length));
+		let mut machine = text_machine(&source(MAX_TEXT_LENGTH));
+		machine.run();
+		assert_eq!(machine.processor.bus, SYSCALL_OK);
+		assert_eq!(text_pixel(&machine, 2, 0), 4);
+
+		let mut machine = text_machine(&source(MAX_TEXT_LENGTH + 1));
+		machine.run();
+		assert_eq!((machine.processor.bus, machine.processor.last_error), (SYSCALL_ERROR, SyscallError::InvalidSize));
+		assert_eq!(text_pixel(&machine, 2, 0), 0);
+	}
+}
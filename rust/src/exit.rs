@@ -0,0 +1,164 @@
+// Why and where a program stopped for good.
+//
+// The host reads this with r_GetExitInfo once the processor is Halted or
+// Faulted, so it can tell a program that halted on purpose from one that
+// ran into garbage, faulted or used up its step budget.
+
+use super::{Processor, ProcessorStatus, StopCode, storage, location};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExitReason {
+	// still running, or never ran
+	None = 0,
+	// the halt opcode, the exit code is the bus
+	Halt = 1,
+	// an opcode that doesn't exist, the exit code is the opcode
+	InvalidOpcode = 2,
+	// see r_GetFaultKind, the exit code is the fault kind
+	Fault = 3,
+	// the step budget ran out, the exit code is the number of steps
+	Budget = 4,
+}
+
+// fields of r_GetExitInfo
+pub const EXIT_REASON: storage = 0;
+pub const EXIT_CODE: storage = 1;
+pub const EXIT_ADDRESS: storage = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExitInfo {
+	pub reason: ExitReason,
+	pub code: storage,
+	// the instruction the program stopped at
+	pub address: location,
+}
+
+impl Default for ExitInfo {
+	fn default() -> ExitInfo {
+		return ExitInfo::new();
+	}
+}
+
+impl ExitInfo {
+	pub fn new() -> ExitInfo {
+		ExitInfo {
+			reason: ExitReason::None,
+			code: 0,
+			address: 0,
+		}
+	}
+
+	pub fn field(&self, field: storage) -> storage {
+		match field {
+			EXIT_REASON => {
+				return self.reason as storage;
+			},
+			EXIT_CODE => {
+				return self.code;
+			},
+			EXIT_ADDRESS => {
+				return self.address;
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+}
+
+impl Processor {
+	// the processor doesn't move on from the instruction it stopped at
+	pub fn exit(&mut self, reason: ExitReason, code: storage, address: location) -> StopCode {
+		self.exit = ExitInfo {
+			reason,
+			code,
+			address,
+		};
+		self.status = ProcessorStatus::Halted;
+		self.next = address;
+		self.perStepDontMove = false;
+		return StopCode::Halt;
+	}
+
+	// steps is the most instructions the program may run, 0 for no limit
+	pub fn set_step_budget(&mut self, steps: u64) {
+		self.step_budget = steps;
+	}
+
+	pub fn is_over_budget(&self) -> bool {
+		return self.step_budget != 0 && self.steps >= self.step_budget;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use protection::FaultKind;
+	use testing::Machine;
+
+	fn run(source: &str) -> Machine {
+		let mut machine = Machine::new(source);
+		machine.run();
+		return machine;
+	}
+
+	fn fields(exit: &ExitInfo) -> (storage, storage, storage) {
+		return (exit.field(EXIT_REASON), exit.field(EXIT_CODE), exit.field(EXIT_ADDRESS));
+	}
+
+	#[test]
+	fn halts_exit_with_the_bus() {
+		let machine = run(".text\nLoadImmmediateToBus 42\n@stop\nHalt");
+		let exit = machine.processor.exit;
+		assert_eq!(exit.reason, ExitReason::Halt);
+		assert_eq!((exit.code, exit.address), (42, machine.address("stop")));
+		assert_eq!(fields(&exit), (ExitReason::Halt as storage, 42, machine.address("stop")));
+		assert!(matches!(machine.processor.status, ProcessorStatus::Halted));
+	}
+
+	#[test]
+	fn invalid_opcodes_exit_with_the_opcode() {
+		let machine = run(".data\nvar bad array 99 0\n.text\ngoto bad");
+		let exit = machine.processor.exit;
+		assert_eq!(exit.reason, ExitReason::InvalidOpcode);
+		assert_eq!((exit.code, exit.address), (99, machine.address("bad")));
+		assert_eq!(fields(&exit), (ExitReason::InvalidOpcode as storage, 99, machine.address("bad")));
+	}
+
+	#[test]
+	fn faults_exit_with_the_fault_kind() {
+		let machine = run(".protect .text rx\n.text\n@here\nLoadImmmediateToBus 1\n@write\nSaveValueInBusToLocation here\nHalt");
+		let exit = machine.processor.exit;
+		assert_eq!(exit.reason, ExitReason::Fault);
+		assert_eq!((exit.code, exit.address), (FaultKind::Write as storage, machine.address("write")));
+		assert_eq!(fields(&exit), (ExitReason::Fault as storage, FaultKind::Write as storage, machine.address("write")));
+		assert!(matches!(machine.processor.status, ProcessorStatus::Faulted));
+	}
+
+	#[test]
+	fn budgets_exit_with_the_steps_before_the_instruction_not_run() {
+		let mut machine = Machine::new(".text\n@spin\ngoto spin");
+		// the jump at the start and 4 times around the loop
+		machine.processor.set_step_budget(10);
+		machine.run();
+		let exit = machine.processor.exit;
+		assert_eq!(exit.reason, ExitReason::Budget);
+		assert_eq!((exit.code, exit.address), (10, machine.address("spin")));
+		assert_eq!(machine.processor.next, machine.address("spin"));
+
+		let mut machine = Machine::new(".text\n@spin\ngoto spin");
+		machine.processor.set_step_budget(11);
+		machine.run();
+		// the jump of the loop was not run
+		assert_eq!(fields(&machine.processor.exit), (ExitReason::Budget as storage, 11, machine.address("spin") + 2));
+	}
+
+	#[test]
+	fn unknown_fields_and_running_programs_are_0() {
+		let exit = ExitInfo::new();
+		assert_eq!(fields(&exit), (ExitReason::None as storage, 0, 0));
+		let machine = run(".text\nLoadImmmediateToBus 7\nHalt");
+		assert_eq!(machine.processor.exit.field(3), 0);
+		assert_eq!(machine.processor.exit.field(storage::MAX), 0);
+	}
+}
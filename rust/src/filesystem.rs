@@ -0,0 +1,314 @@
+// Virtual filesystem owned by the processor.
+//
+// Files are byte vectors kept by name, there are no directories. Programs
+// open them by name with the file syscalls and get a handle back. The host
+// preloads and extracts files through the transfer buffer, see
+// r_GetTransferBuffer in lib.rs.
+
+use std::collections::BTreeMap;
+use super::storage;
+use syscalls::SyscallError;
+
+// flags for Open
+pub const FILE_READ: storage = 1;
+pub const FILE_WRITE: storage = 2;
+// makes the file if it doesn't exist
+pub const FILE_CREATE: storage = 4;
+// empties the file when it is opened
+pub const FILE_TRUNCATE: storage = 8;
+// every write goes to the end of the file
+pub const FILE_APPEND: storage = 16;
+const FILE_FLAGS: storage = FILE_READ | FILE_WRITE | FILE_CREATE | FILE_TRUNCATE | FILE_APPEND;
+
+// Seek
+pub const SEEK_START: storage = 0;
+pub const SEEK_CURRENT: storage = 1;
+pub const SEEK_END: storage = 2;
+
+pub const MAX_OPEN_FILES: usize = 16;
+pub const MAX_FILE_SIZE: usize = 1 << 20;
+pub const MAX_FILENAME_LENGTH: usize = 255;
+
+struct Handle {
+	name: String,
+	position: usize,
+	flags: storage,
+}
+
+pub struct Filesystem {
+	files: BTreeMap<String, Vec<u8>>,
+	// handle n is at index n - 1, so 0 is never a valid handle
+	handles: Vec<Option<Handle>>,
+	// bytes the host and the crate pass each other
+	transfer: Vec<u8>,
+}
+
+impl Filesystem {
+	pub fn new() -> Filesystem {
+		let files = BTreeMap::new();
+		let handles = Vec::new();
+		let transfer = Vec::new();
+		Filesystem {
+			files,
+			handles,
+			transfer,
+		}
+	}
+
+	pub fn open(&mut self, name: &str, flags: storage) -> Result<storage, SyscallError> {
+		if flags & !FILE_FLAGS != 0 || flags & (FILE_READ | FILE_WRITE) == 0 {
+			return Err(SyscallError::InvalidFileMode);
+		}
+		if name.is_empty() || name.len() > MAX_FILENAME_LENGTH {
+			return Err(SyscallError::FileNotFound);
+		}
+		let writes = flags & FILE_WRITE != 0;
+		if !self.files.contains_key(name) {
+			if !(writes && flags & FILE_CREATE != 0) {
+				return Err(SyscallError::FileNotFound);
+			}
+			self.files.insert(name.to_string(), Vec::new());
+		}
+		if writes && flags & FILE_TRUNCATE != 0 {
+			if let Some(contents) = self.files.get_mut(name) {
+				contents.clear();
+			}
+		}
+
+		let handle = Handle {
+			name: name.to_string(),
+			position: 0,
+			flags,
+		};
+		if let Some(index) = self.handles.iter().position(|h| h.is_none()) {
+			self.handles[index] = Some(handle);
+			return Ok(index as storage + 1);
+		}
+		if self.handles.len() == MAX_OPEN_FILES {
+			return Err(SyscallError::TooManyOpenFiles);
+		}
+		self.handles.push(Some(handle));
+		return Ok(self.handles.len() as storage);
+	}
+
+	pub fn close(&mut self, handle: storage) -> Result<(), SyscallError> {
+		self.handle(handle)?;
+		self.handles[handle as usize - 1] = None;
+		return Ok(());
+	}
+
+	// reads up to count bytes, fewer at the end of the file, without moving
+	// the position, so a read that can't be stored doesn't use up the file
+	pub fn peek(&mut self, handle: storage, count: usize) -> Result<Vec<u8>, SyscallError> {
+		let (name, position) = {
+			let handle = self.handle(handle)?;
+			if handle.flags & FILE_READ == 0 {
+				return Err(SyscallError::InvalidFileMode);
+			}
+			(handle.name.clone(), handle.position)
+		};
+		let contents = self.files.get(&name).map_or(&[][..], |c| &c[..]);
+		let start = position.min(contents.len());
+		let end = start.saturating_add(count).min(contents.len());
+		return Ok(contents[start .. end].to_vec());
+	}
+
+	// moves the position past bytes that were peeked
+	pub fn advance(&mut self, handle: storage, count: usize) -> Result<(), SyscallError> {
+		let handle = self.handle(handle)?;
+		handle.position = handle.position.saturating_add(count);
+		return Ok(());
+	}
+
+	// writing past the end of the file fills the gap with zeroes
+	pub fn write(&mut self, handle: storage, bytes: &[u8]) -> Result<(), SyscallError> {
+		let (name, position, flags) = {
+			let handle = self.handle(handle)?;
+			(handle.name.clone(), handle.position, handle.flags)
+		};
+		if flags & FILE_WRITE == 0 {
+			return Err(SyscallError::InvalidFileMode);
+		}
+		// the file can only be gone if the host deleted it while it was open
+		let contents = self.files.entry(name).or_default();
+		let start = if flags & FILE_APPEND != 0 { contents.len() } else { position };
+		let end = start + bytes.len();
+		if end > MAX_FILE_SIZE {
+			return Err(SyscallError::FileTooLarge);
+		}
+		if end > contents.len() {
+			contents.resize(end, 0);
+		}
+		contents[start .. end].copy_from_slice(bytes);
+		self.handle(handle)?.position = end;
+		return Ok(());
+	}
+
+	// returns the new position, positions past the end are allowed
+	pub fn seek(&mut self, handle: storage, offset: i32, whence: storage) -> Result<usize, SyscallError> {
+		let (name, position) = {
+			let handle = self.handle(handle)?;
+			(handle.name.clone(), handle.position)
+		};
+		let base = match whence {
+			SEEK_START => 0,
+			SEEK_CURRENT => position as i64,
+			SEEK_END => self.files.get(&name).map_or(0, |c| c.len()) as i64,
+			_ => return Err(SyscallError::InvalidRange),
+		};
+		let target = base + offset as i64;
+		if target < 0 || target > MAX_FILE_SIZE as i64 {
+			return Err(SyscallError::InvalidRange);
+		}
+		self.handle(handle)?.position = target as usize;
+		return Ok(target as usize);
+	}
+
+	// file names in order
+	pub fn name_at(&self, index: usize) -> Option<&str> {
+		return self.files.keys().nth(index).map(|name| name.as_str());
+	}
+
+	pub fn file_count(&self) -> usize {
+		return self.files.len();
+	}
+
+	pub fn contents(&self, name: &str) -> Option<&[u8]> {
+		return self.files.get(name).map(|c| &c[..]);
+	}
+
+	// replaces the file if it exists
+	pub fn insert(&mut self, name: &str, contents: Vec<u8>) -> Result<(), SyscallError> {
+		if name.is_empty() || name.len() > MAX_FILENAME_LENGTH {
+			return Err(SyscallError::FileNotFound);
+		}
+		if contents.len() > MAX_FILE_SIZE {
+			return Err(SyscallError::FileTooLarge);
+		}
+		self.files.insert(name.to_string(), contents);
+		return Ok(());
+	}
+
+	pub fn remove(&mut self, name: &str) -> bool {
+		return self.files.remove(name).is_some();
+	}
+
+	pub fn transfer(&mut self) -> &mut Vec<u8> {
+		return &mut self.transfer;
+	}
+
+	fn handle(&mut self, handle: storage) -> Result<&mut Handle, SyscallError> {
+		if handle == 0 {
+			return Err(SyscallError::UnknownHandle);
+		}
+		return match self.handles.get_mut(handle as usize - 1) {
+			Some(Some(handle)) => Ok(handle),
+			_ => Err(SyscallError::UnknownHandle),
+		};
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(contents: &[u8], flags: storage) -> (Filesystem, storage) {
+		let mut filesystem = Filesystem::new();
+		filesystem.insert("file", contents.to_vec()).unwrap();
+		let handle = filesystem.open("file", flags).unwrap();
+		return (filesystem, handle);
+	}
+
+	// what ReadFile does once the bytes are stored
+	fn read(filesystem: &mut Filesystem, handle: storage, count: usize) -> Result<Vec<u8>, SyscallError> {
+		let bytes = filesystem.peek(handle, count)?;
+		filesystem.advance(handle, bytes.len())?;
+		return Ok(bytes);
+	}
+
+	#[test]
+	fn seek_moves_from_each_base() {
+		let (mut filesystem, handle) = file(b"abcdef", FILE_READ);
+		assert_eq!(filesystem.seek(handle, 2, SEEK_START), Ok(2));
+		assert_eq!(filesystem.seek(handle, 1, SEEK_CURRENT), Ok(3));
+		assert_eq!(read(&mut filesystem, handle, 2), Ok(b"de".to_vec()));
+		assert_eq!(filesystem.seek(handle, -1, SEEK_END), Ok(5));
+		assert_eq!(read(&mut filesystem, handle, 10), Ok(b"f".to_vec()));
+		// past the end reads nothing
+		assert_eq!(filesystem.seek(handle, 4, SEEK_END), Ok(10));
+		assert_eq!(read(&mut filesystem, handle, 1), Ok(Vec::new()));
+	}
+
+	#[test]
+	fn seek_limits() {
+		let (mut filesystem, handle) = file(b"abc", FILE_READ);
+		assert_eq!(filesystem.seek(handle, -4, SEEK_END), Err(SyscallError::InvalidRange));
+		assert_eq!(filesystem.seek(handle, MAX_FILE_SIZE as i32 + 1, SEEK_START), Err(SyscallError::InvalidRange));
+		assert_eq!(filesystem.seek(handle, 0, 3), Err(SyscallError::InvalidRange));
+		// a failed seek doesn't move the position
+		assert_eq!(filesystem.seek(handle, 0, SEEK_CURRENT), Ok(0));
+		assert_eq!(filesystem.seek(handle, MAX_FILE_SIZE as i32, SEEK_START), Ok(MAX_FILE_SIZE));
+		assert_eq!(filesystem.seek(0, 0, SEEK_START), Err(SyscallError::UnknownHandle));
+	}
+
+	#[test]
+	fn writing_past_the_end_fills_with_zeroes() {
+		let (mut filesystem, handle) = file(b"ab", FILE_WRITE);
+		filesystem.seek(handle, 2, SEEK_END).unwrap();
+		filesystem.write(handle, b"c").unwrap();
+		assert_eq!(filesystem.contents("file"), Some(&b"ab\0\0c"[..]));
+	}
+
+	#[test]
+	fn appends_go_to_the_end() {
+		let (mut filesystem, handle) = file(b"abc", FILE_WRITE | FILE_APPEND);
+		filesystem.seek(handle, 0, SEEK_START).unwrap();
+		filesystem.write(handle, b"d").unwrap();
+		assert_eq!(filesystem.seek(handle, 0, SEEK_CURRENT), Ok(4));
+		// a file written through another handle meanwhile is appended to
+		let other = filesystem.open("file", FILE_WRITE).unwrap();
+		filesystem.seek(other, 0, SEEK_END).unwrap();
+		filesystem.write(other, b"e").unwrap();
+		filesystem.write(handle, b"f").unwrap();
+		assert_eq!(filesystem.contents("file"), Some(&b"abcdef"[..]));
+	}
+
+	#[test]
+	fn files_are_capped() {
+		let (mut filesystem, handle) = file(&[], FILE_WRITE | FILE_APPEND);
+		filesystem.write(handle, &vec![1; MAX_FILE_SIZE - 1]).unwrap();
+		assert_eq!(filesystem.write(handle, b"ab"), Err(SyscallError::FileTooLarge));
+		assert_eq!(filesystem.contents("file").map(|c| c.len()), Some(MAX_FILE_SIZE - 1));
+		assert_eq!(filesystem.write(handle, b"a"), Ok(()));
+		assert_eq!(filesystem.write(handle, b"a"), Err(SyscallError::FileTooLarge));
+		assert_eq!(filesystem.insert("big", vec![0; MAX_FILE_SIZE + 1]), Err(SyscallError::FileTooLarge));
+	}
+
+	#[test]
+	fn open_checks_modes_and_handles() {
+		let mut filesystem = Filesystem::new();
+		assert_eq!(filesystem.open("new", FILE_READ | FILE_CREATE), Err(SyscallError::FileNotFound));
+		assert_eq!(filesystem.open("new", 0), Err(SyscallError::InvalidFileMode));
+		assert_eq!(filesystem.open("new", FILE_WRITE | 32), Err(SyscallError::InvalidFileMode));
+		let handle = filesystem.open("new", FILE_WRITE | FILE_CREATE).unwrap();
+		assert_eq!(read(&mut filesystem, handle, 1), Err(SyscallError::InvalidFileMode));
+
+		for _ in 1 .. MAX_OPEN_FILES {
+			filesystem.open("new", FILE_READ).unwrap();
+		}
+		assert_eq!(filesystem.open("new", FILE_READ), Err(SyscallError::TooManyOpenFiles));
+		// closed handles are reused
+		filesystem.close(handle).unwrap();
+		assert_eq!(filesystem.close(handle), Err(SyscallError::UnknownHandle));
+		assert_eq!(filesystem.open("new", FILE_READ), Ok(handle));
+	}
+
+	#[test]
+	fn truncate_empties_the_file() {
+		let (mut filesystem, _) = file(b"abc", FILE_READ | FILE_TRUNCATE);
+		// only when the file is opened for writing
+		assert_eq!(filesystem.contents("file"), Some(&b"abc"[..]));
+		filesystem.open("file", FILE_WRITE | FILE_TRUNCATE).unwrap();
+		assert_eq!(filesystem.contents("file"), Some(&b""[..]));
+	}
+}
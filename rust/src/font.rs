@@ -0,0 +1,167 @@
+// Bitmap font compiled into the crate, used by the DrawText syscall and the
+// text mode device.
+//
+// Every printable ASCII character is 5 pixels wide and 7 pixels high and
+// sits in a 6 * 8 cell, so text needs no extra spacing between characters
+// or lines.
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+pub const CELL_WIDTH: usize = 6;
+pub const CELL_HEIGHT: usize = 8;
+
+const FIRST_CHAR: usize = 32;
+const LAST_CHAR: usize = 126;
+
+// characters the font doesn't have are drawn as this one
+const REPLACEMENT_CHAR: usize = '?' as usize;
+
+// one byte per row, bit 4 is the leftmost pixel
+const GLYPHS: [[u8; GLYPH_HEIGHT]; LAST_CHAR - FIRST_CHAR + 1] = [
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+	[0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+	[0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // "
+	[0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // #
+	[0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // $
+	[0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+	[0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // &
+	[0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00], // '
+	[0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+	[0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+	[0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // *
+	[0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // +
+	[0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ,
+	[0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // -
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // .
+	[0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+	[0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // 0
+	[0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // 1
+	[0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // 2
+	[0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // 3
+	[0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // 4
+	[0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // 5
+	[0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // 6
+	[0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+	[0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // 8
+	[0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // 9
+	[0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // :
+	[0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ;
+	[0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+	[0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // =
+	[0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+	[0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+	[0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // @
+	[0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // A
+	[0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // B
+	[0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // C
+	[0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // D
+	[0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // E
+	[0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // F
+	[0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // G
+	[0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // H
+	[0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // I
+	[0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // J
+	[0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+	[0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // L
+	[0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+	[0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+	[0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // O
+	[0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // P
+	[0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // Q
+	[0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // R
+	[0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // S
+	[0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+	[0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // U
+	[0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // V
+	[0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // W
+	[0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // X
+	[0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // Y
+	[0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // Z
+	[0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // [
+	[0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // backslash
+	[0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ]
+	[0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // _
+	[0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // `
+	[0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], // a
+	[0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], // b
+	[0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], // c
+	[0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], // d
+	[0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], // e
+	[0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], // f
+	[0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], // g
+	[0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // h
+	[0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], // i
+	[0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], // j
+	[0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // k
+	[0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // l
+	[0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], // m
+	[0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // n
+	[0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], // o
+	[0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], // p
+	[0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], // q
+	[0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // r
+	[0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], // s
+	[0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], // t
+	[0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], // u
+	[0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], // v
+	[0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], // w
+	[0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], // x
+	[0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], // y
+	[0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], // z
+	[0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // {
+	[0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // |
+	[0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // }
+	[0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // ~
+];
+
+pub fn glyph(code: u32) -> &'static [u8; GLYPH_HEIGHT] {
+	let code = code as usize;
+	if !(FIRST_CHAR ..= LAST_CHAR).contains(&code) {
+		return &GLYPHS[REPLACEMENT_CHAR - FIRST_CHAR];
+	}
+	return &GLYPHS[code - FIRST_CHAR];
+}
+
+// whether the pixel at (x, y) of the cell is set, the last column and row
+// of a cell are always empty
+pub fn is_set(code: u32, x: usize, y: usize) -> bool {
+	if x >= GLYPH_WIDTH || y >= GLYPH_HEIGHT {
+		return false;
+	}
+	return glyph(code)[y] & (1 << (GLYPH_WIDTH - 1 - x)) != 0;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn glyphs_are_read_left_to_right() {
+		// "!" is the middle column without its second to last row
+		for y in 0 .. GLYPH_HEIGHT {
+			let row: Vec<bool> = (0 .. GLYPH_WIDTH).map(|x| is_set('!' as u32, x, y)).collect();
+			assert_eq!(row, vec![false, false, y != 5, false, false]);
+		}
+		// the top of "A" is 0x0e, its bar 0x1f
+		assert_eq!((0 .. GLYPH_WIDTH).map(|x| is_set('A' as u32, x, 0)).collect::<Vec<bool>>(), vec![false, true, true, true, false]);
+		assert!((0 .. GLYPH_WIDTH).all(|x| is_set('A' as u32, x, 3)));
+	}
+
+	#[test]
+	fn cells_have_an_empty_edge() {
+		for code in FIRST_CHAR as u32 ..= LAST_CHAR as u32 {
+			assert!((0 .. CELL_HEIGHT).all(|y| !is_set(code, GLYPH_WIDTH, y)));
+			assert!((0 .. CELL_WIDTH).all(|x| !is_set(code, x, GLYPH_HEIGHT)));
+		}
+	}
+
+	#[test]
+	fn missing_characters_are_question_marks() {
+		for &code in [0, 7, 127, 0xE9, 0xFFFF_FFFF].iter() {
+			assert_eq!(glyph(code), glyph('?' as u32));
+		}
+		assert_ne!(glyph(' ' as u32), glyph('?' as u32));
+	}
+}
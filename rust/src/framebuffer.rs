@@ -0,0 +1,322 @@
+// Resolves the OUTPUT_SCREEN buffer through the OUTPUT_PALETTE buffer into
+// RGBA8 pixels, so the host only has to copy them into an ImageData.
+//
+// The pixels are only rendered again after something the picture depends
+// on has changed, which the processor reports with mark_dirty.
+//
+// Rendering always goes into the back image, which is then swapped with the
+// front image the host reads. Once a program uses the Present syscall the
+// front image only changes when the program presents, so the host never
+// sees a half drawn frame.
+
+use std::mem;
+use super::{Processor, storage, location};
+use buffers::{BUFFER_OUTPUT_PALETTE, BUFFER_OUTPUT_SCREEN, BUFFER_OUTPUT_SCREEN_SIZE};
+use textmode::{TextMode, visible_columns, visible_rows};
+use font::{self, CELL_WIDTH, CELL_HEIGHT};
+
+// used when the program has no OUTPUT_SCREEN_SIZE buffer
+pub const DEFAULT_WIDTH: usize = 200;
+pub const DEFAULT_HEIGHT: usize = 200;
+
+const MAX_WIDTH: usize = 1024;
+const MAX_HEIGHT: usize = 1024;
+
+const BYTES_PER_PIXEL: usize = 4;
+
+struct Image {
+	width: usize,
+	height: usize,
+	pixels: Vec<u8>,
+}
+
+impl Image {
+	fn new(width: usize, height: usize) -> Image {
+		let pixels = vec![0; width * height * BYTES_PER_PIXEL];
+		Image {
+			width,
+			height,
+			pixels,
+		}
+	}
+
+	fn resize(&mut self, width: usize, height: usize) {
+		if width != self.width || height != self.height {
+			*self = Image::new(width, height);
+		}
+	}
+}
+
+pub struct Framebuffer {
+	front: Image,
+	back: Image,
+	dirty: bool,
+	// set by the first Present, from then on only presenting changes the front image
+	double_buffered: bool,
+	// a presented frame the host hasn't picked up yet
+	presented: bool,
+}
+
+impl Framebuffer {
+	pub fn new() -> Framebuffer {
+		let front = Image::new(DEFAULT_WIDTH, DEFAULT_HEIGHT);
+		let back = Image::new(DEFAULT_WIDTH, DEFAULT_HEIGHT);
+		let dirty = true;
+		let double_buffered = false;
+		let presented = false;
+		Framebuffer {
+			front,
+			back,
+			dirty,
+			double_buffered,
+			presented,
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		return self.front.width;
+	}
+
+	pub fn height(&self) -> usize {
+		return self.front.height;
+	}
+
+	pub fn pixels(&self) -> &[u8] {
+		return &self.front.pixels;
+	}
+
+	pub fn is_dirty(&self) -> bool {
+		return self.dirty;
+	}
+
+	pub fn mark_dirty(&mut self) {
+		self.dirty = true;
+	}
+
+	pub fn is_double_buffered(&self) -> bool {
+		return self.double_buffered;
+	}
+
+	fn swap(&mut self) {
+		mem::swap(&mut self.front, &mut self.back);
+	}
+}
+
+impl Processor {
+	fn is_output_buffer(&self, address: location) -> bool {
+		for kind in [BUFFER_OUTPUT_PALETTE, BUFFER_OUTPUT_SCREEN, BUFFER_OUTPUT_SCREEN_SIZE].iter() {
+			if let Some(buffer) = self.buffers.of_type(*kind) {
+				if buffer.contains(address) {
+					return true;
+				}
+			}
+		}
+		return false;
+	}
+
+	// called on every write to memory
+	pub fn track_framebuffer_write(&mut self, address: location) {
+		if !self.framebuffer.is_dirty() && self.is_output_buffer(address) {
+			self.framebuffer.mark_dirty();
+		}
+	}
+
+	// the text mode device can't reach the framebuffer when it is written to,
+	// so it keeps its own flag
+	pub fn is_framebuffer_dirty(&mut self) -> bool {
+		if self.framebuffer.double_buffered {
+			return self.framebuffer.presented;
+		}
+		let text_changed = match self.mmio.device::<TextMode>() {
+			Some(text) => text.take_dirty(),
+			None => false,
+		};
+		if text_changed {
+			self.framebuffer.mark_dirty();
+		}
+		return self.framebuffer.is_dirty();
+	}
+
+	// called by the host once per frame
+	// returns false if the pixels are the same as last time
+	pub fn render_framebuffer(&mut self) -> bool {
+		if self.framebuffer.double_buffered {
+			let presented = self.framebuffer.presented;
+			self.framebuffer.presented = false;
+			return presented;
+		}
+		if !self.is_framebuffer_dirty() {
+			return false;
+		}
+		self.render_back();
+		self.framebuffer.swap();
+		return true;
+	}
+
+	// Present syscall, shows everything drawn since the last present
+	pub fn present(&mut self) {
+		self.render_back();
+		self.framebuffer.swap();
+		self.framebuffer.double_buffered = true;
+		self.framebuffer.presented = true;
+	}
+
+	fn render_back(&mut self) {
+		let (width, height) = self.screen_size();
+		self.framebuffer.back.resize(width, height);
+
+		let palette = self.read_palette();
+		let (head, length) = match self.buffers.of_type(BUFFER_OUTPUT_SCREEN) {
+			Some(buffer) => (buffer.head, buffer.length as usize),
+			None => (0, 0),
+		};
+
+		for i in 0 .. width * height {
+			let color = if i < length {
+				let index = self.peek(head + i as location) as usize;
+				match palette.get(index) {
+					Some(&(r, g, b)) => [r, g, b, 255],
+					None => [0, 0, 0, 0],
+				}
+			}
+			else {
+				[0, 0, 0, 0]
+			};
+			let p = i * BYTES_PER_PIXEL;
+			self.framebuffer.back.pixels[p .. p + BYTES_PER_PIXEL].copy_from_slice(&color);
+		}
+		self.render_text_mode(&palette);
+
+		self.framebuffer.dirty = false;
+	}
+
+	// draws the text mode cells over the pixels of the screen buffer
+	fn render_text_mode(&mut self, palette: &[(u8, u8, u8)]) {
+		let (width, height) = (self.framebuffer.back.width, self.framebuffer.back.height);
+		let text = match self.mmio.device::<TextMode>() {
+			Some(text) => text,
+			None => return,
+		};
+		if !text.is_enabled() {
+			return;
+		}
+		let rgba = |index: storage| -> Option<[u8; 4]> {
+			return palette.get(index as usize).map(|&(r, g, b)| [r, g, b, 255]);
+		};
+		for row in 0 .. visible_rows(height) {
+			for column in 0 .. visible_columns(width) {
+				let cell = match text.cell(column, row) {
+					Some(cell) if cell.character != 0 || cell.background.is_some() => cell,
+					_ => continue,
+				};
+				for y in 0 .. CELL_HEIGHT {
+					for x in 0 .. CELL_WIDTH {
+						let color = if cell.character != 0 && font::is_set(cell.character, x, y) {
+							rgba(cell.foreground)
+						}
+						else {
+							cell.background.and_then(rgba)
+						};
+						if let Some(color) = color {
+							let p = ((row * CELL_HEIGHT + y) * width + column * CELL_WIDTH + x) * BYTES_PER_PIXEL;
+							self.framebuffer.back.pixels[p .. p + BYTES_PER_PIXEL].copy_from_slice(&color);
+						}
+					}
+				}
+			}
+		}
+	}
+
+	// from the OUTPUT_SCREEN_SIZE buffer, or the default size
+	pub fn screen_size(&self) -> (usize, usize) {
+		let head = match self.buffers.of_type(BUFFER_OUTPUT_SCREEN_SIZE) {
+			Some(buffer) if buffer.length >= 2 => buffer.head,
+			_ => return (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+		};
+		let width = self.peek(head) as usize;
+		let height = self.peek(head + 1) as usize;
+		if width == 0 || height == 0 {
+			return (DEFAULT_WIDTH, DEFAULT_HEIGHT);
+		}
+		return (width.min(MAX_WIDTH), height.min(MAX_HEIGHT));
+	}
+
+	// every 3 values of the OUTPUT_PALETTE buffer are one color,
+	// 'as u8' keeps each value modulo 256
+	fn read_palette(&mut self) -> Vec<(u8, u8, u8)> {
+		let (head, length) = match self.buffers.of_type(BUFFER_OUTPUT_PALETTE) {
+			Some(buffer) => (buffer.head, buffer.length),
+			None => return Vec::new(),
+		};
+		let mut palette = Vec::new();
+		for color in 0 .. length / 3 {
+			let at = head + color * 3;
+			let r = self.peek(at) as u8;
+			let g = self.peek(at + 1) as u8;
+			let b = self.peek(at + 2) as u8;
+			palette.push((r, g, b));
+		}
+		return palette;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::Machine;
+
+	const SIZE: location = 100;
+	const PALETTE: location = 110;
+	const SCREEN: location = 120;
+
+	// a 2 by 2 screen with a palette of two colors, the screen buffer holds
+	// the given indices
+	fn machine(screen: &[storage]) -> Machine {
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(SIZE, &[2, 2], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(PALETTE, &[10, 20, 30, 300, 5, 6], BUFFER_OUTPUT_PALETTE);
+		machine.buffer(SCREEN, screen, BUFFER_OUTPUT_SCREEN);
+		return machine;
+	}
+
+	fn pixels(machine: &Machine) -> Vec<[u8; 4]> {
+		return machine.processor.framebuffer.pixels().chunks(BYTES_PER_PIXEL).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+	}
+
+	#[test]
+	fn indices_go_through_the_palette() {
+		let mut machine = machine(&[0, 1, 1, 7]);
+		assert!(machine.processor.render_framebuffer());
+		assert_eq!((machine.processor.framebuffer.width(), machine.processor.framebuffer.height()), (2, 2));
+		// 300 wraps to 44, an index past the palette is clear
+		assert_eq!(pixels(&machine), vec![[10, 20, 30, 255], [44, 5, 6, 255], [44, 5, 6, 255], [0, 0, 0, 0]]);
+	}
+
+	#[test]
+	fn pixels_past_the_screen_buffer_are_clear() {
+		let mut machine = machine(&[1]);
+		machine.processor.render_framebuffer();
+		assert_eq!(pixels(&machine)[0], [44, 5, 6, 255]);
+		assert_eq!(pixels(&machine)[1 ..], [[0, 0, 0, 0]; 3]);
+	}
+
+	#[test]
+	fn pixels_are_rendered_again_after_a_write() {
+		let mut machine = machine(&[0, 0, 0, 0]);
+		assert!(machine.processor.render_framebuffer());
+		assert!(!machine.processor.render_framebuffer());
+		machine.processor._set_memory_loc(SCREEN + 3, 1);
+		assert!(machine.processor.render_framebuffer());
+		assert_eq!(pixels(&machine)[3], [44, 5, 6, 255]);
+	}
+
+	#[test]
+	fn presented_frames_hide_later_writes() {
+		let mut machine = machine(&[0, 0, 0, 0]);
+		machine.processor.present();
+		machine.processor._set_memory_loc(SCREEN, 1);
+		assert!(machine.processor.render_framebuffer());
+		assert_eq!(pixels(&machine)[0], [10, 20, 30, 255]);
+		assert!(!machine.processor.render_framebuffer());
+	}
+}
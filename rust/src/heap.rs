@@ -0,0 +1,219 @@
+// Bookkeeping for the Allocate and Free syscalls.
+//
+// The heap only hands out space from memory blocks it added itself, so the
+// program block and blocks created with opcode 20 are never touched.
+
+use std::collections::{HashMap, HashSet};
+use super::{MemoryBlock, storage, location, MEM_SIZE};
+use syscalls::SyscallError;
+
+// upper bound on the number of memory blocks the processor may own
+const MAX_REGIONS: usize = 256;
+
+pub struct Heap {
+	// (start, length) of every free range, sorted by start.
+	// Neighbouring ranges are always merged.
+	free: Vec<(location, storage)>,
+	// start -> length of every live allocation
+	allocations: HashMap<location, storage>,
+	// starts of freed allocations that have not been handed out again,
+	// used to tell a double free from a pointer we never returned
+	released: HashSet<location>,
+}
+
+impl Heap {
+	pub fn new() -> Heap {
+		let free = Vec::new();
+		let allocations = HashMap::new();
+		let released = HashSet::new();
+		Heap {
+			free,
+			allocations,
+			released,
+		}
+	}
+
+	// first fit, adding memory blocks to the end of the address space if
+	// no free range is big enough
+	pub fn allocate(&mut self, size: storage, regions: &mut Vec<MemoryBlock>) -> Result<location, SyscallError> {
+		if size == 0 {
+			return Err(SyscallError::InvalidSize);
+		}
+
+		let index = match self.find_free(size) {
+			Some(index) => index,
+			None => self.grow(size, regions)?,
+		};
+
+		let (start, length) = self.free[index];
+		if length == size {
+			self.free.remove(index);
+		}
+		else {
+			self.free[index] = (start + size, length - size);
+		}
+
+		self.allocations.insert(start, size);
+		// a free inside the new allocation is no longer a double free
+		self.released.retain(|&address| address < start || address - start >= size);
+		return Ok(start);
+	}
+
+	// returns the size of the allocation
+	pub fn free(&mut self, address: location) -> Result<storage, SyscallError> {
+		match self.allocations.remove(&address) {
+			Some(size) => {
+				self.release_range(address, size);
+				self.released.insert(address);
+				return Ok(size);
+			},
+			None => {
+				if self.released.contains(&address) {
+					return Err(SyscallError::DoubleFree);
+				}
+				return Err(SyscallError::UnknownPointer);
+			},
+		}
+	}
+
+	fn find_free(&self, size: storage) -> Option<usize> {
+		return self.free.iter().position(|&(_, length)| length >= size);
+	}
+
+	// returns the index of a free range that can hold the allocation
+	fn grow(&mut self, size: storage, regions: &mut Vec<MemoryBlock>) -> Result<usize, SyscallError> {
+		let end = regions.len() * MEM_SIZE;
+
+		// a free range at the very end of the address space can be extended
+		let tail = match self.free.last() {
+			Some(&(start, length)) if start as usize + length as usize == end => length as usize,
+			_ => 0,
+		};
+
+		let needed = size as usize - tail;
+		let blocks = needed.div_ceil(MEM_SIZE);
+		if regions.len() + blocks > MAX_REGIONS {
+			return Err(SyscallError::OutOfMemory);
+		}
+
+		for _ in 0 .. blocks {
+			regions.push(MemoryBlock::new());
+		}
+		self.release_range(end as location, (blocks * MEM_SIZE) as storage);
+
+		return self.find_free(size).ok_or(SyscallError::OutOfMemory);
+	}
+
+	// gives a range back to the free list, merging it with its neighbours
+	fn release_range(&mut self, start: location, length: storage) {
+		let index = self.free.iter()
+			.position(|&(s, _)| s > start)
+			.unwrap_or(self.free.len());
+		self.free.insert(index, (start, length));
+
+		if index + 1 < self.free.len() {
+			let (s, l) = self.free[index];
+			let (next_start, next_length) = self.free[index + 1];
+			if s + l == next_start {
+				self.free[index] = (s, l + next_length);
+				self.free.remove(index + 1);
+			}
+		}
+
+		if index > 0 {
+			let (prev_start, prev_length) = self.free[index - 1];
+			let (s, l) = self.free[index];
+			if prev_start + prev_length == s {
+				self.free[index - 1] = (prev_start, prev_length + l);
+				self.free.remove(index);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn heap() -> (Heap, Vec<MemoryBlock>) {
+		return (Heap::new(), vec![MemoryBlock::new()]);
+	}
+
+	#[test]
+	fn allocates_after_the_program_block() {
+		let (mut heap, mut regions) = heap();
+		let first = heap.allocate(10, &mut regions).unwrap();
+		let second = heap.allocate(5, &mut regions).unwrap();
+		assert_eq!(first, MEM_SIZE as location);
+		assert_eq!(second, first + 10);
+		assert_eq!(regions.len(), 2);
+	}
+
+	#[test]
+	fn reuses_freed_space() {
+		let (mut heap, mut regions) = heap();
+		let first = heap.allocate(10, &mut regions).unwrap();
+		let second = heap.allocate(10, &mut regions).unwrap();
+		heap.free(first).unwrap();
+		assert_eq!(heap.allocate(4, &mut regions), Ok(first));
+		assert_eq!(heap.allocate(6, &mut regions), Ok(first + 4));
+		assert_eq!(heap.allocate(1, &mut regions), Ok(second + 10));
+	}
+
+	#[test]
+	fn merges_free_neighbours() {
+		let (mut heap, mut regions) = heap();
+		let first = heap.allocate(10, &mut regions).unwrap();
+		let second = heap.allocate(10, &mut regions).unwrap();
+		heap.allocate(10, &mut regions).unwrap();
+		heap.free(second).unwrap();
+		heap.free(first).unwrap();
+		assert_eq!(heap.allocate(20, &mut regions), Ok(first));
+	}
+
+	#[test]
+	fn grows_by_whole_blocks() {
+		let (mut heap, mut regions) = heap();
+		let address = heap.allocate(MEM_SIZE as storage + 1, &mut regions).unwrap();
+		assert_eq!(address, MEM_SIZE as location);
+		assert_eq!(regions.len(), 3);
+	}
+
+	#[test]
+	fn rejects_empty_and_huge_allocations() {
+		let (mut heap, mut regions) = heap();
+		assert_eq!(heap.allocate(0, &mut regions), Err(SyscallError::InvalidSize));
+		assert_eq!(heap.allocate((MAX_REGIONS * MEM_SIZE) as storage, &mut regions), Err(SyscallError::OutOfMemory));
+		assert_eq!(regions.len(), 1);
+	}
+
+	#[test]
+	fn reports_double_free() {
+		let (mut heap, mut regions) = heap();
+		let address = heap.allocate(10, &mut regions).unwrap();
+		assert_eq!(heap.free(address), Ok(10));
+		assert_eq!(heap.free(address), Err(SyscallError::DoubleFree));
+	}
+
+	#[test]
+	fn reports_unknown_pointer() {
+		let (mut heap, mut regions) = heap();
+		let address = heap.allocate(10, &mut regions).unwrap();
+		assert_eq!(heap.free(0), Err(SyscallError::UnknownPointer));
+		assert_eq!(heap.free(address + 1), Err(SyscallError::UnknownPointer));
+	}
+
+	#[test]
+	fn free_inside_a_new_allocation_is_an_unknown_pointer() {
+		let (mut heap, mut regions) = heap();
+		let first = heap.allocate(4, &mut regions).unwrap();
+		let second = heap.allocate(4, &mut regions).unwrap();
+		heap.free(first).unwrap();
+		heap.free(second).unwrap();
+		// covers both of the freed allocations
+		let address = heap.allocate(8, &mut regions).unwrap();
+		assert_eq!(address, first);
+		assert_eq!(heap.free(second), Err(SyscallError::UnknownPointer));
+		assert_eq!(heap.free(first), Ok(8));
+	}
+}
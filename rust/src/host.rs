@@ -0,0 +1,57 @@
+// Syscalls the crate doesn't handle itself go to the host.
+//
+// In the browser that is js_syscall, see js/src/utils/wasmWorker/syscalls.ts.
+// Native builds (dsl-run) have no JS to call, so the few host syscalls are
+// handled here instead.
+
+use super::{Processor, storage, jsint};
+use syscalls::{SYSCALL_OK, SYSCALL_ERROR};
+#[cfg(not(target_arch = "wasm32"))]
+use terminal::Terminal;
+
+pub const ALERT: storage = 30;
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+	fn js_syscall(code: jsint, param: jsint) -> jsint;
+}
+
+impl Processor {
+	#[cfg(target_arch = "wasm32")]
+	pub fn host_syscall(&mut self, code: storage, param: storage) -> storage {
+		unsafe {
+			return js_syscall(code as jsint, param as jsint) as storage;
+		}
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn host_syscall(&mut self, code: storage, param: storage) -> storage {
+		match code {
+			ALERT => {
+				// zero terminated, one byte per word, like the JS Alert
+				let mut bytes = Vec::new();
+				let mut address = param;
+				loop {
+					let value = self.peek(address);
+					if value == 0 {
+						break;
+					}
+					bytes.push(value as u8);
+					address = address.wrapping_add(1);
+				}
+				// through the terminal, so the Runner writes it to its output in
+				// order with everything else the program printed
+				bytes.push(b'\n');
+				if let Some(terminal) = self.mmio.device::<Terminal>() {
+					for &byte in bytes.iter() {
+						terminal.write_byte(byte);
+					}
+				}
+				return SYSCALL_OK;
+			},
+			_ => {
+				return SYSCALL_ERROR;
+			},
+		}
+	}
+}
@@ -0,0 +1,246 @@
+// Interrupt controller.
+//
+// Devices and the host raise numbered interrupt lines. When interrupts are
+// enabled and a line is pending, the processor saves next, the bus and the
+// ALU, disables interrupts and jumps to the handler the vector table holds
+// for the line. The return-from-interrupt opcode restores all of it.
+//
+// The vector table is INTERRUPT_LINES words in VM memory, word n is the
+// address of the handler for line n. Lines whose word is 0 are ignored.
+
+use super::{Processor, ALU, storage, location};
+use protection::FaultKind;
+
+pub const INTERRUPT_LINES: storage = 32;
+
+// lines raised by the built in devices
+pub const IRQ_TIMER: storage = 0;
+pub const IRQ_KEYBOARD: storage = 1;
+pub const IRQ_FRAME: storage = 2;
+pub const IRQ_POINTER: storage = 3;
+pub const IRQ_TERMINAL: storage = 4;
+
+// handlers that enable interrupts again can be interrupted, but only this deep
+const MAX_NESTING: usize = 16;
+
+struct SavedState {
+	next: location,
+	bus: storage,
+	alu: ALU,
+	enabled: bool,
+}
+
+pub struct Interrupts {
+	enabled: bool,
+	// 0 until the program sets one
+	vector_table: location,
+	// bit n is set while line n is pending
+	pending: u32,
+	saved: Vec<SavedState>,
+}
+
+impl Interrupts {
+	pub fn new() -> Interrupts {
+		let enabled = false;
+		let vector_table = 0;
+		let pending = 0;
+		let saved = Vec::new();
+		Interrupts {
+			enabled,
+			vector_table,
+			pending,
+			saved,
+		}
+	}
+
+	// returns false for an unknown line
+	pub fn raise(&mut self, line: storage) -> bool {
+		if line >= INTERRUPT_LINES {
+			return false;
+		}
+		self.pending |= 1 << line;
+		return true;
+	}
+
+	// the lowest pending line goes first
+	fn next_line(&self) -> Option<storage> {
+		if !self.enabled || self.vector_table == 0 || self.pending == 0 || self.saved.len() == MAX_NESTING {
+			return None;
+		}
+		return Some(self.pending.trailing_zeros());
+	}
+}
+
+impl Processor {
+	pub fn raise_interrupt(&mut self, line: storage) -> bool {
+		return self.interrupts.raise(line);
+	}
+
+	// opcode 34 and 35
+	pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+		self.interrupts.enabled = enabled;
+	}
+
+	// opcode 37
+	pub fn set_vector_table(&mut self, address: location) {
+		self.interrupts.vector_table = address;
+	}
+
+	// called before every instruction
+	// returns true if the processor jumped to a handler instead of running
+	// the instruction
+	pub fn dispatch_interrupt(&mut self) -> bool {
+		let mut handler = 0;
+		while handler == 0 {
+			let line = match self.interrupts.next_line() {
+				Some(line) => line,
+				None => return false,
+			};
+			self.interrupts.pending &= !(1 << line);
+			handler = match self.read_memory(self.interrupts.vector_table.wrapping_add(line)) {
+				Some(handler) => handler,
+				None => return false,
+			};
+		}
+		self.interrupts.saved.push(SavedState {
+			next: self.next,
+			bus: self.bus,
+			alu: self.alu.clone(),
+			enabled: self.interrupts.enabled,
+		});
+		self.interrupts.enabled = false;
+		self.next = handler;
+		return true;
+	}
+
+	// opcode 36
+	// next is restored as it was, so the caller must not move it on
+	// None if there is no handler to return from, which faults
+	pub fn return_from_interrupt(&mut self) -> Option<()> {
+		match self.interrupts.saved.pop() {
+			Some(state) => {
+				self.next = state.next;
+				self.bus = state.bus;
+				self.alu = state.alu;
+				self.interrupts.enabled = state.enabled;
+				return Some(());
+			},
+			None => {
+				let current = self.next;
+				self.raise_fault(FaultKind::InvalidReturn, current);
+				return None;
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ProcessorStatus;
+	use testing::Machine;
+
+	#[test]
+	fn return_restores_the_interrupted_state() {
+		let mut machine = Machine::new("
+.data
+var vectors array 0 handler
+var seen number 0
+.text
+SetInterruptVectorTable vectors
+EnableInterrupts
+LoadImmmediateToBus 7
+Pause
+Pause
+@handler
+LoadImmmediateToBus 99
+SaveValueInBusToLocation seen
+ReturnFromInterrupt
+");
+		machine.run();
+		machine.processor.raise_interrupt(IRQ_KEYBOARD);
+		machine.run();
+		assert_eq!(machine.word("seen", 0), 99);
+		assert_eq!(machine.processor.bus, 7);
+		assert!(machine.processor.interrupts.enabled);
+		assert!(machine.processor.interrupts.saved.is_empty());
+	}
+
+	#[test]
+	fn lowest_line_goes_first_and_lines_without_handlers_are_dropped() {
+		let mut machine = Machine::new("
+.data
+var vectors array 0 first second
+var one number 0
+var seen_one number 0
+.text
+SetInterruptVectorTable vectors
+Pause
+EnableInterrupts
+Noop
+Noop
+Pause
+@first
+LoadImmmediateToBus 1
+SaveValueInBusToLocation one
+ReturnFromInterrupt
+@second
+LoadValueAtAddressIntoBus one
+SaveValueInBusToLocation seen_one
+ReturnFromInterrupt
+");
+		machine.run();
+		// raised while interrupts are off, they wait until they are enabled
+		machine.processor.raise_interrupt(IRQ_FRAME);
+		machine.processor.raise_interrupt(IRQ_KEYBOARD);
+		machine.processor.raise_interrupt(IRQ_TIMER);
+		assert!(!machine.processor.dispatch_interrupt());
+		machine.run();
+		assert_eq!(machine.word("seen_one", 0), 1);
+		assert_eq!(machine.processor.interrupts.pending, 0);
+	}
+
+	#[test]
+	fn handlers_nest_only_so_deep() {
+		let mut machine = Machine::new("
+.data
+var vectors array 0 handler
+.text
+SetInterruptVectorTable vectors
+EnableInterrupts
+Pause
+@handler
+EnableInterrupts
+Pause
+ReturnFromInterrupt
+");
+		machine.run();
+		for depth in 1 ..= MAX_NESTING {
+			machine.processor.raise_interrupt(IRQ_KEYBOARD);
+			machine.run();
+			assert_eq!(machine.processor.interrupts.saved.len(), depth);
+		}
+		// the line stays pending until a handler returns
+		machine.processor.raise_interrupt(IRQ_KEYBOARD);
+		machine.processor.step();
+		assert_eq!(machine.processor.interrupts.saved.len(), MAX_NESTING - 1);
+		machine.processor.step();
+		assert_eq!(machine.processor.interrupts.saved.len(), MAX_NESTING);
+	}
+
+	#[test]
+	fn returning_without_an_interrupt_faults() {
+		let mut machine = Machine::new(".text\nReturnFromInterrupt");
+		machine.run();
+		assert!(matches!(machine.processor.status, ProcessorStatus::Faulted));
+		assert_eq!(machine.processor.fault.map(|fault| fault.kind), Some(FaultKind::InvalidReturn));
+	}
+
+	#[test]
+	fn unknown_lines_are_refused() {
+		let mut interrupts = Interrupts::new();
+		assert!(!interrupts.raise(INTERRUPT_LINES));
+		assert!(interrupts.raise(INTERRUPT_LINES - 1));
+		assert_eq!(interrupts.pending, 1 << (INTERRUPT_LINES - 1));
+	}
+}
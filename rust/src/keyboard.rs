@@ -0,0 +1,188 @@
+// Keyboard device.
+//
+// The host pushes every key press and release with r_PushKeyEvent as it
+// happens, so a tap between two frames is never lost. Programs take the
+// events one at a time, in order, with the PollKey syscall or by reading
+// KEYBOARD_NEXT, and can ask whether a key is held with KeyState.
+//
+// Key codes are the browser's KeyboardEvent.keyCode values.
+
+use std::any::Any;
+use std::collections::{HashSet, VecDeque};
+use super::{storage, location};
+use mmio::{Device, MMIO_BASE};
+
+pub const KEYBOARD_BASE: location = MMIO_BASE + 0x2000;
+
+// registers
+// read only, number of events in the queue
+pub const KEYBOARD_PENDING: storage = 0;
+// reading takes the next event, 0 if there is none
+pub const KEYBOARD_NEXT: storage = 1;
+// events that didn't fit in the queue, writing clears it
+pub const KEYBOARD_DROPPED: storage = 2;
+
+pub const KEYBOARD_SIZE: storage = 3;
+
+pub const MAX_KEY_EVENTS: usize = 64;
+pub const MAX_KEY_CODE: storage = 0xFFFF;
+
+// an event is the key code, with this bit set for a press
+pub const KEY_EVENT_DOWN: storage = 1 << 16;
+
+pub struct Keyboard {
+	events: VecDeque<storage>,
+	held: HashSet<storage>,
+	dropped: storage,
+}
+
+impl Keyboard {
+	pub fn new() -> Keyboard {
+		let events = VecDeque::with_capacity(MAX_KEY_EVENTS);
+		let held = HashSet::new();
+		let dropped = 0;
+		Keyboard {
+			events,
+			held,
+			dropped,
+		}
+	}
+
+	// returns false if the event was dropped, either because the code is out
+	// of range or because the queue is full
+	// the held keys are updated either way, so KeyState is always right
+	pub fn push(&mut self, code: storage, down: bool) -> bool {
+		if code == 0 || code > MAX_KEY_CODE {
+			return false;
+		}
+		if down {
+			self.held.insert(code);
+		}
+		else {
+			self.held.remove(&code);
+		}
+		if self.events.len() == MAX_KEY_EVENTS {
+			self.dropped = self.dropped.saturating_add(1);
+			return false;
+		}
+		self.events.push_back(if down { code | KEY_EVENT_DOWN } else { code });
+		return true;
+	}
+
+	pub fn next_event(&mut self) -> storage {
+		return self.events.pop_front().unwrap_or(0);
+	}
+
+	pub fn is_held(&self, code: storage) -> bool {
+		return self.held.contains(&code);
+	}
+}
+
+impl Device for Keyboard {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			KEYBOARD_PENDING => {
+				return self.events.len() as storage;
+			},
+			KEYBOARD_NEXT => {
+				return self.events.front().copied().unwrap_or(0);
+			},
+			KEYBOARD_DROPPED => {
+				return self.dropped;
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+
+	fn read(&mut self, offset: storage) -> storage {
+		if offset == KEYBOARD_NEXT {
+			return self.next_event();
+		}
+		return self.peek(offset);
+	}
+
+	fn write(&mut self, offset: storage, _value: storage) {
+		if offset == KEYBOARD_DROPPED {
+			self.dropped = 0;
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::Machine;
+
+	#[test]
+	fn events_come_out_in_order() {
+		let mut keyboard = Keyboard::new();
+		assert!(keyboard.push(65, true));
+		assert!(keyboard.push(66, true));
+		assert!(keyboard.push(65, false));
+		assert_eq!(keyboard.peek(KEYBOARD_PENDING), 3);
+		assert_eq!(keyboard.read(KEYBOARD_NEXT), 65 | KEY_EVENT_DOWN);
+		assert_eq!(keyboard.next_event(), 66 | KEY_EVENT_DOWN);
+		assert_eq!(keyboard.next_event(), 65);
+		// an empty queue reads 0
+		assert_eq!(keyboard.read(KEYBOARD_NEXT), 0);
+		assert!(keyboard.is_held(66));
+		assert!(!keyboard.is_held(65));
+	}
+
+	#[test]
+	fn a_full_queue_drops_events_but_keeps_held_keys() {
+		let mut keyboard = Keyboard::new();
+		for _ in 0 .. MAX_KEY_EVENTS {
+			assert!(keyboard.push(65, true));
+		}
+		assert!(!keyboard.push(66, true));
+		assert!(!keyboard.push(67, true));
+		assert_eq!(keyboard.peek(KEYBOARD_PENDING), MAX_KEY_EVENTS as storage);
+		assert_eq!(keyboard.peek(KEYBOARD_DROPPED), 2);
+		assert!(keyboard.is_held(66));
+
+		keyboard.write(KEYBOARD_DROPPED, 0);
+		assert_eq!(keyboard.peek(KEYBOARD_DROPPED), 0);
+		// taking an event makes room again
+		keyboard.next_event();
+		assert!(keyboard.push(66, false));
+		assert!(!keyboard.is_held(66));
+	}
+
+	#[test]
+	fn codes_out_of_range_are_refused() {
+		let mut keyboard = Keyboard::new();
+		assert!(!keyboard.push(0, true));
+		assert!(!keyboard.push(MAX_KEY_CODE + 1, true));
+		assert_eq!(keyboard.peek(KEYBOARD_PENDING), 0);
+		assert_eq!(keyboard.peek(KEYBOARD_DROPPED), 0);
+	}
+
+	#[test]
+	fn syscalls_take_events_and_report_held_keys() {
+		let mut machine = Machine::new("
+.data
+var first number 0
+.text
+Syscall 60
+SaveValueInBusToLocation first
+LoadImmmediateToBus 66
+Syscall 61
+");
+		{
+			let keyboard = machine.processor.mmio.device::<Keyboard>().unwrap();
+			keyboard.push(65, true);
+			keyboard.push(66, true);
+		}
+		machine.run();
+		assert_eq!(machine.word("first", 0), 65 | KEY_EVENT_DOWN);
+		assert_eq!(machine.processor.bus, 1);
+		assert_eq!(machine.processor.peek(KEYBOARD_BASE + KEYBOARD_PENDING), 1);
+	}
+}
@@ -3,6 +3,37 @@
 #[macro_use] 
 extern crate lazy_static;
 
+mod syscalls;
+mod heap;
+mod protection;
+mod mmio;
+mod buffers;
+mod framebuffer;
+mod drawing;
+mod font;
+mod textmode;
+mod tiles;
+mod keyboard;
+mod pointer;
+mod terminal;
+mod audio;
+mod filesystem;
+mod clock;
+mod interrupts;
+mod random;
+mod exit;
+mod host;
+mod opcodes;
+pub mod listing;
+pub mod assembler;
+pub mod object;
+pub mod stdlib;
+pub mod debuginfo;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod native;
+#[cfg(test)]
+mod testing;
+
 use std::env;
 use std::fs::File;
 use std::time::Duration;
@@ -13,6 +44,26 @@ use std::sync::Mutex;
 use std::collections::{HashSet, HashMap};
 use std::os::raw::{c_double, c_float, c_int};
 
+use heap::Heap;
+use protection::{Protection, Fault, FaultKind, ImageRange, PERM_READ, PERM_WRITE, PERM_EXECUTE};
+use mmio::Mmio;
+use buffers::{BufferRegistry, BUFFER_NONE};
+use framebuffer::Framebuffer;
+use textmode::{TextMode, TEXT_MODE_BASE, TEXT_MODE_SIZE};
+use tiles::{TileEngine, TILES_BASE, TILES_SIZE};
+use keyboard::{Keyboard, KEYBOARD_BASE, KEYBOARD_SIZE};
+use pointer::{Pointer, POINTER_BASE, POINTER_SIZE};
+use terminal::{Terminal, TERMINAL_BASE, TERMINAL_SIZE};
+use audio::{Audio, AUDIO_BASE, AUDIO_SIZE};
+use random::{Random, RANDOM_BASE, RANDOM_SIZE};
+use exit::{ExitInfo, ExitReason};
+use filesystem::Filesystem;
+use clock::Clock;
+use interrupts::{Interrupts, IRQ_TIMER, IRQ_KEYBOARD, IRQ_FRAME, IRQ_POINTER, IRQ_TERMINAL};
+use syscalls::SyscallError;
+use debuginfo::DebugInfo;
+use listing::{Listing, LOAD_ADDRESS};
+
 enum StopCode {
 	Pause,
 	Halt,
@@ -25,18 +76,24 @@ enum ProcessorStatus {
 	NotStarted,
 	Running,
 	Empty,
+	Faulted,
+	// presented a frame, runs again after r_NextFrame
+	WaitingForFrame,
+	// ReadLine found no line, runs again once the host pushed one
+	WaitingForInput,
+	// in Sleep, runs again once the clock reached the wake up time
+	Sleeping,
 }
 
 const MEM_SIZE: usize = 1024 * 32;
 
+#[allow(non_camel_case_types)]
 type storage = u32;
+#[allow(non_camel_case_types)]
 type location = u32;
+#[allow(non_camel_case_types)]
 type jsint = c_int;
 
-extern "C" {
-	fn js_syscall(code: jsint, param: jsint) -> jsint;
-}
-
 #[no_mangle]
 pub extern "C" fn r_SetBreakpoint(n: jsint) {
 	SetBreakpoint(n as u32);
@@ -84,7 +141,593 @@ pub extern "C" fn r_GetProcessorStatus() -> jsint {
 		ProcessorStatus::NotStarted => 2,
 		ProcessorStatus::Running => 3,
 		ProcessorStatus::Empty => 4,
+		ProcessorStatus::Faulted => 5,
+		ProcessorStatus::WaitingForFrame => 6,
+		ProcessorStatus::WaitingForInput => 7,
+		ProcessorStatus::Sleeping => 8,
+	}
+}
+
+// moves the clock on, a sleeping program whose wake up time was reached is
+// paused again so it can be continued
+#[no_mangle]
+pub extern "C" fn r_AdvanceClock(milliseconds: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	program.Processor.advance_clock(milliseconds.max(0) as u64);
+}
+
+// the low 32 bits of the tick count, in milliseconds
+#[no_mangle]
+pub extern "C" fn r_GetClockTicks() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.clock.ticks() as u32 as jsint;
+}
+
+// milliseconds until a sleeping program wakes up, -1 if it isn't sleeping
+#[no_mangle]
+pub extern "C" fn r_GetSleepRemaining() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.clock.sleep_remaining() {
+		Some(remaining) => remaining.min(i32::MAX as u64) as jsint,
+		None => -1,
+	};
+}
+
+// mode 0 is driven by r_AdvanceClock, mode 1 ticks every
+// instructionsPerTick instructions
+// returns false for an unknown mode
+#[no_mangle]
+pub extern "C" fn r_SetClockMode(mode: jsint, instructionsPerTick: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.clock.set_mode(mode as storage, instructionsPerTick.max(1) as u32);
+}
+
+// the host calls this once per frame, a program waiting for the next frame
+// is paused again so it can be continued
+// returns false if the program wasn't waiting
+#[no_mangle]
+pub extern "C" fn r_NextFrame() -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.next_frame();
+}
+
+#[no_mangle]
+pub extern "C" fn r_IsDoubleBuffered() -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.framebuffer.is_double_buffered();
+}
+
+// down is true for a press and false for a release
+// returns false if the event was dropped
+#[no_mangle]
+pub extern "C" fn r_PushKeyEvent(code: jsint, down: bool) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let pushed = match program.Processor.mmio.device::<Keyboard>() {
+		Some(keyboard) => keyboard.push(code as storage, down),
+		None => false,
+	};
+	if pushed {
+		program.Processor.raise_interrupt(IRQ_KEYBOARD);
+	}
+	return pushed;
+}
+
+// one byte of input at a time, the host encodes text as utf8
+// returns false if the input was closed or is full
+#[no_mangle]
+pub extern "C" fn r_PushTerminalInput(byte: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let pushed = match program.Processor.mmio.device::<Terminal>() {
+		Some(terminal) => terminal.push_input(byte as u8),
+		None => false,
+	};
+	if pushed {
+		program.Processor.raise_interrupt(IRQ_TERMINAL);
+	}
+	program.Processor.wake_on_input();
+	return pushed;
+}
+
+// no more input will come, ReadLine returns what is left and then reports
+// the end of the input
+#[no_mangle]
+pub extern "C" fn r_CloseTerminalInput() {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if let Some(terminal) = program.Processor.mmio.device::<Terminal>() {
+		terminal.close_input();
+	}
+	program.Processor.wake_on_input();
+}
+
+// output is read through the pointer and length, then cleared with
+// r_ClearTerminalOutput
+#[no_mangle]
+pub extern "C" fn r_GetTerminalOutputPointer() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.mmio.device::<Terminal>() {
+		Some(terminal) => terminal.output().as_ptr() as jsint,
+		None => 0,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetTerminalOutputLength() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.mmio.device::<Terminal>() {
+		Some(terminal) => terminal.output().len() as jsint,
+		None => 0,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_ClearTerminalOutput() {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if let Some(terminal) = program.Processor.mmio.device::<Terminal>() {
+		terminal.clear_output();
+	}
+}
+
+// The host passes file names and contents through the transfer buffer.
+// It sizes the buffer with r_GetTransferBuffer and writes or reads the
+// bytes at the returned pointer.
+#[no_mangle]
+pub extern "C" fn r_GetTransferBuffer(size: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let transfer = program.Processor.files.transfer();
+	transfer.resize(size.max(0) as usize, 0);
+	return transfer.as_ptr() as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetTransferLength() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.files.transfer().len() as jsint;
+}
+
+// the first nameLength bytes of the transfer buffer are the name, the rest
+// are the contents, an existing file is replaced
+#[no_mangle]
+pub extern "C" fn r_PreloadFile(nameLength: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let transfer = files.transfer().clone();
+	let split = (nameLength.max(0) as usize).min(transfer.len());
+	let name = String::from_utf8_lossy(&transfer[.. split]).into_owned();
+	return files.insert(&name, transfer[split ..].to_vec()).is_ok();
+}
+
+// the transfer buffer holds the name, it is replaced by the contents
+// returns the length of the file, or -1 if there is no such file
+#[no_mangle]
+pub extern "C" fn r_ExtractFile(nameLength: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let name = transfer_name(files, nameLength);
+	let contents = match files.contents(&name) {
+		Some(contents) => contents.to_vec(),
+		None => return -1,
+	};
+	*files.transfer() = contents;
+	return files.transfer().len() as jsint;
+}
+
+// the transfer buffer holds the name
+#[no_mangle]
+pub extern "C" fn r_DeleteFile(nameLength: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let name = transfer_name(files, nameLength);
+	return files.remove(&name);
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFileCount() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.files.file_count() as jsint;
+}
+
+// puts the name of the file at the index, in name order, in the transfer
+// buffer and returns its length, or -1 past the last file
+#[no_mangle]
+pub extern "C" fn r_GetFileNameAt(index: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let name = match files.name_at(index.max(0) as usize) {
+		Some(name) => name.as_bytes().to_vec(),
+		None => return -1,
+	};
+	*files.transfer() = name;
+	return files.transfer().len() as jsint;
+}
+
+fn transfer_name(files: &mut Filesystem, length: jsint) -> String {
+	let transfer = files.transfer();
+	let length = (length.max(0) as usize).min(transfer.len());
+	return String::from_utf8_lossy(&transfer[.. length]).into_owned();
+}
+
+// the transfer buffer holds DSL assembly, it is replaced by the numeric
+// listing, or by the errors one per line if it doesn't assemble
+// .include reads from the program's files, the debug info of the listing
+// is loaded, see r_LoadDebugInfo
+#[no_mangle]
+pub extern "C" fn r_Assemble(sourceLength: jsint) -> bool {
+	let program: &mut Program = &mut MAIN_PROGRAM.lock().unwrap();
+	let debug = &mut program.Debug;
+	let files = &mut program.Processor.files;
+	let source = transfer_name(files, sourceLength);
+	let result = {
+		let mut read_file = |name: &str| match files.contents(name) {
+			Some(contents) => Ok(String::from_utf8_lossy(contents).into_owned()),
+			None => Err("no such file".to_string()),
+		};
+		assembler::assemble_with_includes("", &source, &mut read_file)
+	};
+	let (assembled, text) = match result {
+		Ok(assembly) => {
+			*debug = assembly.debug_info();
+			(true, assembly.to_listing())
+		},
+		Err(errors) => (false, errors.iter().map(|error| format!("{}\n", error)).collect()),
+	};
+	*files.transfer() = text.into_bytes();
+	return assembled;
+}
+
+// like r_Assemble, but the listing is replaced by an object file, see object.rs
+#[no_mangle]
+pub extern "C" fn r_AssembleObject(sourceLength: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let source = transfer_name(files, sourceLength);
+	let result = {
+		let mut read_file = |name: &str| match files.contents(name) {
+			Some(contents) => Ok(String::from_utf8_lossy(contents).into_owned()),
+			None => Err("no such file".to_string()),
+		};
+		assembler::assemble_object("", &source, &mut read_file)
+	};
+	let (assembled, bytes) = match result {
+		Ok(object) => (true, object.to_bytes()),
+		Err(errors) => (false, errors.iter().map(|error| format!("{}\n", error)).collect::<String>().into_bytes()),
+	};
+	*files.transfer() = bytes;
+	return assembled;
+}
+
+// the transfer buffer holds the names of program files, one per line, the
+// first is the program, .dsla files are assembled and the others are object
+// files, it is replaced by the numeric listing or the errors one per line
+// like r_Assemble, the debug info of the listing is loaded
+#[no_mangle]
+pub extern "C" fn r_Link(namesLength: jsint) -> bool {
+	let program: &mut Program = &mut MAIN_PROGRAM.lock().unwrap();
+	let debug = &mut program.Debug;
+	let files = &mut program.Processor.files;
+	let names = transfer_name(files, namesLength);
+	let mut objects = Vec::new();
+	let mut errors = Vec::new();
+	for name in names.lines().filter(|name| !name.is_empty()) {
+		let contents = match files.contents(name) {
+			Some(contents) => contents.to_vec(),
+			None => {
+				errors.push(format!("{}: no such file", name));
+				continue;
+			},
+		};
+		if name.ends_with(".dsla") {
+			let mut read_file = |include: &str| match files.contents(include) {
+				Some(contents) => Ok(String::from_utf8_lossy(contents).into_owned()),
+				None => Err("no such file".to_string()),
+			};
+			match assembler::assemble_object(name, &String::from_utf8_lossy(&contents), &mut read_file) {
+				Ok(object) => objects.push(object),
+				Err(asm_errors) => errors.extend(asm_errors.iter().map(|error| error.to_string())),
+			}
+		}
+		else {
+			match object::Object::from_bytes(&contents) {
+				Ok(mut object) => {
+					if object.files.is_empty() {
+						object.files.push(name.to_string());
+					}
+					objects.push(object);
+				},
+				Err(error) => errors.push(format!("{}: {}", name, error)),
+			}
+		}
+	}
+	if errors.is_empty() {
+		match object::link(&objects) {
+			Ok(assembly) => {
+				*debug = assembly.debug_info();
+				*files.transfer() = assembly.to_listing().into_bytes();
+				return true;
+			},
+			Err(link_errors) => errors.extend(link_errors.iter().map(|error| error.to_string())),
+		}
 	}
+	*files.transfer() = errors.iter().map(|error| format!("{}\n", error)).collect::<String>().into_bytes();
+	return false;
+}
+
+// the transfer buffer holds a debug section, see debuginfo.rs, that maps
+// the addresses of the program to its source, false if it can't be read
+#[no_mangle]
+pub extern "C" fn r_LoadDebugInfo(length: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let bytes = {
+		let transfer = program.Processor.files.transfer();
+		let length = (length.max(0) as usize).min(transfer.len());
+		transfer[.. length].to_vec()
+	};
+	return match DebugInfo::from_bytes(&bytes) {
+		Ok(debug) => {
+			program.Debug = debug;
+			true
+		},
+		Err(_) => false,
+	};
+}
+
+// puts the debug section of the program in the transfer buffer, to save it
+// and load it later, and returns its length
+#[no_mangle]
+pub extern "C" fn r_ExportDebugInfo() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let bytes = program.Debug.to_bytes();
+	let transfer = program.Processor.files.transfer();
+	*transfer = bytes;
+	return transfer.len() as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_ClearDebugInfo() {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	program.Debug = DebugInfo::new();
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetDebugFileCount() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Debug.files.len() as jsint;
+}
+
+// puts the name of the source file at the index in the transfer buffer and
+// returns its length, or -1 past the last file
+#[no_mangle]
+pub extern "C" fn r_GetDebugFileName(index: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let name = match program.Debug.files.get(index.max(0) as usize) {
+		Some(name) => name.as_bytes().to_vec(),
+		None => return -1,
+	};
+	let transfer = program.Processor.files.transfer();
+	*transfer = name;
+	return transfer.len() as jsint;
+}
+
+// field 0 is the index of the file, 1 the line, 2 the column and 3 the
+// length of the source the word at the address came from, 4 the first
+// address made from it, -1 if the address has no source
+#[no_mangle]
+pub extern "C" fn r_GetSourceLocation(address: jsint, field: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Debug.range_at(address as location).and_then(|range| range.field(field as storage)) {
+		Some(value) => value as jsint,
+		None => -1,
+	};
+}
+
+// the first address of code from the line of the file, -1 if there is none
+#[no_mangle]
+pub extern "C" fn r_GetLineAddress(file: jsint, line: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Debug.line_address(file.max(0) as usize, line.max(0) as usize) {
+		Some(address) => address as jsint,
+		None => -1,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetSymbolCount() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Debug.symbols.len() as jsint;
+}
+
+// the transfer buffer holds the name of a label or variable, returns the
+// index of its symbol or -1
+#[no_mangle]
+pub extern "C" fn r_FindSymbol(nameLength: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let name = transfer_name(&mut program.Processor.files, nameLength);
+	return match program.Debug.symbols.iter().position(|symbol| symbol.name == name) {
+		Some(index) => index as jsint,
+		None => -1,
+	};
+}
+
+// the index of the label the address is in, the closest one at or before
+// it, or -1
+#[no_mangle]
+pub extern "C" fn r_GetLabelAt(address: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let debug = &program.Debug;
+	return match debug.label_at(address as location) {
+		Some(label) => debug.symbols.iter().position(|symbol| ::std::ptr::eq(symbol, label)).map_or(-1, |index| index as jsint),
+		None => -1,
+	};
+}
+
+// puts the name of the symbol in the transfer buffer and returns its
+// length, or -1 past the last symbol
+#[no_mangle]
+pub extern "C" fn r_GetSymbolName(index: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if index < 0 {
+		return -1;
+	}
+	let name = match program.Debug.symbols.get(index as usize) {
+		Some(symbol) => symbol.name.as_bytes().to_vec(),
+		None => return -1,
+	};
+	let transfer = program.Processor.files.transfer();
+	*transfer = name;
+	return transfer.len() as jsint;
+}
+
+// field 0 is the address, 1 the kind (the export kinds of object.rs), 2 the
+// file, 3 the line and 4 the column it was declared at, -1 past the last
+// symbol
+#[no_mangle]
+pub extern "C" fn r_GetSymbolInfo(index: jsint, field: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if index < 0 {
+		return -1;
+	}
+	let symbol = match program.Debug.symbols.get(index as usize) {
+		Some(symbol) => symbol,
+		None => return -1,
+	};
+	return match field as storage {
+		debuginfo::SYMBOL_ADDRESS => symbol.address as jsint,
+		debuginfo::SYMBOL_KIND => object::kind_word(symbol.kind) as jsint,
+		debuginfo::SYMBOL_FILE => symbol.span.file as jsint,
+		debuginfo::SYMBOL_LINE => symbol.span.line as jsint,
+		debuginfo::SYMBOL_COLUMN => symbol.span.column as jsint,
+		_ => -1,
+	};
+}
+
+// a breakpoint on the first code from the line, returns its address or -1
+// if the line has no code after it
+#[no_mangle]
+pub extern "C" fn r_SetBreakpointAtLine(file: jsint, line: jsint) -> jsint {
+	let address = r_GetLineAddress(file, line);
+	if address >= 0 {
+		SetBreakpoint(address as u32);
+	}
+	return address;
+}
+
+// the transfer buffer holds the name of a label, returns its address or -1
+// if there is no such label
+#[no_mangle]
+pub extern "C" fn r_SetBreakpointAtSymbol(nameLength: jsint) -> jsint {
+	let address = {
+		let program = &mut MAIN_PROGRAM.lock().unwrap();
+		let name = transfer_name(&mut program.Processor.files, nameLength);
+		match program.Debug.label(&name) {
+			Some(label) => label.address,
+			None => return -1,
+		}
+	};
+	SetBreakpoint(address);
+	return address as jsint;
+}
+
+// puts the standard library in the program's files, as the object std.dslo
+// to link with and as the sources programs include, see stdlib.rs
+#[no_mangle]
+pub extern "C" fn r_PreloadStdlib() -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let files = &mut program.Processor.files;
+	let mut preloaded = files.insert(stdlib::OBJECT_NAME, stdlib::object().to_bytes()).is_ok();
+	for &(name, source) in stdlib::FILES.iter() {
+		preloaded &= files.insert(name, source.as_bytes().to_vec()).is_ok();
+	}
+	return preloaded;
+}
+
+// makes the next count samples, signed 16 bit at the sample rate, and
+// returns how many were made, read them with r_GetAudioPointer
+#[no_mangle]
+pub extern "C" fn r_SynthesizeAudio(count: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.mmio.device::<Audio>() {
+		Some(audio) => audio.synthesize(count.max(0) as usize).len() as jsint,
+		None => 0,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetAudioPointer() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.mmio.device::<Audio>() {
+		Some(audio) => audio.output().as_ptr() as jsint,
+		None => 0,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetAudioSampleRate() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.mmio.device::<Audio>() {
+		Some(audio) => audio.sample_rate() as jsint,
+		None => 0,
+	};
+}
+
+// set to the rate of the host's AudioContext
+#[no_mangle]
+pub extern "C" fn r_SetAudioSampleRate(rate: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if let Some(audio) = program.Processor.mmio.device::<Audio>() {
+		audio.set_sample_rate(rate.max(0) as u32);
+	}
+}
+
+// the same seed always gives the program the same numbers
+#[no_mangle]
+pub extern "C" fn r_SetRandomSeed(seed: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if let Some(random) = program.Processor.mmio.device::<Random>() {
+		random.set_seed(seed as storage);
+	}
+}
+
+// the 64 bit generator state, half 0 is the low word and half 1 the high
+// word, so the host can save it and continue the same sequence later
+#[no_mangle]
+pub extern "C" fn r_GetRandomState(half: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let state = match program.Processor.mmio.device::<Random>() {
+		Some(random) => random.state(),
+		None => 0,
+	};
+	return if half == 0 { state as u32 as jsint } else { (state >> 32) as u32 as jsint };
+}
+
+#[no_mangle]
+pub extern "C" fn r_SetRandomState(low: jsint, high: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	if let Some(random) = program.Processor.mmio.device::<Random>() {
+		random.set_state((high as u32 as u64) << 32 | low as u32 as u64);
+	}
+}
+
+// kind is 1 move, 2 button down, 3 button up, 4 wheel
+// data is the button, or the wheel delta
+// x and y are relative to the element the screen is shown in, which is
+// displayWidth * displayHeight large
+// returns false if the event was dropped
+#[no_mangle]
+pub extern "C" fn r_PushPointerEvent(kind: jsint, x: jsint, y: jsint, data: jsint, displayWidth: jsint, displayHeight: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let pushed = program.Processor.push_pointer_event(kind as storage, x, y, data as storage, displayWidth, displayHeight);
+	if pushed {
+		program.Processor.raise_interrupt(IRQ_POINTER);
+	}
+	return pushed;
+}
+
+// lines 0 to 4 are raised by the devices, see interrupts.rs, programs can
+// use the others as they like
+// returns false for an unknown line
+#[no_mangle]
+pub extern "C" fn r_RaiseInterrupt(line: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.raise_interrupt(line as storage);
 }
 
 #[no_mangle]
@@ -110,6 +753,182 @@ pub extern "C" fn r_GetWasmMemoryLocation(location: jsint) -> jsint {
 	return program.Processor._get_pointer(location as u32);
 }
 
+#[no_mangle]
+pub extern "C" fn r_SetMemoryProtection(start: jsint, length: jsint, permissions: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	program.Processor.protection.lock(start as u32, length as u32, permissions as u32);
+}
+
+// the transfer buffer holds the numeric listing of the program, the
+// permissions of its #[protect] lines are applied, false if it can't be read
+#[no_mangle]
+pub extern "C" fn r_ProtectListing(length: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	let text = transfer_name(&mut program.Processor.files, length);
+	return match Listing::parse(&text) {
+		Ok(listing) => {
+			program.Processor.protect_image(&listing.protections);
+			true
+		},
+		Err(_) => false,
+	};
+}
+
+#[no_mangle]
+pub extern "C" fn r_ClearMemoryProtection() {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	program.Processor.protection.clear();
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFaultKind() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.fault {
+		Some(fault) => fault.kind as jsint,
+		None => FaultKind::None as jsint,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFaultAddress() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.fault {
+		Some(fault) => fault.address as jsint,
+		None => 0,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFaultInstruction() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.fault {
+		Some(fault) => fault.instruction as jsint,
+		None => 0,
+	}
+}
+
+// field 0 is the reason (see exit.rs), 1 the exit code and 2 the address
+// of the instruction the program stopped at
+#[no_mangle]
+pub extern "C" fn r_GetExitInfo(field: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.exit.field(field as storage) as jsint;
+}
+
+// the program halts once it ran this many instructions, 0 for no limit
+#[no_mangle]
+pub extern "C" fn r_SetStepBudget(steps: jsint) {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	program.Processor.set_step_budget(steps.max(0) as u64);
+}
+
+// low 32 bits of the number of instructions run so far
+#[no_mangle]
+pub extern "C" fn r_GetStepCount() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.steps as u32 as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetBufferCount() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.buffers.len() as jsint;
+}
+
+// returns 0 if there is no buffer at the index
+#[no_mangle]
+pub extern "C" fn r_GetBufferIdAt(index: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.at(index as usize) {
+		Some(buffer) => buffer.id as jsint,
+		None => 0,
+	}
+}
+
+// returns 0 if there is no initialized buffer of the type
+#[no_mangle]
+pub extern "C" fn r_FindBufferOfType(kind: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.of_type(kind as u32) {
+		Some(buffer) => buffer.id as jsint,
+		None => 0,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_IsBufferInitialized(id: jsint) -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.get(id as u32) {
+		Some(buffer) => buffer.is_initialized(),
+		None => false,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetBufferHead(id: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.get(id as u32) {
+		Some(buffer) => buffer.head as jsint,
+		None => 0,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetBufferLength(id: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.get(id as u32) {
+		Some(buffer) => buffer.length as jsint,
+		None => 0,
+	}
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetBufferType(id: jsint) -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return match program.Processor.buffers.get(id as u32) {
+		Some(buffer) => buffer.kind as jsint,
+		None => BUFFER_NONE as jsint,
+	}
+}
+
+// renders the screen buffer into RGBA pixels if anything changed
+// returns false if the pixels are the same as last time
+#[no_mangle]
+pub extern "C" fn r_RenderFramebuffer() -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.render_framebuffer();
+}
+
+#[no_mangle]
+pub extern "C" fn r_IsFramebufferDirty() -> bool {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.is_framebuffer_dirty();
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFramebufferPointer() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.framebuffer.pixels().as_ptr() as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFramebufferWidth() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.framebuffer.width() as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetFramebufferHeight() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.framebuffer.height() as jsint;
+}
+
+#[no_mangle]
+pub extern "C" fn r_GetLastSyscallError() -> jsint {
+	let program = &mut MAIN_PROGRAM.lock().unwrap();
+	return program.Processor.last_error as jsint;
+}
+
 lazy_static! {
 	static ref MAIN_PROGRAM: Mutex<Program> = Mutex::new(Program::new());
 }
@@ -120,6 +939,10 @@ fn run() {
 	match program.Processor.status {
 		ProcessorStatus::Halted => {},
 		ProcessorStatus::Empty => {},
+		ProcessorStatus::Faulted => {},
+		ProcessorStatus::WaitingForFrame => {},
+		ProcessorStatus::WaitingForInput => {},
+		ProcessorStatus::Sleeping => {},
 		_ => { // paused, not started, running
 			program.Processor.status = ProcessorStatus::Running;
 			while !step(program) {
@@ -130,12 +953,10 @@ fn run() {
 
 fn step(program: &mut Program) -> bool {
 
-	if program.DoBreakpoints {
-		if program.Breakpoints.contains(&program.Processor.next) {
-			program.Processor.status = ProcessorStatus::Paused;
-			return true;
-		}
-	} 
+	if program.DoBreakpoints && program.Breakpoints.contains(&program.Processor.next) {
+		program.Processor.status = ProcessorStatus::Paused;
+		return true;
+	}
 
 	let stopCode = program.Processor.step();
 
@@ -154,12 +975,6 @@ fn step(program: &mut Program) -> bool {
 	return false;
 }
 
-fn syscall(code: storage, param: storage) -> i32 {
-	unsafe {
-		return js_syscall(code as jsint, param as c_int);
-	}
-}
-
 fn SetBreakpoint(point: u32) {
 	let mut prog = MAIN_PROGRAM.lock().unwrap();
 	if !prog.Breakpoints.contains(&point) {
@@ -175,7 +990,7 @@ fn RemoveBreakpoint(point: u32) {
 }
 
 fn GetIsBreakpoint(point: u32) -> bool {
-	let mut prog = MAIN_PROGRAM.lock().unwrap();
+	let prog = MAIN_PROGRAM.lock().unwrap();
 	return prog.Breakpoints.contains(&point);
 }
 
@@ -201,16 +1016,19 @@ struct Program {
 	Processor: Processor,
 	Breakpoints: HashSet<u32>,
 	DoBreakpoints: bool,
+	Debug: DebugInfo,
 }
 impl Program {
 	fn new() -> Program {
 		let Processor = Processor::new();
 		let Breakpoints = HashSet::new();
 		let DoBreakpoints = false;
+		let Debug = DebugInfo::new();
 		Program {
 			Processor,
 			Breakpoints,
 			DoBreakpoints,
+			Debug,
 		}
 	}
 }
@@ -221,6 +1039,20 @@ struct Processor {
 	next: location,
 	status: ProcessorStatus,
 	regions: Vec<MemoryBlock>,
+	heap: Heap,
+	last_error: SyscallError,
+	protection: Protection,
+	fault: Option<Fault>,
+	mmio: Mmio,
+	buffers: BufferRegistry,
+	framebuffer: Framebuffer,
+	files: Filesystem,
+	clock: Clock,
+	interrupts: Interrupts,
+	exit: ExitInfo,
+	// instructions run so far, and the most that may run, 0 for no limit
+	steps: u64,
+	step_budget: u64,
 
 	perStepParamPointer: u32,
 	perStepDontMove: bool,
@@ -232,8 +1064,27 @@ impl Processor {
 		let alu = ALU::new();
 		let next = 1;
 		let status = ProcessorStatus::Empty;
-		let mut regions: Vec<MemoryBlock> = Vec::new();
-		regions.push(MemoryBlock::new());
+		let regions: Vec<MemoryBlock> = vec![MemoryBlock::new()];
+		let heap = Heap::new();
+		let last_error = SyscallError::None;
+		let protection = Protection::new();
+		let fault = None;
+		let mut mmio = Mmio::new();
+		mmio.map(TEXT_MODE_BASE, TEXT_MODE_SIZE, Box::new(TextMode::new()));
+		mmio.map(TILES_BASE, TILES_SIZE, Box::new(TileEngine::new()));
+		mmio.map(KEYBOARD_BASE, KEYBOARD_SIZE, Box::new(Keyboard::new()));
+		mmio.map(POINTER_BASE, POINTER_SIZE, Box::new(Pointer::new()));
+		mmio.map(TERMINAL_BASE, TERMINAL_SIZE, Box::new(Terminal::new()));
+		mmio.map(AUDIO_BASE, AUDIO_SIZE, Box::new(Audio::new()));
+		mmio.map(RANDOM_BASE, RANDOM_SIZE, Box::new(Random::new()));
+		let buffers = BufferRegistry::new();
+		let framebuffer = Framebuffer::new();
+		let files = Filesystem::new();
+		let clock = Clock::new();
+		let interrupts = Interrupts::new();
+		let exit = ExitInfo::new();
+		let steps = 0;
+		let step_budget = 0;
 		let perStepParamPointer = 0;
 		let perStepDontMove = false;
 		Processor {
@@ -242,17 +1093,30 @@ impl Processor {
 			next,
 			status,
 			regions,
+			heap,
+			last_error,
+			protection,
+			fault,
+			mmio,
+			buffers,
+			framebuffer,
+			files,
+			clock,
+			interrupts,
+			exit,
+			steps,
+			step_budget,
 			perStepParamPointer,
 			perStepDontMove,
 		}
 	}
 
-	fn getParam(&mut self) -> storage {
+	fn getParam(&mut self) -> Option<storage> {
 		let n = self.next;
 		let perStepParamPointer = self.perStepParamPointer + 1;
-		let param: storage = self._get_memory_loc(n + perStepParamPointer);
+		let param: storage = self.fetch_memory(n + perStepParamPointer)?;
 		self.perStepParamPointer = perStepParamPointer;
-		return param;
+		return Some(param);
 	}
 
 	fn dontMoveParamPointer(&mut self) {
@@ -262,11 +1126,26 @@ impl Processor {
 	// returns whether or not a breakpoint was hit
 	fn step(&mut self) -> StopCode {
 		let n = self.next;
-		let mut stopCode = StopCode::None;
 
         self.perStepParamPointer = 0;
 
-		let op = self._get_memory_loc(n);
+		if self.is_over_budget() {
+			let steps = self.steps.min(storage::MAX as u64) as storage;
+			return self.exit(ExitReason::Budget, steps, n);
+		}
+
+		// the handler's first instruction runs on the next step
+		if self.dispatch_interrupt() {
+			return StopCode::None;
+		}
+		if self.fault.is_some() {
+			return self.stop_on_fault(n);
+		}
+
+		let op = match self.fetch_memory(n) {
+			Some(op) => op,
+			None => return self.stop_on_fault(n),
+		};
 
 		// 'parameter' is always an unsigned integer, and is type 'storage'
 		// 'as' means 'transmute the bytes to'
@@ -308,7 +1187,7 @@ impl Processor {
 
 		//	20	new block, beginning address -> bus
 		//	21	syscall with parameter as code and bus as argument
-		//	22	halt
+		//	22	halt with the bus as exit code
 		//	23	pause
 
 		//	24	parameter -> bus
@@ -320,36 +1199,78 @@ impl Processor {
 
 		//	28	get current instruction counter
 
+		//	29	ALU compare with parameter as mode
+		//	30	ALU.or
+		//	31	ALU.and
+		//	32	ALU.shift_left
+		//	33	ALU.shift_right
+
+		//	34	enable interrupts
+		//	35	disable interrupts
+		//	36	return from interrupt, restores next, bus and the ALU
+		//	37	parameter -> interrupt vector table address
+
+		let stopCode = match self.execute(op, n) {
+			Some(stopCode) => stopCode,
+			None => return self.stop_on_fault(n),
+		};
+		// halted, next stays on the instruction that stopped it
+		if let StopCode::Halt = stopCode {
+			return stopCode;
+		}
+
+		self.steps += 1;
+		if self.clock.retire() {
+			self.raise_interrupt(IRQ_TIMER);
+		}
+
+		if !self.perStepDontMove {
+			// perStepParamPointer represents how many parameters were used
+			// by the operation, so we want to move perStepParamPointer + 1
+			self.next += self.perStepParamPointer + 1;
+		}
+        else {
+            self.perStepDontMove = false;
+        }
+
+		return stopCode;
+	}
+
+	// runs the instruction, None if one of its accesses faulted, which
+	// stops it there, before it changes anything else
+	fn execute(&mut self, op: storage, n: location) -> Option<StopCode> {
+		let mut stopCode = StopCode::None;
+
 		match op {
 			0 => {},
             1 => {
-                let param = self.getParam();
-				self.load_location(param);
+                let param = self.getParam()?;
+				self.load_location(param)?;
             },
 			2 => {
-				let param = self.getParam();
-				self.set_location(param);
+				let param = self.getParam()?;
+				self.set_location(param)?;
 			},
 			3 => {
-				let param = self.getParam();
-				self.load_location_relative(param);
+				let param = self.getParam()?;
+				self.load_location_relative(param)?;
 			},
 			4 => {
-				let param = self.getParam();
-				self.set_location_relative(param);
+				let param = self.getParam()?;
+				self.set_location_relative(param)?;
 			},
 			5 => {
-				let pointer = self.getParam();
-				let constant = self.getParam();
-				self.load_with_constant_offset_to_bus(pointer, constant);
+				let pointer = self.getParam()?;
+				let constant = self.getParam()?;
+				self.load_with_constant_offset_to_bus(pointer, constant)?;
 			},
 			6 => {
-				let pointer = self.getParam();
-				let constant = self.getParam();
-				self.save_with_constant_offset_from_bus(pointer, constant);
+				let pointer = self.getParam()?;
+				let constant = self.getParam()?;
+				self.save_with_constant_offset_from_bus(pointer, constant)?;
 			},
 			7 => {
-				self.load_location_relative_with_bus();
+				self.load_location_relative_with_bus()?;
 			},
 			9 => {
 				self.add();
@@ -366,7 +1287,7 @@ impl Processor {
 				self.dontMoveParamPointer();
 			},
 			14 => {
-				let param = self.getParam();
+				let param = self.getParam()?;
 				if self.alu.compare_result {
 					self.dontMoveParamPointer();
 					self.jump(param);
@@ -391,20 +1312,29 @@ impl Processor {
 				self.alu_to_float();
 			},
 			20 => {
+				let start = (self.regions.len() * MEM_SIZE) as location;
 				let newblock = MemoryBlock::new();
 				self.add_region(newblock);
+				self.bus = start;
 			},
 			21 => {
-				let code = self.getParam();
+				let code = self.getParam()?;
 				// syscall
-				self.syscall(code);
+				self.syscall(code)?;
+				if self.is_waiting() {
+					stopCode = StopCode::Pause;
+				}
+			},
+			22 => {
+				let code = self.bus;
+				return Some(self.exit(ExitReason::Halt, code, n));
 			},
 			23 => {
 				stopCode = StopCode::Pause;
 				self.status = ProcessorStatus::Paused;
 			},
 			24 => {
-				let param = self.getParam();
+				let param = self.getParam()?;
 				self.load_immediate(param);
 			},
 			25 => {
@@ -413,21 +1343,21 @@ impl Processor {
 				self.push_to_alu();
 			},
 			26 => {
-				let p1 = self.getParam();
-				let p2 = self.getParam();
-				self.load_with_variable_offset_to_bus(p1, p2);
+				let p1 = self.getParam()?;
+				let p2 = self.getParam()?;
+				self.load_with_variable_offset_to_bus(p1, p2)?;
 			},
 			27 => {
-				let p1 = self.getParam();
-				let p2 = self.getParam();
-				self.save_with_variable_offset_from_bus(p1, p2);
+				let p1 = self.getParam()?;
+				let p2 = self.getParam()?;
+				self.save_with_variable_offset_from_bus(p1, p2)?;
 			},
 			28 => {
 				let counter = self.next;
 				self.bus = counter;
 			},
 			29 => {
-				let mode = self.getParam();
+				let mode = self.getParam()?;
 				self.alu_compare_with_mode(mode);
 			},
 			30 => {
@@ -442,23 +1372,26 @@ impl Processor {
 			33 => {
 				self.shift_right();
 			},
-			_ => {
-				stopCode = StopCode::Halt;
-				self.status = ProcessorStatus::Halted;
+			34 => {
+				self.set_interrupts_enabled(true);
+			},
+			35 => {
+				self.set_interrupts_enabled(false);
+			},
+			36 => {
+				self.return_from_interrupt()?;
 				self.dontMoveParamPointer();
 			},
+			37 => {
+				let param = self.getParam()?;
+				self.set_vector_table(param);
+			},
+			_ => {
+				return Some(self.exit(ExitReason::InvalidOpcode, op, n));
+			},
 		};
 
-		if !self.perStepDontMove {
-			// perStepParamPointer represents how many parameters were used
-			// by the operation, so we want to move perStepParamPointer + 1
-			self.next += self.perStepParamPointer + 1;
-		}
-        else {
-            self.perStepDontMove = false;
-        }
-
-		return stopCode;
+		return Some(stopCode);
 	}
 
 	// opcode 1
@@ -477,26 +1410,90 @@ impl Processor {
 	// 	self._r_set_memory(next, offset, value);
 	// }
 
+	// set by syscalls that block until the host does something
+	fn is_waiting(&self) -> bool {
+		return matches!(self.status, ProcessorStatus::WaitingForFrame | ProcessorStatus::WaitingForInput | ProcessorStatus::Sleeping);
+	}
+
+	// the machine's frame step, the tile engine draws before the program is
+	// told about the new frame
+	fn next_frame(&mut self) -> bool {
+		self.composite_tiles();
+		self.raise_interrupt(IRQ_FRAME);
+		if let ProcessorStatus::WaitingForFrame = self.status {
+			self.status = ProcessorStatus::Paused;
+			return true;
+		}
+		return false;
+	}
+
+	fn advance_clock(&mut self, ticks: u64) {
+		if self.clock.advance(ticks) {
+			self.raise_interrupt(IRQ_TIMER);
+		}
+		if let ProcessorStatus::Sleeping = self.status {
+			if self.clock.take_wake() {
+				self.status = ProcessorStatus::Paused;
+			}
+		}
+	}
+
+	// a program waiting in ReadLine is paused again once there is a line
+	// for it, it runs the syscall again when it is continued
+	fn wake_on_input(&mut self) {
+		if let ProcessorStatus::WaitingForInput = self.status {
+			let ready = match self.mmio.device::<Terminal>() {
+				Some(terminal) => terminal.has_line() || terminal.has_ended(),
+				None => true,
+			};
+			if ready {
+				self.status = ProcessorStatus::Paused;
+			}
+		}
+	}
+
+	// the faulting instruction is left as the next instruction, so the
+	// debugger shows where the bad access happened
+	fn stop_on_fault(&mut self, current: location) -> StopCode {
+		let kind = self.fault.map_or(FaultKind::None, |fault| fault.kind);
+		let stopCode = self.exit(ExitReason::Fault, kind as storage, current);
+		self.status = ProcessorStatus::Faulted;
+		return stopCode;
+	}
+
+	fn raise_fault(&mut self, kind: FaultKind, address: location) {
+		if self.fault.is_none() {
+			let instruction = self.next;
+			self.fault = Some(Fault {
+				kind,
+				address,
+				instruction,
+			});
+		}
+	}
+
 	// opcode 3
-	fn load_location_relative(&mut self, _offset: storage) {
+	fn load_location_relative(&mut self, _offset: storage) -> Option<()> {
 		let offset = bits_to_i32(_offset);
 		let next = self.next;
-		self.bus = self._get_memory_loc((offset + next as i32) as location);
+		self.bus = self.read_memory((offset + next as i32) as location)?;
+		return Some(());
 	}
 
 	// opcode 3
-	fn load_location_relative_with_bus(&mut self) {
+	fn load_location_relative_with_bus(&mut self) -> Option<()> {
 		let offset = bits_to_i32(self.bus);
 		let next = self.next;
-		self.bus = self._get_memory_loc((offset + next as i32) as location);
+		self.bus = self.read_memory((offset + next as i32) as location)?;
+		return Some(());
 	}
 
 	// opcode 4
-	fn set_location_relative(&mut self, _offset: storage) {
+	fn set_location_relative(&mut self, _offset: storage) -> Option<()> {
 		let offset = bits_to_i32(_offset);
 		let value = self.bus;
 		let next = self.next;
-		self._set_memory_loc((offset + next as i32) as u32, value);
+		return self.write_memory((offset + next as i32) as u32, value);
 	}
 
 	// opcode 5
@@ -589,43 +1586,72 @@ impl Processor {
 		self.regions.push(region);
 	}
 
+	// the image goes at LOAD_ADDRESS, where the processor starts, with one
+	// block more than the image needs, like r_Initialize
+	fn load_image(&mut self, image: &[storage]) {
+		let blocks = (image.len() + LOAD_ADDRESS as usize).div_ceil(MEM_SIZE);
+		while self.regions.len() < blocks + 1 {
+			self.add_region(MemoryBlock::new());
+		}
+		for (index, &value) in image.iter().enumerate() {
+			self._set_memory_loc(index as location + LOAD_ADDRESS, value);
+		}
+		self.status = ProcessorStatus::NotStarted;
+	}
+
+	// the permissions the program declares for its image
+	fn protect_image(&mut self, ranges: &[ImageRange]) {
+		for range in ranges.iter() {
+			self.protection.lock(range.start, range.length, range.permissions);
+		}
+	}
+
 	// opcode 15
-	fn syscall(&mut self, code: storage) {
+	// syscalls implemented in the crate take priority over the host, see host.rs
+	// None if a native syscall faulted on the memory it was given
+	fn syscall(&mut self, code: storage) -> Option<()> {
 		let param = self.bus;
-		self.bus = i32_to_bits(syscall(code, param));
+		self.bus = match self.native_syscall(code, param) {
+			Some(result) => result?,
+			None => self.host_syscall(code, param),
+		};
+		return Some(());
 	}
 
 	// opcode 1
-	fn load_location(&mut self, location: location) {
-		self.bus = self._get_memory_loc(location);
+	fn load_location(&mut self, location: location) -> Option<()> {
+		self.bus = self.read_memory(location)?;
+		return Some(());
 	}
 
 	// opcode 2
-	fn set_location(&mut self, location: location) {
+	fn set_location(&mut self, location: location) -> Option<()> {
 		let value = self.bus;
-		self._set_memory_loc(location, value);
+		return self.write_memory(location, value);
 	}
 
-	fn load_with_constant_offset_to_bus(&mut self, p1: location, p2: storage) {
-		let val = self._get_memory_loc(p1 + p2);
+	fn load_with_constant_offset_to_bus(&mut self, p1: location, p2: storage) -> Option<()> {
+		let val = self.read_memory(p1 + p2)?;
 		self.bus = val;
+		return Some(());
 	}
 
-	fn load_with_variable_offset_to_bus(&mut self, p1: location, p2: location) {
-		let offset = self._get_memory_loc(p2);
-		let val = self._get_memory_loc(p1 + offset);
+	fn load_with_variable_offset_to_bus(&mut self, p1: location, p2: location) -> Option<()> {
+		let offset = self.read_memory(p2)?;
+		let val = self.read_memory(p1 + offset)?;
 		self.bus = val;
+		return Some(());
 	}
 
-	fn save_with_constant_offset_from_bus(&mut self, p1: location, p2: storage) {
+	fn save_with_constant_offset_from_bus(&mut self, p1: location, p2: storage) -> Option<()> {
 		let value = self.bus;
-		self._set_memory_loc(p1 + p2, value);
+		return self.write_memory(p1 + p2, value);
 	}
 	
-	fn save_with_variable_offset_from_bus(&mut self, p1: location, p2: location) {
-		let offset = self._get_memory_loc(p2);
+	fn save_with_variable_offset_from_bus(&mut self, p1: location, p2: location) -> Option<()> {
+		let offset = self.read_memory(p2)?;
 		let value = self.bus;
-		self._set_memory_loc(p1 + offset, value);
+		return self.write_memory(p1 + offset, value);
 	}
 
 	// opcode 20
@@ -736,18 +1762,76 @@ impl Processor {
 	//	}
 	// }
 
-	fn _r_get_memory(&mut self, location: location, offset: i32) -> storage {
+	fn _r_get_memory(&mut self, location: location, offset: i32) -> Option<storage> {
 		let newLocation = (location as i32 + offset) as u32;
-		return self._get_memory_loc(newLocation);
+		return self.read_memory(newLocation);
 	}
 
-	fn _r_set_memory(&mut self, location: location, offset: i32, value: storage) {
+	fn _r_set_memory(&mut self, location: location, offset: i32, value: storage) -> Option<()> {
 		let newLocation = (location as i32 + offset) as u32;
-		self._set_memory_loc(newLocation, value);
+		return self.write_memory(newLocation, value);
+	}
+
+	// memory access made by an instruction, checked against the permissions
+	// None if it faulted, nothing was read or written then
+	fn read_memory(&mut self, location: location) -> Option<storage> {
+		self.check_access(location, PERM_READ, FaultKind::Read)?;
+		return Some(self._get_memory_loc(location));
+	}
+
+	fn write_memory(&mut self, location: location, value: storage) -> Option<()> {
+		self.check_access(location, PERM_WRITE, FaultKind::Write)?;
+		self._set_memory_loc(location, value);
+		return Some(());
+	}
+
+	// opcodes and their parameters
+	fn fetch_memory(&mut self, location: location) -> Option<storage> {
+		self.check_access(location, PERM_EXECUTE, FaultKind::Execute)?;
+		return Some(self._get_memory_loc(location));
+	}
+
+	// a read made by a syscall, checked like an instruction's but without
+	// the side effects of reading a device
+	fn peek_memory(&mut self, location: location) -> Option<storage> {
+		self.check_access(location, PERM_READ, FaultKind::Read)?;
+		return Some(self.peek(location));
+	}
+
+	// raises the fault if the access isn't allowed or nothing is there
+	fn check_access(&mut self, location: location, permission: storage, kind: FaultKind) -> Option<()> {
+		if !self.protection.allows(location, permission) {
+			self.raise_fault(kind, location);
+			return None;
+		}
+		if !self.is_mapped(location) {
+			self.raise_fault(FaultKind::Unmapped, location);
+			return None;
+		}
+		return Some(());
+	}
+
+	// number of addresses backed by memory blocks
+	fn mapped_size(&self) -> usize {
+		return self.regions.len() * MEM_SIZE;
+	}
+
+	// backed by a memory block or claimed by a device
+	fn is_mapped(&self, location: location) -> bool {
+		return (location as usize) < self.mapped_size() || self.mmio.claims(location);
 	}
 
 	// helper
-	fn _get_memory_loc(&self, location: location) -> storage {
+	// devices get the access before memory does, reading a device register
+	// can change it, see peek
+	fn _get_memory_loc(&mut self, location: location) -> storage {
+		if let Some(value) = self.text_mode_register(location) {
+			return value;
+		}
+		if let Some(value) = self.mmio.read(location) {
+			return value;
+		}
+
 		let offset = location as usize % MEM_SIZE;
 		let region_num = (location as f64 / MEM_SIZE as f64).floor() as usize;
 
@@ -758,12 +1842,34 @@ impl Processor {
 		return 0;
 	}
 
+	// the value at the location without side effects, devices are peeked
+	// instead of read and nothing is checked, for the host, dumps and rendering
+	pub fn peek(&self, location: location) -> storage {
+		if let Some(value) = self.text_mode_register(location) {
+			return value;
+		}
+		if let Some(value) = self.mmio.peek(location) {
+			return value;
+		}
+		return match self.regions.get(location as usize / MEM_SIZE) {
+			Some(region) => region.memory[location as usize % MEM_SIZE],
+			None => 0,
+		};
+	}
+
 	// helper
 	fn _set_memory_loc(&mut self, location: location, value: storage) {
+		if self.mmio.write(location, value) {
+			return;
+		}
+
 		let offset = location as usize % MEM_SIZE;
 		let region_num = (location as f64 / MEM_SIZE as f64).floor() as usize;
 
-		self.regions[region_num].memory[offset] = value;
+		if region_num < self.regions.len() {
+			self.regions[region_num].memory[offset] = value;
+			self.track_framebuffer_write(location);
+		}
 	}
 
 	// used only for JS to get memory from wasm
@@ -780,11 +1886,15 @@ impl Processor {
 	}
 }
 
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
 enum ALUMode {
 	int,
 	float
 }
 
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
 enum ALUCompareMode {
 	greater_than,
 	greater_than_or_equal,
@@ -794,6 +1904,8 @@ enum ALUCompareMode {
 	lesser_than_or_equal,
 }
 
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 struct ALU {
 	value_a_int: i32, // recent value
 	value_b_int: i32, // oldest value
@@ -822,6 +1934,7 @@ impl ALU {
 		}
 	}
 
+	#[allow(dead_code)]
 	fn mode_int_save_value(&mut self) {
 		self.mode = ALUMode::int;
 
@@ -829,6 +1942,7 @@ impl ALU {
 		self.value_b_int = self.value_b_float as i32;
 	}
 
+	#[allow(dead_code)]
 	fn mode_float_save_value(&mut self) {
 		self.mode = ALUMode::float;
 
@@ -886,19 +2000,19 @@ impl ALU {
 	}
 
 	fn bitwise_or(&mut self) {
-		self.value_a_int = self.value_a_int | self.value_b_int;
+		self.value_a_int |= self.value_b_int;
 	}
 
 	fn bitwise_and(&mut self) {
-		self.value_a_int = self.value_a_int & self.value_b_int;
+		self.value_a_int &= self.value_b_int;
 	}
 
 	fn shift_left(&mut self) {
-		self.value_a_int = self.value_a_int << self.value_b_int;
+		self.value_a_int <<= self.value_b_int;
 	}
 
 	fn shift_right(&mut self) {
-		self.value_a_int = self.value_a_int >> self.value_b_int;
+		self.value_a_int >>= self.value_b_int;
 	}
 
 	fn push_int(&mut self, value: i32) {
@@ -1024,25 +2138,18 @@ impl MemoryBlock {
 }
 
 fn i32_to_bits(v: i32) -> u32 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as u32;
 }
 
 fn bits_to_i32(v: u32) -> i32 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as i32;
 }
 
 fn i64_to_bits(v: i64) -> u64 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as u64;
 }
 
+#[allow(dead_code)]
 fn bits_to_i64(v: u64) -> i64 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as i64;
 }
\ No newline at end of file
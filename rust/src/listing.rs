@@ -0,0 +1,274 @@
+// Loader for the numeric listing format of dsl/hello.dsl.
+//
+// Every line holds at most one number, everything after a # is a comment.
+// A number followed by the #[code] annotation is an opcode, and is checked
+// against the opcode table along with its parameters. Lines without a
+// number take no address. The comments and line numbers are kept as debug
+// info so addresses can be reported by source line.
+//
+// A line without a number can hold #[protect start length permissions],
+// the permissions of a range of the image like in assembly's .protect, for
+// the loader to apply.
+
+use std::fmt;
+use super::{storage, location};
+use opcodes::opcode;
+use protection::{ImageRange, parse_permissions, permissions_text};
+
+// the first word of a program goes here, where the processor starts
+pub const LOAD_ADDRESS: location = 1;
+
+const CODE_ANNOTATION: &str = "#[code]";
+const PROTECT_ANNOTATION: &str = "#[protect";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListingErrorKind {
+	NotANumber(String),
+	UnknownAnnotation(String),
+	// not #[protect start length permissions]
+	InvalidProtection(String),
+	// #[code] on a line without a number
+	AnnotationWithoutValue,
+	UnknownOpcode(storage),
+	// the opcode needs more words than follow it
+	MissingParameters {
+		opcode: storage,
+		expected: usize,
+		found: usize,
+	},
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingError {
+	// 1 based
+	pub line: usize,
+	pub kind: ListingErrorKind,
+}
+
+impl fmt::Display for ListingError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: ", self.line)?;
+		match self.kind {
+			ListingErrorKind::NotANumber(ref text) => {
+				return write!(f, "'{}' is not a number", text);
+			},
+			ListingErrorKind::UnknownAnnotation(ref text) => {
+				return write!(f, "unknown annotation {}", text);
+			},
+			ListingErrorKind::InvalidProtection(ref text) => {
+				return write!(f, "{} is not {} start length permissions]", text, PROTECT_ANNOTATION);
+			},
+			ListingErrorKind::AnnotationWithoutValue => {
+				return write!(f, "{} needs a number on the same line", CODE_ANNOTATION);
+			},
+			ListingErrorKind::UnknownOpcode(code) => {
+				return write!(f, "{} is not an opcode", code);
+			},
+			ListingErrorKind::MissingParameters { opcode, expected, found } => {
+				return write!(f, "opcode {} takes {} parameters but only {} follow it", opcode, expected, found);
+			},
+		}
+	}
+}
+
+// debug info for one word of the image
+#[derive(Clone, Debug)]
+pub struct SourceWord {
+	pub line: usize,
+	pub code: bool,
+	// the comment on the line, without the #
+	pub comment: String,
+}
+
+pub struct Listing {
+	pub image: Vec<storage>,
+	// one per word of the image
+	pub words: Vec<SourceWord>,
+	pub protections: Vec<ImageRange>,
+}
+
+impl Listing {
+	pub fn parse(text: &str) -> Result<Listing, ListingError> {
+		let mut image = Vec::new();
+		let mut words = Vec::new();
+		let mut protections = Vec::new();
+
+		for (index, line) in text.lines().enumerate() {
+			let line_number = index + 1;
+			let (value, comment) = match line.find('#') {
+				Some(start) => (&line[.. start], &line[start ..]),
+				None => (line, ""),
+			};
+			let value = value.trim();
+			let comment = comment.trim();
+
+			let code = comment.starts_with(CODE_ANNOTATION);
+			if value.is_empty() && comment.starts_with(PROTECT_ANNOTATION) {
+				let range = parse_protection(comment)
+					.ok_or(ListingError { line: line_number, kind: ListingErrorKind::InvalidProtection(comment.to_string()) })?;
+				protections.push(range);
+				continue;
+			}
+			if !code && comment.starts_with("#[") {
+				let end = comment.find(']').map_or(comment.len(), |end| end + 1);
+				let annotation = comment[.. end].to_string();
+				return Err(ListingError { line: line_number, kind: ListingErrorKind::UnknownAnnotation(annotation) });
+			}
+			if value.is_empty() {
+				if code {
+					return Err(ListingError { line: line_number, kind: ListingErrorKind::AnnotationWithoutValue });
+				}
+				continue;
+			}
+
+			let value = match value.parse::<i64>() {
+				Ok(value) if value >= i32::MIN as i64 && value <= storage::MAX as i64 => value as storage,
+				_ => return Err(ListingError { line: line_number, kind: ListingErrorKind::NotANumber(value.to_string()) }),
+			};
+			let comment = if code { &comment[CODE_ANNOTATION.len() ..] } else { comment };
+			image.push(value);
+			words.push(SourceWord {
+				line: line_number,
+				code,
+				comment: comment.trim_start_matches('#').trim().to_string(),
+			});
+		}
+
+		let listing = Listing {
+			image,
+			words,
+			protections,
+		};
+		listing.check_code()?;
+		return Ok(listing);
+	}
+
+	// every #[code] word is an opcode, followed by its parameters
+	fn check_code(&self) -> Result<(), ListingError> {
+		for (index, word) in self.words.iter().enumerate() {
+			if !word.code {
+				continue;
+			}
+			let code = self.image[index];
+			let op = match opcode(code) {
+				Some(op) => op,
+				None => return Err(ListingError { line: word.line, kind: ListingErrorKind::UnknownOpcode(code) }),
+			};
+			// parameters are plain numbers, the next opcode ends them
+			let found = self.words[index + 1 ..].iter()
+				.take(op.parameters)
+				.take_while(|word| !word.code)
+				.count();
+			if found < op.parameters {
+				return Err(ListingError {
+					line: word.line,
+					kind: ListingErrorKind::MissingParameters {
+						opcode: code,
+						expected: op.parameters,
+						found,
+					},
+				});
+			}
+		}
+		return Ok(());
+	}
+
+	pub fn word_at(&self, address: location) -> Option<&SourceWord> {
+		let index = address.checked_sub(LOAD_ADDRESS)?;
+		return self.words.get(index as usize);
+	}
+
+	// the source line of the word at the address
+	pub fn line_of(&self, address: location) -> Option<usize> {
+		return self.word_at(address).map(|word| word.line);
+	}
+
+	// the address of the first word on the line or after it
+	pub fn address_of(&self, line: usize) -> Option<location> {
+		let index = self.words.iter().position(|word| word.line >= line)?;
+		return Some(index as location + LOAD_ADDRESS);
+	}
+}
+
+// #[protect start length permissions]
+fn parse_protection(comment: &str) -> Option<ImageRange> {
+	let end = comment.find(']')?;
+	let fields: Vec<&str> = comment[PROTECT_ANNOTATION.len() .. end].split_whitespace().collect();
+	if fields.len() != 3 || !comment[end + 1 ..].trim().is_empty() {
+		return None;
+	}
+	return Some(ImageRange {
+		start: fields[0].parse().ok()?,
+		length: fields[1].parse().ok()?,
+		permissions: parse_permissions(fields[2])?,
+	});
+}
+
+// the annotation for the range, on a line of its own
+pub fn protection_annotation(range: &ImageRange) -> String {
+	return format!("{} {} {} {}]", PROTECT_ANNOTATION, range.start, range.length, permissions_text(range.permissions));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use opcodes::{SAVE, SAVE_CONSTANT_OFFSET, HALT};
+
+	fn error(text: &str) -> ListingError {
+		return match Listing::parse(text) {
+			Ok(_) => panic!("parsed {:?}", text),
+			Err(error) => error,
+		};
+	}
+
+	fn missing(opcode: storage, expected: usize, found: usize) -> ListingErrorKind {
+		return ListingErrorKind::MissingParameters {
+			opcode,
+			expected,
+			found,
+		};
+	}
+
+	#[test]
+	fn parameters_can_be_split_by_comments_and_blank_lines() {
+		let listing = Listing::parse("6 #[code] save\n# the address\n10\n\n2\n22 #[code]").unwrap();
+		assert_eq!(listing.image, vec![SAVE_CONSTANT_OFFSET, 10, 2, HALT]);
+		assert_eq!(listing.words[0].comment, "save");
+	}
+
+	#[test]
+	fn missing_parameters_at_the_end() {
+		assert_eq!(error("22 #[code]\n2 #[code]"), ListingError { line: 2, kind: missing(SAVE, 1, 0) });
+		assert_eq!(error("6 #[code]\n1"), ListingError { line: 1, kind: missing(SAVE_CONSTANT_OFFSET, 2, 1) });
+	}
+
+	#[test]
+	fn the_next_opcode_ends_the_parameters() {
+		assert_eq!(error("6 #[code]\n1\n22 #[code]\n0"), ListingError { line: 1, kind: missing(SAVE_CONSTANT_OFFSET, 2, 1) });
+		let error = error("2 #[code]\n\n22 #[code]");
+		assert_eq!(error.kind, missing(SAVE, 1, 0));
+		assert_eq!(error.to_string(), "line 1: opcode 2 takes 1 parameters but only 0 follow it");
+	}
+
+	#[test]
+	fn opcodes_and_annotations_are_checked() {
+		assert_eq!(error("8 #[code]").kind, ListingErrorKind::UnknownOpcode(8));
+		assert_eq!(error("#[code]").kind, ListingErrorKind::AnnotationWithoutValue);
+		assert_eq!(error("1 #[data]").kind, ListingErrorKind::UnknownAnnotation("#[data]".to_string()));
+		assert_eq!(error("one").kind, ListingErrorKind::NotANumber("one".to_string()));
+		assert_eq!(error("4294967296").kind, ListingErrorKind::NotANumber("4294967296".to_string()));
+		// negative numbers are stored as their 32 bits
+		assert_eq!(Listing::parse("-1").unwrap().image, vec![storage::MAX]);
+	}
+
+	#[test]
+	fn lines_and_addresses_map_both_ways() {
+		let listing = Listing::parse("# start\n24 #[code]\n5\n\n22 #[code]").unwrap();
+		assert_eq!(listing.line_of(LOAD_ADDRESS), Some(2));
+		assert_eq!(listing.line_of(LOAD_ADDRESS + 2), Some(5));
+		assert_eq!(listing.line_of(LOAD_ADDRESS + 3), None);
+		assert_eq!(listing.address_of(1), Some(LOAD_ADDRESS));
+		assert_eq!(listing.address_of(4), Some(LOAD_ADDRESS + 2));
+		assert_eq!(listing.address_of(6), None);
+	}
+}
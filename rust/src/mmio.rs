@@ -0,0 +1,145 @@
+// Memory mapped I/O.
+//
+// A device claims a range of addresses and sees every load and store to
+// that range as soon as the instruction runs, instead of the host copying
+// buffers in and out between steps.
+
+use std::any::Any;
+use super::{storage, location};
+
+// Devices are mapped from here to the top of the address space, far above
+// any memory block the processor can own.
+pub const MMIO_BASE: location = 0xFFFF_0000;
+
+pub trait Device: Any + Send {
+	// offset is relative to the start of the range the device claimed
+	// the value without any side effects, for syscalls, dumps and the host
+	fn peek(&self, offset: storage) -> storage;
+	// a load by an instruction, devices whose registers consume what they
+	// return override it
+	fn read(&mut self, offset: storage) -> storage {
+		return self.peek(offset);
+	}
+	fn write(&mut self, offset: storage, value: storage);
+
+	fn as_any(&mut self) -> &mut dyn Any;
+}
+
+struct Mapping {
+	start: location,
+	length: storage,
+	device: Box<dyn Device>,
+}
+
+impl Mapping {
+	fn contains(&self, address: location) -> bool {
+		return address >= self.start && address - self.start < self.length;
+	}
+
+	// last address in the range, mappings never wrap around
+	fn last(&self) -> location {
+		return self.start + (self.length - 1);
+	}
+}
+
+pub struct Mmio {
+	mappings: Vec<Mapping>,
+}
+
+impl Mmio {
+	pub fn new() -> Mmio {
+		let mappings = Vec::new();
+		Mmio {
+			mappings,
+		}
+	}
+
+	// returns false if the range overlaps a range that is already claimed
+	pub fn map(&mut self, start: location, length: storage, device: Box<dyn Device>) -> bool {
+		if length == 0 || start.checked_add(length - 1).is_none() {
+			return false;
+		}
+		let last = start + (length - 1);
+		if self.mappings.iter().any(|m| m.start <= last && start <= m.last()) {
+			return false;
+		}
+		self.mappings.push(Mapping {
+			start,
+			length,
+			device,
+		});
+		return true;
+	}
+
+	pub fn claims(&self, address: location) -> bool {
+		return self.mappings.iter().any(|m| m.contains(address));
+	}
+
+	pub fn peek(&self, address: location) -> Option<storage> {
+		let mapping = self.mappings.iter().find(|m| m.contains(address))?;
+		return Some(mapping.device.peek(address - mapping.start));
+	}
+
+	pub fn read(&mut self, address: location) -> Option<storage> {
+		let mapping = self.mappings.iter_mut().find(|m| m.contains(address))?;
+		let offset = address - mapping.start;
+		return Some(mapping.device.read(offset));
+	}
+
+	// returns false if no device claimed the address
+	pub fn write(&mut self, address: location, value: storage) -> bool {
+		match self.mappings.iter_mut().find(|m| m.contains(address)) {
+			Some(mapping) => {
+				let offset = address - mapping.start;
+				mapping.device.write(offset, value);
+				return true;
+			},
+			None => {
+				return false;
+			},
+		}
+	}
+
+	// the first mapped device of the given type, so syscalls and host exports
+	// can reach the same device the program talks to through memory
+	pub fn device<T: Device>(&mut self) -> Option<&mut T> {
+		for mapping in self.mappings.iter_mut() {
+			if let Some(device) = mapping.device.as_any().downcast_mut::<T>() {
+				return Some(device);
+			}
+		}
+		return None;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use keyboard::{Keyboard, KEYBOARD_BASE, KEYBOARD_NEXT, KEYBOARD_PENDING, KEY_EVENT_DOWN};
+	use random::{RANDOM_BASE, RANDOM_NEXT};
+	use testing::Machine;
+
+	#[test]
+	fn peeking_leaves_device_registers_alone() {
+		let mut machine = Machine::new(&format!(".text\nLoadValueAtAddressIntoBus {}", KEYBOARD_BASE + KEYBOARD_NEXT));
+		machine.processor.mmio.device::<Keyboard>().unwrap().push(65, true);
+		let next = machine.processor.peek(KEYBOARD_BASE + KEYBOARD_NEXT);
+		assert_eq!(next, 65 | KEY_EVENT_DOWN);
+		assert_eq!(machine.processor.peek(KEYBOARD_BASE + KEYBOARD_NEXT), next);
+		assert_eq!(machine.processor.peek(KEYBOARD_BASE + KEYBOARD_PENDING), 1);
+		// the instruction takes the event
+		machine.run();
+		assert_eq!(machine.processor.bus, next);
+		assert_eq!(machine.processor.peek(KEYBOARD_BASE + KEYBOARD_PENDING), 0);
+	}
+
+	#[test]
+	fn syscall_arguments_are_peeked() {
+		// WriteString reads its string from the random device, which must
+		// not move the generator on
+		let address = RANDOM_BASE + RANDOM_NEXT;
+		let mut machine = Machine::new(&format!(".text\nLoadImmmediateToBus {}\nSyscall 32\nLoadValueAtAddressIntoBus {}", address, address));
+		let next = machine.processor.peek(address);
+		machine.run();
+		assert_eq!(machine.processor.bus, next);
+	}
+}
@@ -0,0 +1,275 @@
+// Runs programs outside of the browser, for dsl-run.
+//
+// A Runner owns its own processor instead of using MAIN_PROGRAM, and plays
+// the part the JS host plays in the browser: frames are taken as soon as
+// they are presented, sleeps skip the clock ahead, ReadLine reads from the
+// input and the terminal output, alerts included, is written to the output
+// as it comes.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use super::{Processor, ProcessorStatus, storage, location};
+use terminal::Terminal;
+use random::Random;
+use listing::{Listing, LOAD_ADDRESS};
+use assembler::{self as asm, Assembly, Span, Word};
+use object::{Object, link};
+use debuginfo::DebugInfo;
+use stdlib;
+
+pub use exit::{ExitInfo, ExitReason};
+
+// values per line of a memory dump
+const DUMP_WIDTH: usize = 8;
+
+pub struct Runner {
+	processor: Processor,
+	// where the words of the program came from, if it was loaded from a
+	// listing or assembled
+	debug: DebugInfo,
+}
+
+impl Default for Runner {
+	fn default() -> Runner {
+		return Runner::new();
+	}
+}
+
+impl Runner {
+	pub fn new() -> Runner {
+		let processor = Processor::new();
+		let debug = DebugInfo::new();
+		Runner {
+			processor,
+			debug,
+		}
+	}
+
+	// the image goes at LOAD_ADDRESS, where the processor starts, the same
+	// as Initialize in rustUtils.ts
+	pub fn load(&mut self, image: &[storage]) {
+		self.processor.load_image(image);
+	}
+
+	// the listing is the one file of its debug info, its lines have no
+	// columns
+	pub fn load_listing(&mut self, listing: Listing) {
+		self.load(&listing.image);
+		self.processor.protect_image(&listing.protections);
+		let words: Vec<Word> = listing.words.iter().map(|word| Word {
+			span: Some(Span { file: 0, line: word.line, column: 0, length: 0 }),
+			code: word.code,
+			comment: String::new(),
+		}).collect();
+		self.debug = DebugInfo::from_source(LOAD_ADDRESS, &words, &[], &[String::new()]);
+	}
+
+	pub fn load_assembly(&mut self, assembly: Assembly) {
+		self.load(&assembly.image);
+		self.processor.protect_image(&assembly.protections);
+		self.debug = assembly.debug_info();
+	}
+
+	// for a binary image, from dsl-asm --debug-info
+	pub fn load_debug_info(&mut self, debug: DebugInfo) {
+		self.debug = debug;
+	}
+
+	pub fn debug_info(&self) -> &DebugInfo {
+		return &self.debug;
+	}
+
+	// the line of the source the word at the address came from
+	pub fn source_line(&self, address: location) -> Option<usize> {
+		return self.debug.range_at(address).map(|range| range.span.line);
+	}
+
+	// the program halts once it ran this many instructions, 0 for no limit
+	pub fn set_step_limit(&mut self, steps: u64) {
+		self.processor.set_step_budget(steps);
+	}
+
+	pub fn set_seed(&mut self, seed: storage) {
+		if let Some(random) = self.processor.mmio.device::<Random>() {
+			random.set_seed(seed);
+		}
+	}
+
+	// runs until the program halts or faults
+	pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<ExitInfo> {
+		self.processor.status = ProcessorStatus::Running;
+		loop {
+			self.processor.step();
+			self.flush_terminal(output)?;
+			match self.processor.status {
+				ProcessorStatus::Halted | ProcessorStatus::Faulted => {
+					break;
+				},
+				ProcessorStatus::WaitingForFrame => {
+					self.processor.next_frame();
+				},
+				ProcessorStatus::Sleeping => {
+					let remaining = self.processor.clock.sleep_remaining().unwrap_or(0);
+					self.processor.advance_clock(remaining);
+				},
+				ProcessorStatus::WaitingForInput => {
+					self.read_input(input)?;
+				},
+				_ => {},
+			}
+			// nobody continues a paused program here, so it just keeps going
+			if let ProcessorStatus::Paused = self.processor.status {
+				self.processor.status = ProcessorStatus::Running;
+			}
+		}
+		output.flush()?;
+		return Ok(self.processor.exit);
+	}
+
+	pub fn write_registers(&mut self, output: &mut dyn Write) -> io::Result<()> {
+		let processor = &self.processor;
+		let alu = &processor.alu;
+		writeln!(output, "next     {}", processor.next)?;
+		writeln!(output, "bus      {}", processor.bus)?;
+		writeln!(output, "alu a    {} / {}", alu.value_a_int, alu.value_a_float)?;
+		writeln!(output, "alu b    {} / {}", alu.value_b_int, alu.value_b_float)?;
+		writeln!(output, "alu hi   {}", alu.hi)?;
+		writeln!(output, "alu lo   {}", alu.lo)?;
+		writeln!(output, "compare  {}", alu.compare_result)?;
+		writeln!(output, "steps    {}", processor.steps)?;
+		return Ok(());
+	}
+
+	pub fn read_memory(&mut self, address: location) -> storage {
+		return self.processor.peek(address);
+	}
+
+	// one line per DUMP_WIDTH words, starting with the address
+	pub fn write_memory(&mut self, output: &mut dyn Write, start: location, length: storage) -> io::Result<()> {
+		let mut address = start;
+		let end = start.saturating_add(length);
+		while address < end {
+			write!(output, "{}:", address)?;
+			let count = ((end - address) as usize).min(DUMP_WIDTH);
+			for _ in 0 .. count {
+				write!(output, " {}", self.processor.peek(address))?;
+				address += 1;
+			}
+			writeln!(output)?;
+		}
+		return Ok(());
+	}
+
+	fn flush_terminal(&mut self, output: &mut dyn Write) -> io::Result<()> {
+		if let Some(terminal) = self.processor.mmio.device::<Terminal>() {
+			if !terminal.output().is_empty() {
+				output.write_all(terminal.output())?;
+				terminal.clear_output();
+			}
+		}
+		return Ok(());
+	}
+
+	// one line at a time, so a program that reads interactively sees each
+	// line as soon as it is typed
+	fn read_input(&mut self, input: &mut dyn BufRead) -> io::Result<()> {
+		let mut line = Vec::new();
+		input.read_until(b'\n', &mut line)?;
+		if let Some(terminal) = self.processor.mmio.device::<Terminal>() {
+			if line.is_empty() {
+				terminal.close_input();
+			}
+			for &byte in line.iter() {
+				terminal.push_input(byte);
+			}
+		}
+		self.processor.wake_on_input();
+		return Ok(());
+	}
+}
+
+// a .dsla file is assembled, anything else is read as an object file
+pub fn read_object(path: &str) -> Result<Object, String> {
+	if path.ends_with(".dsla") {
+		return assemble_file(path);
+	}
+	return read_object_file(path);
+}
+
+// the includes are read from disk, or are the files of the standard
+// library if there's no such file, the errors are one per line
+pub fn assemble_file(path: &str) -> Result<Object, String> {
+	let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+	let mut read_file = |name: &str| match fs::read_to_string(name) {
+		Ok(source) => Ok(source),
+		Err(error) => stdlib::file(name).map(str::to_string).ok_or(error.to_string()),
+	};
+	return asm::assemble_object(path, &source, &mut read_file).map_err(|errors| {
+		let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+		return errors.join("\n");
+	});
+}
+
+pub fn read_object_file(path: &str) -> Result<Object, String> {
+	let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+	let mut object = Object::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error))?;
+	// objects without debug info are named by their file
+	if object.files.is_empty() {
+		object.files.push(path.to_string());
+	}
+	return Ok(object);
+}
+
+// links the objects, the errors are one per line
+pub fn link_objects(objects: &[Object]) -> Result<Assembly, String> {
+	return link(objects).map_err(|errors| {
+		let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+		return errors.join("\n");
+	});
+}
+
+pub fn read_debug_info(path: &str) -> Result<DebugInfo, String> {
+	let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+	return DebugInfo::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error));
+}
+
+// little endian 32 bit words
+pub fn parse_image(bytes: &[u8]) -> Result<Vec<storage>, String> {
+	if !bytes.len().is_multiple_of(4) {
+		return Err(format!("image is {} bytes long, not a whole number of words", bytes.len()));
+	}
+	let image = bytes.chunks(4)
+		.map(|word| storage::from_le_bytes([word[0], word[1], word[2], word[3]]))
+		.collect();
+	return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn alerts_go_to_the_output_in_order() {
+		let source = "
+.data
+var before string \"before \"
+var alert string \"alert\"
+var after string \"after\"
+.text
+LoadImmmediateToBus before
+Syscall 32
+LoadImmmediateToBus alert
+Syscall 30
+LoadImmmediateToBus after
+Syscall 32
+LoadImmmediateToBus 0
+Halt
+";
+		let mut runner = Runner::new();
+		runner.load_assembly(asm::assemble(source).unwrap());
+		let mut output = Vec::new();
+		let exit = runner.run(&mut io::empty(), &mut output).unwrap();
+		assert_eq!(exit.reason, ExitReason::Halt);
+		assert_eq!(String::from_utf8_lossy(&output), "before alert\nafter");
+	}
+}
@@ -0,0 +1,540 @@
+// Relocatable object files and the linker.
+//
+// An object is assembled as if it was loaded at address 0. Every word that
+// holds an address, like the operands of opcodes 1, 2, 5, 6, 26 and 27, a
+// label given to LoadImmmediateToBus or an address in a variable, has a
+// relocation. The linker puts the objects one after the other from
+// LOAD_ADDRESS and adds the address of the object, or of the symbol it
+// imports, to those words.
+//
+// The first object is the program, it starts with the jump to its code. The
+// other objects also start with a jump and end with a halt, but are only
+// entered through the labels they export.
+//
+// An object file is little endian 32 bit words:
+//
+//	OBJECT_MAGIC OBJECT_VERSION
+//	length, the words of the image
+//	count, per export: address, kind, name
+//	count, per import: name
+//	count, per relocation: address, target
+//	count, per protected range: start, length, permissions
+//	the debug info, see debuginfo.rs
+//
+// A name is its length followed by one word per character, like a DSL
+// string. Export kinds are EXPORT_LABEL, EXPORT_UNIT and EXPORT_ARRAY. A
+// relocation target is 0 for the object itself, or 1 plus the index of the
+// import.
+
+use std::collections::HashMap;
+use std::fmt;
+use super::{storage, location};
+use assembler::{Assembly, Symbol, SymbolKind, VariableType, Word};
+use debuginfo::DebugInfo;
+use listing::LOAD_ADDRESS;
+use protection::{ImageRange, PERM_ALL};
+
+// "DSLO"
+pub const OBJECT_MAGIC: storage = 0x4F4C_5344;
+pub const OBJECT_VERSION: storage = 1;
+
+pub const EXPORT_LABEL: storage = 0;
+pub const EXPORT_UNIT: storage = 1;
+pub const EXPORT_ARRAY: storage = 2;
+
+// the longest name an object file can hold
+const MAX_NAME_LENGTH: storage = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelocationTarget {
+	// the address of the object
+	Object,
+	// the address of the import with this index
+	Import(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Relocation {
+	// the word that holds the address, from the start of the object
+	pub address: location,
+	pub target: RelocationTarget,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Export {
+	pub name: String,
+	pub kind: SymbolKind,
+	// from the start of the object
+	pub address: location,
+}
+
+pub struct Object {
+	pub image: Vec<storage>,
+	pub relocations: Vec<Relocation>,
+	pub exports: Vec<Export>,
+	pub imports: Vec<String>,
+	// debug info, one word per word of the image, addresses are from the
+	// start of the object
+	pub words: Vec<Word>,
+	pub symbols: Vec<Symbol>,
+	pub files: Vec<String>,
+	// from the start of the object
+	pub protections: Vec<ImageRange>,
+}
+
+impl Object {
+	pub fn to_words(&self) -> Vec<storage> {
+		let mut words = vec![OBJECT_MAGIC, OBJECT_VERSION];
+		words.push(self.image.len() as storage);
+		words.extend_from_slice(&self.image);
+		words.push(self.exports.len() as storage);
+		for export in self.exports.iter() {
+			words.push(export.address);
+			words.push(kind_word(export.kind));
+			push_name(&mut words, &export.name);
+		}
+		words.push(self.imports.len() as storage);
+		for import in self.imports.iter() {
+			push_name(&mut words, import);
+		}
+		words.push(self.relocations.len() as storage);
+		for relocation in self.relocations.iter() {
+			let target = match relocation.target {
+				RelocationTarget::Object => 0,
+				RelocationTarget::Import(index) => index as storage + 1,
+			};
+			words.push(relocation.address);
+			words.push(target);
+		}
+		words.push(self.protections.len() as storage);
+		for range in self.protections.iter() {
+			words.extend_from_slice(&[range.start, range.length, range.permissions]);
+		}
+		DebugInfo::from_source(0, &self.words, &self.symbols, &self.files).write(&mut words);
+		return words;
+	}
+
+	pub fn from_words(words: &[storage]) -> Result<Object, String> {
+		let mut reader = Reader::new(words);
+		if reader.next()? != OBJECT_MAGIC {
+			return Err("not an object file".to_string());
+		}
+		let version = reader.next()?;
+		if version != OBJECT_VERSION {
+			return Err(format!("object file version {} is not supported", version));
+		}
+
+		let length = reader.next()? as usize;
+		let image = reader.take(length)?.to_vec();
+		let mut exports = Vec::new();
+		for _ in 0 .. reader.next()? {
+			let address = reader.address(length)?;
+			let kind = word_kind(reader.next()?)?;
+			let name = reader.name()?;
+			exports.push(Export {
+				name,
+				kind,
+				address,
+			});
+		}
+		let mut imports = Vec::new();
+		for _ in 0 .. reader.next()? {
+			imports.push(reader.name()?);
+		}
+		let mut relocations = Vec::new();
+		for _ in 0 .. reader.next()? {
+			let address = reader.address(length)?;
+			let target = match reader.next()? {
+				0 => RelocationTarget::Object,
+				target if target as usize <= imports.len() => RelocationTarget::Import(target as usize - 1),
+				target => return Err(format!("relocation to import {} but there are only {}", target, imports.len())),
+			};
+			relocations.push(Relocation {
+				address,
+				target,
+			});
+		}
+		let mut protections = Vec::new();
+		for _ in 0 .. reader.next()? {
+			let start = reader.address(length)?;
+			let range_length = reader.next()?;
+			if range_length as usize > length - start as usize {
+				return Err(format!("protected range at {} goes past the end of the object", start));
+			}
+			let permissions = reader.next()?;
+			if permissions & !PERM_ALL != 0 {
+				return Err(format!("{} are not permissions", permissions));
+			}
+			protections.push(ImageRange {
+				start,
+				length: range_length,
+				permissions,
+			});
+		}
+		let debug = DebugInfo::read(&mut reader)?;
+		reader.end()?;
+
+		let words = debug.to_source(0, &image);
+		return Ok(Object {
+			image,
+			relocations,
+			exports,
+			imports,
+			words,
+			symbols: debug.symbols,
+			files: debug.files,
+			protections,
+		});
+	}
+
+	// little endian, like the binary images dsl-run loads
+	pub fn to_bytes(&self) -> Vec<u8> {
+		return self.to_words().iter().flat_map(|word| word.to_le_bytes()).collect();
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Object, String> {
+		if !bytes.len().is_multiple_of(4) {
+			return Err(format!("object is {} bytes long, not a whole number of words", bytes.len()));
+		}
+		let words: Vec<storage> = bytes.chunks(4)
+			.map(|word| storage::from_le_bytes([word[0], word[1], word[2], word[3]]))
+			.collect();
+		return Object::from_words(&words);
+	}
+}
+
+pub(crate) fn kind_word(kind: SymbolKind) -> storage {
+	match kind {
+		SymbolKind::Label => {
+			return EXPORT_LABEL;
+		},
+		SymbolKind::Variable(VariableType::Unit) => {
+			return EXPORT_UNIT;
+		},
+		SymbolKind::Variable(VariableType::Array) => {
+			return EXPORT_ARRAY;
+		},
+	}
+}
+
+pub(crate) fn word_kind(word: storage) -> Result<SymbolKind, String> {
+	match word {
+		EXPORT_LABEL => {
+			return Ok(SymbolKind::Label);
+		},
+		EXPORT_UNIT => {
+			return Ok(SymbolKind::Variable(VariableType::Unit));
+		},
+		EXPORT_ARRAY => {
+			return Ok(SymbolKind::Variable(VariableType::Array));
+		},
+		kind => {
+			return Err(format!("{} is not a symbol kind", kind));
+		},
+	}
+}
+
+pub(crate) fn push_name(words: &mut Vec<storage>, name: &str) {
+	let chars: Vec<storage> = name.chars().map(|c| c as storage).collect();
+	words.push(chars.len() as storage);
+	words.extend(chars);
+}
+
+pub(crate) struct Reader<'a> {
+	words: &'a [storage],
+	index: usize,
+}
+
+impl<'a> Reader<'a> {
+	pub(crate) fn new(words: &'a [storage]) -> Reader<'a> {
+		return Reader {
+			words,
+			index: 0,
+		};
+	}
+
+	fn take(&mut self, count: usize) -> Result<&'a [storage], String> {
+		if count > self.words.len() - self.index {
+			return Err("the object file ends too soon".to_string());
+		}
+		self.index += count;
+		return Ok(&self.words[self.index - count .. self.index]);
+	}
+
+	pub(crate) fn next(&mut self) -> Result<storage, String> {
+		return Ok(self.take(1)?[0]);
+	}
+
+	// an address inside an image of the length
+	fn address(&mut self, length: usize) -> Result<location, String> {
+		let address = self.next()?;
+		if address as usize >= length {
+			return Err(format!("address {} is outside of the object", address));
+		}
+		return Ok(address);
+	}
+
+	pub(crate) fn name(&mut self) -> Result<String, String> {
+		let length = self.next()?;
+		if length > MAX_NAME_LENGTH {
+			return Err(format!("name of {} characters is too long", length));
+		}
+		let name = self.take(length as usize)?.iter()
+			.map(|&c| ::std::char::from_u32(c).ok_or(format!("{} is not a character", c)))
+			.collect::<Result<String, String>>()?;
+		return Ok(name);
+	}
+
+	// nothing is left over
+	pub(crate) fn end(&self) -> Result<(), String> {
+		if self.index != self.words.len() {
+			return Err(format!("{} words after the end", self.words.len() - self.index));
+		}
+		return Ok(());
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkError {
+	// the first file of the object, or its index if it has none
+	pub object: String,
+	pub message: String,
+}
+
+impl fmt::Display for LinkError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return write!(f, "{}: {}", self.object, self.message);
+	}
+}
+
+// one program image from the objects, the first is the program
+pub fn link(objects: &[Object]) -> Result<Assembly, Vec<LinkError>> {
+	let mut errors = Vec::new();
+	let object_name = |index: usize| match objects[index].files.first() {
+		Some(name) if !name.is_empty() => name.clone(),
+		_ => format!("object {}", index + 1),
+	};
+
+	let mut bases = Vec::new();
+	let mut base = LOAD_ADDRESS;
+	for (index, object) in objects.iter().enumerate() {
+		bases.push(base);
+		base = match base.checked_add(object.image.len() as location) {
+			Some(next) => next,
+			None => {
+				errors.push(LinkError {
+					object: object_name(index),
+					message: "the objects do not fit in memory".to_string(),
+				});
+				return Err(errors);
+			},
+		};
+	}
+	// an address from the start of an object, none if a malformed object
+	// points past the end of memory
+	let relocated = |index: usize, address: location| bases[index].checked_add(address);
+	let outside = |index: usize, what: &str, address: location| LinkError {
+		object: object_name(index),
+		message: format!("{} at {} is outside of memory", what, address),
+	};
+
+	let mut exports: HashMap<&str, (location, usize)> = HashMap::new();
+	for (index, object) in objects.iter().enumerate() {
+		for export in object.exports.iter() {
+			if let Some(&(_, other)) = exports.get(export.name.as_str()) {
+				errors.push(LinkError {
+					object: object_name(index),
+					message: format!("'{}' is already exported by {}", export.name, object_name(other)),
+				});
+				continue;
+			}
+			match relocated(index, export.address) {
+				Some(address) => {
+					exports.insert(&export.name, (address, index));
+				},
+				None => errors.push(outside(index, &format!("export '{}'", export.name), export.address)),
+			}
+		}
+	}
+
+	let mut image = Vec::new();
+	let mut words = Vec::new();
+	let mut symbols = Vec::new();
+	let mut files = Vec::new();
+	let mut protections = Vec::new();
+	for (index, object) in objects.iter().enumerate() {
+		for name in object.imports.iter() {
+			if !exports.contains_key(name.as_str()) {
+				errors.push(LinkError {
+					object: object_name(index),
+					message: format!("'{}' is imported but no object exports it", name),
+				});
+			}
+		}
+	}
+	if !errors.is_empty() {
+		return Err(errors);
+	}
+
+	for (index, object) in objects.iter().enumerate() {
+		let start = image.len();
+		image.extend_from_slice(&object.image);
+		for relocation in object.relocations.iter() {
+			let address = match relocation.target {
+				RelocationTarget::Object => bases[index],
+				RelocationTarget::Import(import) => exports[object.imports[import].as_str()].0,
+			};
+			match object.image.get(relocation.address as usize) {
+				Some(_) => {
+					let word = &mut image[start + relocation.address as usize];
+					*word = word.wrapping_add(address);
+				},
+				None => errors.push(outside(index, "a relocation", relocation.address)),
+			}
+		}
+
+		// debug info, with the spans pointing at the files of the object
+		let file_offset = files.len();
+		let relocate = |span: ::assembler::Span| ::assembler::Span { file: span.file + file_offset, ..span };
+		words.extend(object.words.iter().map(|word| Word { span: word.span.map(relocate), ..word.clone() }));
+		for symbol in object.symbols.iter() {
+			match relocated(index, symbol.address) {
+				Some(address) => symbols.push(Symbol {
+					address,
+					span: relocate(symbol.span),
+					..symbol.clone()
+				}),
+				None => errors.push(outside(index, &format!("symbol '{}'", symbol.name), symbol.address)),
+			}
+		}
+		files.extend(object.files.iter().cloned());
+		for range in object.protections.iter() {
+			match relocated(index, range.start) {
+				Some(start) => protections.push(ImageRange { start, ..*range }),
+				None => errors.push(outside(index, "a protected range", range.start)),
+			}
+		}
+	}
+	if !errors.is_empty() {
+		return Err(errors);
+	}
+
+	return Ok(Assembly {
+		image,
+		words,
+		symbols,
+		files,
+		protections,
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::assemble_object;
+	use opcodes::{LOAD, LOAD_IMMEDIATE, JUMP, HALT};
+
+	fn object(source: &str) -> Object {
+		return assemble_object("test.dsla", source, &mut |_| Err(String::new())).unwrap();
+	}
+
+	const PROGRAM: &str = ".import helper\n.data\nvar local number 5\n.text\nLoadValueAtAddressIntoBus local\ngoto helper";
+	const LIBRARY: &str = ".export helper\n.text\n@helper\nHalt";
+
+	#[test]
+	fn addresses_are_relocated_to_where_the_objects_land() {
+		let program = object(PROGRAM);
+		let library = object(LIBRARY);
+		// the library lands right after the program
+		let helper = LOAD_ADDRESS + program.image.len() as location + library.exports[0].address;
+		let local = LOAD_ADDRESS + 3;
+		let assembly = link(&[program, library]).unwrap();
+		assert_eq!(assembly.image[.. 10].to_vec(), vec![
+			LOAD_IMMEDIATE, LOAD_ADDRESS + 4, JUMP,
+			5,
+			LOAD, local, LOAD_IMMEDIATE, helper, JUMP,
+			HALT,
+		]);
+		let symbol = |name: &str| assembly.symbols.iter().find(|symbol| symbol.name == name).unwrap().address;
+		assert_eq!((symbol("local"), symbol("helper")), (local, helper));
+		assert_eq!(assembly.image[(helper - LOAD_ADDRESS) as usize], HALT);
+		assert_eq!(assembly.files, vec!["test.dsla", "test.dsla"]);
+	}
+
+	#[test]
+	fn objects_survive_their_bytes() {
+		let source = ".export start\n.import helper\n.protect .data r\n.data\nvar table array 1 2\n.text\n@start\nLoadValueAtAddressIntoBus table\ngoto helper";
+		let written = object(source);
+		let read = Object::from_bytes(&written.to_bytes()).unwrap();
+		assert_eq!(read.image, written.image);
+		assert_eq!(read.relocations, written.relocations);
+		assert_eq!(read.exports, written.exports);
+		assert_eq!(read.imports, written.imports);
+		assert_eq!(read.files, written.files);
+		assert_eq!(read.protections, written.protections);
+		let symbols = |object: &Object| object.symbols.iter().map(|symbol| (symbol.name.clone(), symbol.kind, symbol.address, symbol.span)).collect::<Vec<_>>();
+		assert_eq!(symbols(&read), symbols(&written));
+		// the debug info only holds words made from the source, the jump and
+		// halt the assembler adds have no span
+		let words = |object: &Object| object.words.iter().map(|word| (word.span, word.span.is_some() && word.code)).collect::<Vec<_>>();
+		assert_eq!(words(&read), words(&written));
+		assert_eq!(read.to_bytes(), written.to_bytes());
+	}
+
+	#[test]
+	fn other_versions_are_refused() {
+		let mut words = object(LIBRARY).to_words();
+		for &version in [0, OBJECT_VERSION + 1].iter() {
+			words[1] = version;
+			assert_eq!(Object::from_words(&words).err().unwrap(), format!("object file version {} is not supported", version));
+		}
+		assert_eq!(Object::from_words(&[0, OBJECT_VERSION]).err().unwrap(), "not an object file");
+		assert!(Object::from_bytes(&[1, 2, 3]).is_err());
+	}
+
+	#[test]
+	fn duplicate_exports_are_link_errors() {
+		let mut second = object(LIBRARY);
+		second.files = vec!["second.dsla".to_string()];
+		let errors = link(&[object(PROGRAM), object(LIBRARY), second]).err().unwrap();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].to_string(), "second.dsla: 'helper' is already exported by test.dsla");
+	}
+
+	#[test]
+	fn unresolved_imports_are_link_errors() {
+		let errors = link(&[object(PROGRAM)]).err().unwrap();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].to_string(), "test.dsla: 'helper' is imported but no object exports it");
+		let mut unnamed = object(PROGRAM);
+		unnamed.files.clear();
+		assert_eq!(link(&[unnamed]).err().unwrap()[0].object, "object 1");
+	}
+
+	#[test]
+	fn malformed_addresses_are_link_errors() {
+		let mut malformed = object(".text\n@start\nHalt");
+		malformed.symbols[0].address = location::MAX;
+		// the address survives the object file, the debug info does not check it
+		let read = Object::from_words(&malformed.to_words()).unwrap();
+		let errors = link(&[read]).err().unwrap();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].object, "test.dsla");
+		assert!(errors[0].message.contains("outside of memory"));
+
+		let mut malformed = object(".text\n@start\nHalt");
+		malformed.exports.push(Export {
+			name: "start".to_string(),
+			kind: SymbolKind::Label,
+			address: location::MAX,
+		});
+		assert!(link(&[malformed]).is_err());
+
+		let mut malformed = object(".text\n@start\nHalt");
+		malformed.relocations.push(Relocation {
+			address: location::MAX,
+			target: RelocationTarget::Object,
+		});
+		assert!(link(&[malformed]).is_err());
+	}
+}
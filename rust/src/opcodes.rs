@@ -0,0 +1,106 @@
+// The opcodes the processor runs, see Processor::step in lib.rs.
+//
+// Names are the same as _DslOpcodes in js/src/utils/language/dslmachine.ts.
+// Tools that read or write machine code (the listing loader, the
+// assembler) use this table instead of their own copy of the encoding.
+
+use super::storage;
+
+pub struct Opcode {
+	pub code: storage,
+	pub name: &'static str,
+	// words that follow the opcode
+	pub parameters: usize,
+}
+
+pub const NOOP: storage = 0;
+pub const LOAD: storage = 1;
+pub const SAVE: storage = 2;
+pub const LOAD_RELATIVE: storage = 3;
+pub const SAVE_RELATIVE: storage = 4;
+pub const LOAD_CONSTANT_OFFSET: storage = 5;
+pub const SAVE_CONSTANT_OFFSET: storage = 6;
+pub const LOAD_BUS_RELATIVE: storage = 7;
+pub const ADD: storage = 9;
+pub const MULTIPLY: storage = 11;
+pub const DIVIDE: storage = 12;
+pub const JUMP: storage = 13;
+pub const BRANCH: storage = 14;
+pub const LINK: storage = 15;
+pub const HI_TO_BUS: storage = 16;
+pub const LO_TO_BUS: storage = 17;
+pub const ALU_TO_INT: storage = 18;
+pub const ALU_TO_FLOAT: storage = 19;
+pub const NEW_BLOCK: storage = 20;
+pub const SYSCALL: storage = 21;
+pub const HALT: storage = 22;
+pub const PAUSE: storage = 23;
+pub const LOAD_IMMEDIATE: storage = 24;
+pub const PUSH_TO_ALU: storage = 25;
+pub const LOAD_VARIABLE_OFFSET: storage = 26;
+pub const SAVE_VARIABLE_OFFSET: storage = 27;
+pub const GET_POSITION: storage = 28;
+pub const COMPARE: storage = 29;
+pub const OR: storage = 30;
+pub const AND: storage = 31;
+pub const SHIFT_LEFT: storage = 32;
+pub const SHIFT_RIGHT: storage = 33;
+pub const ENABLE_INTERRUPTS: storage = 34;
+pub const DISABLE_INTERRUPTS: storage = 35;
+pub const RETURN_FROM_INTERRUPT: storage = 36;
+pub const SET_VECTOR_TABLE: storage = 37;
+
+const fn op(code: storage, name: &'static str, parameters: usize) -> Opcode {
+	Opcode {
+		code,
+		name,
+		parameters,
+	}
+}
+
+pub static OPCODES: [Opcode; 36] = [
+	op(NOOP, "Noop", 0),
+	op(LOAD, "LoadValueAtAddressIntoBus", 1),
+	op(SAVE, "SaveValueInBusToLocation", 1),
+	op(LOAD_RELATIVE, "LoadWithConstantOffsetFromHereToBus", 1),
+	op(SAVE_RELATIVE, "SaveFromBusWithConstantOffsetFromHere", 1),
+	op(LOAD_CONSTANT_OFFSET, "LoadWithConstantOffsetToBus", 2),
+	op(SAVE_CONSTANT_OFFSET, "SaveFromBusWithConstantOffset", 2),
+	op(LOAD_BUS_RELATIVE, "LoadWithBusAsConstantOffsetFromHere", 0),
+	op(ADD, "AluDoAdd", 0),
+	op(MULTIPLY, "AluMultiply", 0),
+	op(DIVIDE, "AluDivide", 0),
+	op(JUMP, "JumpWithBusValueRelative", 0),
+	op(BRANCH, "BranchTo", 1),
+	op(LINK, "LinkIfBranched", 0),
+	op(HI_TO_BUS, "AluHiToBus", 0),
+	op(LO_TO_BUS, "AluLoToBus", 0),
+	op(ALU_TO_INT, "AluToInt", 0),
+	op(ALU_TO_FLOAT, "AluToFloat", 0),
+	op(NEW_BLOCK, "NewBlock", 0),
+	op(SYSCALL, "Syscall", 1),
+	op(HALT, "Halt", 0),
+	op(PAUSE, "Pause", 0),
+	op(LOAD_IMMEDIATE, "LoadImmmediateToBus", 1),
+	op(PUSH_TO_ALU, "AluPushFromBus", 0),
+	op(LOAD_VARIABLE_OFFSET, "LoadWithVariableOffsetToBus", 2),
+	op(SAVE_VARIABLE_OFFSET, "SaveFromBusWithVariableOffset", 2),
+	op(GET_POSITION, "GetCurrentPosition", 0),
+	op(COMPARE, "AluDoComparisonWithMode", 1),
+	op(OR, "Or", 0),
+	op(AND, "And", 0),
+	op(SHIFT_LEFT, "ShiftLeft", 0),
+	op(SHIFT_RIGHT, "ShiftRight", 0),
+	op(ENABLE_INTERRUPTS, "EnableInterrupts", 0),
+	op(DISABLE_INTERRUPTS, "DisableInterrupts", 0),
+	op(RETURN_FROM_INTERRUPT, "ReturnFromInterrupt", 0),
+	op(SET_VECTOR_TABLE, "SetInterruptVectorTable", 1),
+];
+
+pub fn opcode(code: storage) -> Option<&'static Opcode> {
+	return OPCODES.iter().find(|op| op.code == code);
+}
+
+pub fn opcode_named(name: &str) -> Option<&'static Opcode> {
+	return OPCODES.iter().find(|op| op.name == name);
+}
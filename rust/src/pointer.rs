@@ -0,0 +1,270 @@
+// Pointer (mouse) device.
+//
+// The host pushes moves, button presses and releases and wheel turns with
+// r_PushPointerEvent, in the coordinates of the element the screen is shown
+// in. They are scaled to screen buffer pixels before they are queued, so
+// programs never have to know how large the screen is displayed.
+//
+// Programs can read the current state from the registers, or take the
+// events one at a time with the PollPointer syscall.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use super::{Processor, storage, location};
+use mmio::{Device, MMIO_BASE};
+use drawing::signed;
+
+pub const POINTER_BASE: location = MMIO_BASE + 0x3000;
+
+// registers, all read only except POINTER_WHEEL
+// x and y are signed, the pointer can be outside of the screen
+pub const POINTER_X: storage = 0;
+pub const POINTER_Y: storage = 1;
+// bit n is set while button n is held, 0 is the main button
+pub const POINTER_BUTTONS: storage = 2;
+// signed sum of the wheel turns, writing clears it
+pub const POINTER_WHEEL: storage = 3;
+pub const POINTER_PENDING: storage = 4;
+
+pub const POINTER_SIZE: storage = 5;
+
+pub const POINTER_MOVE: storage = 1;
+pub const POINTER_DOWN: storage = 2;
+pub const POINTER_UP: storage = 3;
+pub const POINTER_SCROLL: storage = 4;
+
+pub const MAX_POINTER_EVENTS: usize = 64;
+const MAX_BUTTON: storage = 31;
+
+// events are [kind, x, y, data], data is the button for POINTER_DOWN and
+// POINTER_UP and the signed wheel delta for POINTER_SCROLL
+pub const POINTER_EVENT_WORDS: storage = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PointerEvent {
+	pub kind: storage,
+	pub x: i32,
+	pub y: i32,
+	pub data: storage,
+}
+
+pub struct Pointer {
+	events: VecDeque<PointerEvent>,
+	x: i32,
+	y: i32,
+	buttons: storage,
+	wheel: i32,
+}
+
+impl Pointer {
+	pub fn new() -> Pointer {
+		let events = VecDeque::with_capacity(MAX_POINTER_EVENTS);
+		let x = 0;
+		let y = 0;
+		let buttons = 0;
+		let wheel = 0;
+		Pointer {
+			events,
+			x,
+			y,
+			buttons,
+			wheel,
+		}
+	}
+
+	// returns false if the event was dropped, because it is invalid or
+	// because the queue is full
+	// the state registers are updated either way
+	pub fn push(&mut self, event: PointerEvent) -> bool {
+		match event.kind {
+			POINTER_MOVE => {},
+			POINTER_DOWN if event.data <= MAX_BUTTON => self.buttons |= 1 << event.data,
+			POINTER_UP if event.data <= MAX_BUTTON => self.buttons &= !(1 << event.data),
+			POINTER_SCROLL => self.wheel = self.wheel.saturating_add(signed(event.data)),
+			_ => return false,
+		}
+		self.x = event.x;
+		self.y = event.y;
+
+		// a program only cares where the pointer ended up, so moves in a row
+		// take a single slot
+		if event.kind == POINTER_MOVE {
+			if let Some(last) = self.events.back_mut() {
+				if last.kind == POINTER_MOVE {
+					*last = event;
+					return true;
+				}
+			}
+		}
+		if self.events.len() == MAX_POINTER_EVENTS {
+			return false;
+		}
+		self.events.push_back(event);
+		return true;
+	}
+
+	pub fn next_event(&mut self) -> Option<PointerEvent> {
+		return self.events.pop_front();
+	}
+
+	pub fn position(&self) -> (i32, i32) {
+		return (self.x, self.y);
+	}
+
+	pub fn buttons(&self) -> storage {
+		return self.buttons;
+	}
+}
+
+impl Device for Pointer {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			POINTER_X => {
+				return self.x as storage;
+			},
+			POINTER_Y => {
+				return self.y as storage;
+			},
+			POINTER_BUTTONS => {
+				return self.buttons;
+			},
+			POINTER_WHEEL => {
+				return self.wheel as storage;
+			},
+			POINTER_PENDING => {
+				return self.events.len() as storage;
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+
+	fn write(&mut self, offset: storage, _value: storage) {
+		if offset == POINTER_WHEEL {
+			self.wheel = 0;
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+impl Processor {
+	// (x, y) is a position in an element display_width * display_height
+	// large that shows the whole screen
+	pub fn push_pointer_event(&mut self, kind: storage, x: i32, y: i32, data: storage, display_width: i32, display_height: i32) -> bool {
+		if display_width <= 0 || display_height <= 0 {
+			return false;
+		}
+		let (width, height) = self.screen_size();
+		let event = PointerEvent {
+			kind,
+			x: scale(x, width as i64, display_width),
+			y: scale(y, height as i64, display_height),
+			data,
+		};
+		return match self.mmio.device::<Pointer>() {
+			Some(pointer) => pointer.push(event),
+			None => false,
+		};
+	}
+}
+
+// rounds towards negative infinity, so positions just left of or above
+// the screen stay negative
+fn scale(position: i32, size: i64, display_size: i32) -> i32 {
+	return (position as i64 * size).div_euclid(display_size as i64) as i32;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use buffers::BUFFER_OUTPUT_SCREEN_SIZE;
+	use testing::Machine;
+
+	fn event(kind: storage, x: i32, y: i32, data: storage) -> PointerEvent {
+		PointerEvent {
+			kind,
+			x,
+			y,
+			data,
+		}
+	}
+
+	#[test]
+	fn moves_in_a_row_take_one_slot() {
+		let mut pointer = Pointer::new();
+		assert!(pointer.push(event(POINTER_MOVE, 1, 1, 0)));
+		assert!(pointer.push(event(POINTER_MOVE, 2, 3, 0)));
+		assert!(pointer.push(event(POINTER_DOWN, 2, 3, 0)));
+		assert!(pointer.push(event(POINTER_MOVE, 4, 4, 0)));
+		assert_eq!(pointer.peek(POINTER_PENDING), 3);
+		assert_eq!(pointer.next_event(), Some(event(POINTER_MOVE, 2, 3, 0)));
+		assert_eq!(pointer.next_event(), Some(event(POINTER_DOWN, 2, 3, 0)));
+		assert_eq!(pointer.next_event(), Some(event(POINTER_MOVE, 4, 4, 0)));
+		assert_eq!(pointer.next_event(), None);
+	}
+
+	#[test]
+	fn a_full_queue_drops_events_but_keeps_the_state() {
+		let mut pointer = Pointer::new();
+		for i in 0 .. MAX_POINTER_EVENTS {
+			assert!(pointer.push(event(POINTER_SCROLL, 0, 0, i as storage % 2)));
+		}
+		assert!(!pointer.push(event(POINTER_DOWN, 5, 6, 1)));
+		assert_eq!(pointer.peek(POINTER_PENDING), MAX_POINTER_EVENTS as storage);
+		assert_eq!(pointer.position(), (5, 6));
+		assert_eq!(pointer.buttons(), 2);
+		assert_eq!(pointer.peek(POINTER_WHEEL), MAX_POINTER_EVENTS as storage / 2);
+		pointer.write(POINTER_WHEEL, 0);
+		assert_eq!(pointer.peek(POINTER_WHEEL), 0);
+	}
+
+	#[test]
+	fn invalid_events_are_refused() {
+		let mut pointer = Pointer::new();
+		assert!(!pointer.push(event(0, 1, 1, 0)));
+		assert!(!pointer.push(event(POINTER_DOWN, 1, 1, MAX_BUTTON + 1)));
+		assert_eq!(pointer.position(), (0, 0));
+		assert_eq!(pointer.peek(POINTER_PENDING), 0);
+	}
+
+	#[test]
+	fn positions_are_scaled_to_the_screen() {
+		let mut machine = Machine::new(".data\nvar state array 0 0 0\n.text\nLoadImmmediateToBus state\nSyscall 63");
+		machine.buffer(100, &[320, 200], BUFFER_OUTPUT_SCREEN_SIZE);
+		assert!(machine.processor.push_pointer_event(POINTER_DOWN, 101, 51, 0, 640, 400));
+		assert!(!machine.processor.push_pointer_event(POINTER_MOVE, 1, 1, 0, 0, 400));
+		machine.run();
+		assert_eq!((0 .. 3).map(|i| machine.word("state", i)).collect::<Vec<_>>(), vec![50, 25, 1]);
+
+		// just left of the screen stays negative
+		machine.processor.push_pointer_event(POINTER_MOVE, -1, 0, 0, 640, 400);
+		let pointer = machine.processor.mmio.device::<Pointer>().unwrap();
+		assert_eq!(pointer.position(), (-1, 0));
+	}
+
+	#[test]
+	fn poll_pointer_writes_the_event() {
+		let mut machine = Machine::new("
+.data
+var event array 0 0 0 0
+.text
+LoadImmmediateToBus event
+Syscall 62
+Pause
+LoadImmmediateToBus event
+Syscall 62
+");
+		machine.processor.mmio.device::<Pointer>().unwrap().push(event(POINTER_SCROLL, 3, 4, -2i32 as storage));
+		machine.run();
+		assert_eq!(machine.processor.bus, 1);
+		assert_eq!((0 .. 4).map(|i| machine.word("event", i)).collect::<Vec<_>>(), vec![POINTER_SCROLL, 3, 4, -2i32 as storage]);
+		// nothing left, the words are not touched
+		machine.run();
+		assert_eq!(machine.processor.bus, 0);
+		assert_eq!(machine.word("event", 0), POINTER_SCROLL);
+	}
+}
@@ -0,0 +1,404 @@
+// Per-range memory permissions.
+//
+// Addresses that are not covered by any range can be read, written and
+// executed, so programs that never set permissions behave as before.
+//
+// The ranges the image declares, or the host sets, are locked: the ranges a
+// program sets with Protect can take permissions away from them but never
+// give any back, so a program can't make its read-only code writable.
+
+use super::{storage, location};
+
+// how many ranges a program can set with Protect, ranges that a later one
+// hides completely don't count
+pub const MAX_RANGES: usize = 256;
+
+pub const PERM_READ: storage = 1;
+pub const PERM_WRITE: storage = 2;
+pub const PERM_EXECUTE: storage = 4;
+pub const PERM_ALL: storage = PERM_READ | PERM_WRITE | PERM_EXECUTE;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FaultKind {
+	None = 0,
+	// read from memory without PERM_READ
+	Read = 1,
+	// write to memory without PERM_WRITE
+	Write = 2,
+	// instruction or parameter fetched from memory without PERM_EXECUTE
+	Execute = 3,
+	// access past the end of the last memory block that no device claimed
+	Unmapped = 4,
+	// return from interrupt outside of an interrupt handler
+	InvalidReturn = 5,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fault {
+	pub kind: FaultKind,
+	// the address that was accessed
+	pub address: location,
+	// the instruction that made the access
+	pub instruction: location,
+}
+
+// a range of a program image and its permissions, from .protect in
+// assembly, see assembler.rs, or #[protect] in a listing, see listing.rs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageRange {
+	pub start: location,
+	pub length: storage,
+	pub permissions: storage,
+}
+
+// the letters r, w and x in any order, or - for none
+pub fn parse_permissions(text: &str) -> Option<storage> {
+	if text == "-" {
+		return Some(0);
+	}
+	let mut permissions = 0;
+	for letter in text.chars() {
+		let permission = match letter {
+			'r' => PERM_READ,
+			'w' => PERM_WRITE,
+			'x' => PERM_EXECUTE,
+			_ => return None,
+		};
+		if permissions & permission != 0 {
+			return None;
+		}
+		permissions |= permission;
+	}
+	if permissions == 0 {
+		return None;
+	}
+	return Some(permissions);
+}
+
+pub fn permissions_text(permissions: storage) -> String {
+	let letters: String = [(PERM_READ, 'r'), (PERM_WRITE, 'w'), (PERM_EXECUTE, 'x')].iter()
+		.filter(|&&(permission, _)| permissions & permission != 0)
+		.map(|&(_, letter)| letter)
+		.collect();
+	if letters.is_empty() {
+		return "-".to_string();
+	}
+	return letters;
+}
+
+struct Range {
+	start: location,
+	end: location, // exclusive
+	permissions: storage,
+}
+
+// later ranges take priority over earlier ones where they overlap
+fn add_range(ranges: &mut Vec<Range>, start: location, length: storage, permissions: storage) {
+	let end = start.saturating_add(length);
+	// ranges that are completely hidden by the new one can never be seen again
+	ranges.retain(|r| r.start < start || r.end > end);
+	ranges.push(Range {
+		start,
+		end,
+		permissions: permissions & PERM_ALL,
+	});
+}
+
+fn find_range(ranges: &[Range], address: location) -> storage {
+	for range in ranges.iter().rev() {
+		if address >= range.start && address < range.end {
+			return range.permissions;
+		}
+	}
+	return PERM_ALL;
+}
+
+pub struct Protection {
+	// declared by the image or set by the host, locked
+	image: Vec<Range>,
+	// set by the program
+	ranges: Vec<Range>,
+}
+
+impl Protection {
+	pub fn new() -> Protection {
+		let image = Vec::new();
+		let ranges = Vec::new();
+		Protection {
+			image,
+			ranges,
+		}
+	}
+
+	// false if the program already has MAX_RANGES ranges
+	pub fn set(&mut self, start: location, length: storage, permissions: storage) -> bool {
+		if length == 0 {
+			return true;
+		}
+		let end = start.saturating_add(length);
+		let hidden = self.ranges.iter().filter(|r| r.start >= start && r.end <= end).count();
+		if self.ranges.len() - hidden >= MAX_RANGES {
+			return false;
+		}
+		add_range(&mut self.ranges, start, length, permissions);
+		return true;
+	}
+
+	// a range of the image, Protect can't raise its permissions
+	pub fn lock(&mut self, start: location, length: storage, permissions: storage) {
+		if length == 0 {
+			return;
+		}
+		add_range(&mut self.image, start, length, permissions);
+	}
+
+	pub fn clear(&mut self) {
+		self.image.clear();
+		self.ranges.clear();
+	}
+
+	pub fn permissions(&self, address: location) -> storage {
+		return find_range(&self.image, address) & find_range(&self.ranges, address);
+	}
+
+	pub fn allows(&self, address: location, permission: storage) -> bool {
+		return self.permissions(address) & permission == permission;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use exit::ExitReason;
+	use random::{RANDOM_BASE, RANDOM_SEED};
+	use testing::Machine;
+	use assembler::{assemble, assemble_object};
+	use listing::{Listing, LOAD_ADDRESS};
+	use object::Object;
+
+	// protects the variable `guarded` with the permissions, then runs the code
+	fn guarded(permissions: storage, code: &str) -> Machine {
+		let mut machine = Machine::new(&format!("
+.data
+var args array 0 1 {}
+var guarded number 7
+var target number 7
+var index number 0
+.text
+LoadImmmediateToBus guarded
+SaveValueInBusToLocation args
+LoadImmmediateToBus args
+Syscall 42
+LoadImmmediateToBus 99
+{}
+", permissions, code));
+		machine.run();
+		return machine;
+	}
+
+	fn fault(machine: &Machine) -> (FaultKind, location) {
+		let fault = machine.processor.fault.expect("the program didn't fault");
+		assert_eq!(machine.processor.exit.reason, ExitReason::Fault);
+		return (fault.kind, fault.address);
+	}
+
+	#[test]
+	fn guarded_write_leaves_memory_unchanged() {
+		let mut machine = guarded(PERM_READ, "SaveFromBusWithVariableOffset guarded index");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Write, address));
+		assert_eq!(machine.word("guarded", 0), 7);
+	}
+
+	#[test]
+	fn faulting_offset_read_stops_the_write() {
+		// the offset is read from the guarded word, target must not be written
+		let mut machine = guarded(0, "SaveFromBusWithVariableOffset target guarded");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Read, address));
+		assert_eq!(machine.word("target", 0), 7);
+	}
+
+	#[test]
+	fn faulting_read_leaves_the_bus() {
+		let machine = guarded(PERM_WRITE, "LoadValueAtAddressIntoBus guarded");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Read, address));
+		assert_eq!(machine.processor.bus, 99);
+	}
+
+	#[test]
+	fn faulting_instruction_is_the_next_one() {
+		let machine = guarded(PERM_READ, "SaveValueInBusToLocation guarded");
+		let fault = machine.processor.fault.unwrap();
+		assert_eq!(machine.processor.next, fault.instruction);
+		assert_eq!(machine.processor.exit.address, fault.instruction);
+	}
+
+	#[test]
+	fn fetching_data_faults() {
+		// jumps into the variables, which aren't executable once protected
+		let machine = guarded(PERM_READ | PERM_WRITE, "LoadImmmediateToBus guarded\nJumpWithBusValueRelative");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Execute, address));
+		assert_eq!(machine.processor.bus, address);
+	}
+
+	#[test]
+	fn syscall_read_faults() {
+		let machine = guarded(0, "LoadImmmediateToBus guarded\nSyscall 32");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Read, address));
+		assert_eq!(machine.processor.bus, address);
+	}
+
+	#[test]
+	fn syscall_write_faults() {
+		// PointerState writes 3 words, the first one already faults
+		let mut machine = guarded(PERM_READ, "LoadImmmediateToBus guarded\nSyscall 63");
+		let address = machine.address("guarded");
+		assert_eq!(fault(&machine), (FaultKind::Write, address));
+		assert_eq!(machine.word("guarded", 0), 7);
+		assert_eq!(machine.word("target", 0), 7);
+	}
+
+	#[test]
+	fn protected_code_is_not_writable() {
+		let mut machine = Machine::new("
+.protect .text rx
+.data
+var value number 7
+.text
+@start
+LoadImmmediateToBus 5
+SaveValueInBusToLocation start
+");
+		machine.run();
+		let address = machine.address("start");
+		assert_eq!(fault(&machine), (FaultKind::Write, address));
+	}
+
+	#[test]
+	fn protect_cannot_make_code_writable() {
+		let mut machine = Machine::new(&format!("
+.protect .text rx
+.data
+var args array start 1 {}
+.text
+@start
+LoadImmmediateToBus args
+Syscall 42
+LoadImmmediateToBus 5
+SaveValueInBusToLocation start
+", PERM_ALL));
+		machine.run();
+		let address = machine.address("start");
+		assert_eq!(fault(&machine), (FaultKind::Write, address));
+	}
+
+	#[test]
+	fn protected_variable_is_read_only() {
+		let mut machine = Machine::new("
+.protect table r
+.data
+var table array 1 2
+var value number 7
+.text
+LoadImmmediateToBus 5
+SaveValueInBusToLocation value
+LoadImmmediateToBus 5
+SaveValueInBusToLocation table + 2
+");
+		machine.run();
+		let address = machine.address("table");
+		assert_eq!(machine.word("value", 0), 5);
+		// the terminator of the array is protected too
+		assert_eq!(fault(&machine), (FaultKind::Write, address + 2));
+	}
+
+	#[test]
+	fn protections_survive_listings_and_objects() {
+		let source = ".protect .data r\n.protect .text rx\n.data\nvar value number 7\n.text\nLoadImmmediateToBus 5";
+		let assembly = assemble(source).unwrap();
+		assert_eq!(assembly.protections, vec![
+			ImageRange { start: LOAD_ADDRESS + 3, length: 1, permissions: PERM_READ },
+			ImageRange { start: LOAD_ADDRESS, length: 3, permissions: PERM_READ | PERM_EXECUTE },
+			ImageRange { start: LOAD_ADDRESS + 4, length: 3, permissions: PERM_READ | PERM_EXECUTE },
+		]);
+		let listing = Listing::parse(&assembly.to_listing()).unwrap();
+		assert_eq!(listing.protections, assembly.protections);
+		assert_eq!(listing.image, assembly.image);
+
+		let object = assemble_object("", source, &mut |_| Err(String::new())).unwrap();
+		let read = Object::from_words(&object.to_words()).unwrap();
+		assert_eq!(read.protections, object.protections);
+		assert_eq!(read.protections[0].start, 3);
+	}
+
+	#[test]
+	fn permissions_are_letters() {
+		assert_eq!(parse_permissions("rx"), Some(PERM_READ | PERM_EXECUTE));
+		assert_eq!(parse_permissions("xwr"), Some(PERM_ALL));
+		assert_eq!(parse_permissions("-"), Some(0));
+		assert_eq!(parse_permissions("rr"), None);
+		assert_eq!(parse_permissions(""), None);
+		assert_eq!(permissions_text(PERM_READ | PERM_WRITE), "rw");
+		assert_eq!(permissions_text(0), "-");
+	}
+
+	#[test]
+	fn locked_ranges_can_only_lose_permissions() {
+		let mut protection = Protection::new();
+		protection.lock(10, 10, PERM_READ | PERM_EXECUTE);
+		protection.set(0, 100, PERM_ALL);
+		assert_eq!(protection.permissions(10), PERM_READ | PERM_EXECUTE);
+		protection.set(12, 1, PERM_READ);
+		assert_eq!(protection.permissions(12), PERM_READ);
+		assert_eq!(protection.permissions(30), PERM_ALL);
+	}
+
+	#[test]
+	fn ranges_are_capped() {
+		let mut protection = Protection::new();
+		for i in 0 .. MAX_RANGES as storage {
+			assert!(protection.set(i * 2, 1, PERM_READ));
+		}
+		assert!(!protection.set(1, 1, PERM_READ));
+		// a range that hides the others replaces them
+		assert!(protection.set(0, MAX_RANGES as storage * 2, PERM_WRITE));
+		assert_eq!(protection.permissions(2), PERM_WRITE);
+	}
+
+	#[test]
+	fn ranges_override_earlier_ones() {
+		let mut protection = Protection::new();
+		protection.set(10, 10, PERM_READ);
+		protection.set(15, 2, PERM_ALL);
+		assert_eq!(protection.permissions(9), PERM_ALL);
+		assert_eq!(protection.permissions(10), PERM_READ);
+		assert_eq!(protection.permissions(15), PERM_ALL);
+		assert_eq!(protection.permissions(17), PERM_READ);
+		assert_eq!(protection.permissions(20), PERM_ALL);
+	}
+	#[test]
+	fn unmapped_read_faults() {
+		let mut machine = Machine::new(".text\nLoadValueAtAddressIntoBus 100000");
+		machine.run();
+		assert_eq!(fault(&machine), (FaultKind::Unmapped, 100000));
+	}
+
+	#[test]
+	fn unmapped_fetch_faults() {
+		let mut machine = Machine::new(".text\nLoadImmmediateToBus 100000\nJumpWithBusValueRelative");
+		machine.run();
+		assert_eq!(fault(&machine), (FaultKind::Unmapped, 100000));
+	}
+
+	#[test]
+	fn devices_are_mapped() {
+		let mut machine = Machine::new(&format!(".text\nLoadValueAtAddressIntoBus {}", RANDOM_BASE + RANDOM_SEED));
+		machine.run();
+		assert!(machine.processor.fault.is_none());
+		assert_eq!(machine.processor.exit.reason, ExitReason::Halt);
+	}
+}
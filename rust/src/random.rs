@@ -0,0 +1,176 @@
+// Seedable random number device.
+//
+// A PCG32 generator, so the numbers are good enough for games and
+// simulations while the same seed always gives the same numbers. The host
+// seeds it with r_SetRandomSeed and can save and restore the whole state
+// with r_GetRandomState and r_SetRandomState to make a run repeatable.
+// There are no machine snapshots, so the host keeps the state itself next
+// to whatever else it saves of a run.
+
+use std::any::Any;
+use super::{storage, location};
+use mmio::{Device, MMIO_BASE};
+
+pub const RANDOM_BASE: location = MMIO_BASE + 0x6000;
+
+// read only, every read returns the next number
+pub const RANDOM_NEXT: storage = 0;
+// writing seeds the generator, reading returns the last seed
+pub const RANDOM_SEED: storage = 1;
+
+pub const RANDOM_SIZE: storage = 2;
+
+// the seed a program starts with unless the host sets one
+pub const DEFAULT_SEED: storage = 0;
+
+const MULTIPLIER: u64 = 6364136223846793005;
+const INCREMENT: u64 = 1442695040888963407;
+
+pub struct Random {
+	state: u64,
+	seed: storage,
+}
+
+impl Random {
+	pub fn new() -> Random {
+		let mut random = Random {
+			state: 0,
+			seed: DEFAULT_SEED,
+		};
+		random.set_seed(DEFAULT_SEED);
+		return random;
+	}
+
+	// the way PCG's reference implementation seeds
+	pub fn set_seed(&mut self, seed: storage) {
+		self.seed = seed;
+		self.state = 0;
+		self.next();
+		self.state = self.state.wrapping_add(seed as u64);
+		self.next();
+	}
+
+	pub fn state(&self) -> u64 {
+		return self.state;
+	}
+
+	pub fn set_state(&mut self, state: u64) {
+		self.state = state;
+	}
+
+	pub fn next(&mut self) -> u32 {
+		let value = self.peek_next();
+		self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+		return value;
+	}
+
+	// the number next() returns, without moving on
+	pub fn peek_next(&self) -> u32 {
+		let old = self.state;
+		let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+		let rotation = (old >> 59) as u32;
+		return xorshifted.rotate_right(rotation);
+	}
+
+	// a number from 0 up to bound, without the bias of next() % bound
+	// a bound of 0 means any 32 bit number
+	pub fn below(&mut self, bound: storage) -> storage {
+		if bound == 0 {
+			return self.next();
+		}
+		// numbers under threshold would make the low results more likely
+		let threshold = bound.wrapping_neg() % bound;
+		loop {
+			let value = self.next();
+			if value >= threshold {
+				return value % bound;
+			}
+		}
+	}
+}
+
+impl Device for Random {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			RANDOM_NEXT => {
+				return self.peek_next();
+			},
+			RANDOM_SEED => {
+				return self.seed;
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+
+	fn read(&mut self, offset: storage) -> storage {
+		if offset == RANDOM_NEXT {
+			return self.next();
+		}
+		return self.peek(offset);
+	}
+
+	fn write(&mut self, offset: storage, value: storage) {
+		if offset == RANDOM_SEED {
+			self.set_seed(value);
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn numbers(random: &mut Random, count: usize) -> Vec<u32> {
+		return (0 .. count).map(|_| random.next()).collect();
+	}
+
+	#[test]
+	fn the_same_seed_gives_the_same_numbers() {
+		let mut first = Random::new();
+		let mut second = Random::new();
+		first.set_seed(1234);
+		second.write(RANDOM_SEED, 1234);
+		assert_eq!(numbers(&mut first, 8), numbers(&mut second, 8));
+		assert_eq!(second.peek(RANDOM_SEED), 1234);
+
+		second.set_seed(1235);
+		assert_ne!(numbers(&mut first, 8), numbers(&mut second, 8));
+	}
+
+	#[test]
+	fn a_restored_state_continues_the_sequence() {
+		let mut random = Random::new();
+		numbers(&mut random, 3);
+		let state = random.state();
+		let expected = numbers(&mut random, 8);
+
+		let mut restored = Random::new();
+		restored.set_state(state);
+		assert_eq!(numbers(&mut restored, 8), expected);
+	}
+
+	#[test]
+	fn reads_take_numbers_and_peeks_do_not() {
+		let mut random = Random::new();
+		let next = random.peek(RANDOM_NEXT);
+		assert_eq!(random.peek(RANDOM_NEXT), next);
+		assert_eq!(random.read(RANDOM_NEXT), next);
+		assert_ne!(random.peek(RANDOM_NEXT), next);
+	}
+
+	#[test]
+	fn numbers_stay_below_the_bound() {
+		let mut random = Random::new();
+		for bound in [1, 2, 3, 7, 1000, storage::MAX].iter() {
+			for _ in 0 .. 100 {
+				assert!(random.below(*bound) < *bound);
+			}
+		}
+	}
+}
@@ -0,0 +1,39 @@
+// The standard library of DSL routines, its sources are in rust/stdlib.
+//
+// std.dsla is the library itself, programs include std-header.dsla for its
+// imports and the call macro, and std-macros.dsla has the calling
+// convention. The sources are built into the crate, so the library is there
+// without any files, as an object to link with and as includes.
+
+use assembler::assemble_object;
+use object::Object;
+
+// the name the library is linked and preloaded as
+pub const OBJECT_NAME: &str = "std.dslo";
+pub const LIBRARY_NAME: &str = "std.dsla";
+pub const HEADER_NAME: &str = "std-header.dsla";
+
+pub const FILES: [(&str, &str); 3] = [
+	(LIBRARY_NAME, include_str!("../stdlib/std.dsla")),
+	(HEADER_NAME, include_str!("../stdlib/std-header.dsla")),
+	("std-macros.dsla", include_str!("../stdlib/std-macros.dsla")),
+];
+
+// one of the files by its name, without the directory, so an include of
+// "std-header.dsla" finds it from any file
+pub fn file(name: &str) -> Option<&'static str> {
+	let name = match name.rfind('/') {
+		Some(end) => &name[end + 1 ..],
+		None => name,
+	};
+	return FILES.iter().find(|&&(file, _)| file == name).map(|&(_, source)| source);
+}
+
+// the library assembled, to link after the program
+pub fn object() -> Object {
+	let mut read_file = |name: &str| file(name).map(str::to_string).ok_or(format!("{} is not in the standard library", name));
+	return match assemble_object(LIBRARY_NAME, FILES[0].1, &mut read_file) {
+		Ok(object) => object,
+		Err(errors) => panic!("the standard library doesn't assemble: {}", errors[0]),
+	};
+}
@@ -0,0 +1,779 @@
+// Syscalls that are handled inside the crate instead of being forwarded to
+// the host through js_syscall.
+//
+// The codes are shared with js/src/utils/SyscallsEnum.ts, keep them in sync.
+
+use super::{Processor, ProcessorStatus, storage, location};
+use drawing::{Screen, Sprite, signed, in_range, MAX_TEXT_LENGTH};
+use keyboard::Keyboard;
+use pointer::{Pointer, POINTER_EVENT_WORDS};
+use terminal::{Terminal, MAX_TERMINAL_OUTPUT, END_OF_INPUT};
+use audio::Audio;
+use random::Random;
+use filesystem::{MAX_FILENAME_LENGTH, MAX_FILE_SIZE};
+
+pub const CREATE_BUFFER: storage = 1;
+pub const SET_BUFFER_HEAD: storage = 2;
+pub const SET_BUFFER_LENGTH: storage = 3;
+pub const SET_BUFFER_TYPE: storage = 4;
+pub const DELETE_BUFFER: storage = 5;
+
+pub const OPEN_FILE: storage = 9;
+pub const READ_FILE: storage = 10;
+pub const WRITE_FILE: storage = 11;
+pub const SEEK_FILE: storage = 12;
+pub const CLOSE_FILE: storage = 13;
+pub const LIST_FILES: storage = 14;
+
+pub const SLEEP: storage = 20;
+pub const GET_TIME: storage = 21;
+pub const SET_TIMER: storage = 22;
+pub const TIMER_FIRED: storage = 23;
+
+pub const RANDOM: storage = 24;
+pub const SEED_RANDOM: storage = 25;
+
+pub const WRITE_CHAR: storage = 31;
+pub const WRITE_STRING: storage = 32;
+pub const READ_LINE: storage = 33;
+
+pub const ALLOCATE: storage = 40;
+pub const FREE: storage = 41;
+pub const PROTECT: storage = 42;
+
+pub const FILL_RECT: storage = 50;
+pub const DRAW_LINE: storage = 51;
+pub const DRAW_CIRCLE: storage = 52;
+pub const BLIT: storage = 53;
+pub const SCROLL: storage = 54;
+pub const DRAW_TEXT: storage = 55;
+pub const PRESENT: storage = 56;
+
+pub const POLL_KEY: storage = 60;
+pub const KEY_STATE: storage = 61;
+pub const POLL_POINTER: storage = 62;
+pub const POINTER_STATE: storage = 63;
+
+pub const QUEUE_SAMPLES: storage = 70;
+
+pub const SYSCALL_OK: storage = 0;
+pub const SYSCALL_ERROR: storage = 1;
+
+// returned by the file syscalls that return a count or a position
+pub const FILE_ERROR: storage = 0xFFFF_FFFF;
+
+// Reason the most recent native syscall failed, None if it succeeded.
+// The host can read it with r_GetLastSyscallError.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyscallError {
+	None = 0,
+	InvalidSize = 1,
+	OutOfMemory = 2,
+	DoubleFree = 3,
+	UnknownPointer = 4,
+	UnknownBuffer = 5,
+	BufferAlreadySet = 6,
+	InvalidRange = 7,
+	InvalidBufferType = 8,
+	NoScreen = 9,
+	OutputFull = 10,
+	FileNotFound = 11,
+	UnknownHandle = 12,
+	TooManyOpenFiles = 13,
+	InvalidFileMode = 14,
+	FileTooLarge = 15,
+	UnknownTimer = 16,
+	TooManyRanges = 17,
+}
+
+impl Processor {
+	// returns None if the syscall is not handled natively and should be
+	// given to the host instead, Some(None) if it faulted
+	pub fn native_syscall(&mut self, code: storage, param: storage) -> Option<Option<storage>> {
+		let error = self.last_error;
+		self.last_error = SyscallError::None;
+		let result = match code {
+			CREATE_BUFFER => Some(self.sys_create_buffer()),
+			SET_BUFFER_HEAD => self.sys_set_buffer_head(param),
+			SET_BUFFER_LENGTH => self.sys_set_buffer_length(param),
+			SET_BUFFER_TYPE => self.sys_set_buffer_type(param),
+			DELETE_BUFFER => Some(self.sys_delete_buffer(param)),
+			OPEN_FILE => self.sys_open_file(param),
+			READ_FILE => self.sys_read_file(param),
+			WRITE_FILE => self.sys_write_file(param),
+			SEEK_FILE => self.sys_seek_file(param),
+			CLOSE_FILE => Some(self.sys_close_file(param)),
+			LIST_FILES => self.sys_list_files(param),
+			SLEEP => Some(self.sys_sleep(param)),
+			GET_TIME => Some(self.sys_get_time()),
+			SET_TIMER => self.sys_set_timer(param),
+			TIMER_FIRED => Some(self.sys_timer_fired(param)),
+			RANDOM => Some(self.sys_random(param)),
+			SEED_RANDOM => Some(self.sys_seed_random(param)),
+			WRITE_CHAR => Some(self.sys_write_char(param)),
+			WRITE_STRING => self.sys_write_string(param),
+			READ_LINE => self.sys_read_line(param),
+			ALLOCATE => Some(self.sys_allocate(param)),
+			FREE => Some(self.sys_free(param)),
+			PROTECT => self.sys_protect(param),
+			FILL_RECT => self.sys_fill_rect(param),
+			DRAW_LINE => self.sys_draw_line(param),
+			DRAW_CIRCLE => self.sys_draw_circle(param),
+			BLIT => self.sys_blit(param),
+			SCROLL => self.sys_scroll(param),
+			DRAW_TEXT => self.sys_draw_text(param),
+			PRESENT => Some(self.sys_present()),
+			POLL_KEY => Some(self.sys_poll_key()),
+			KEY_STATE => Some(self.sys_key_state(param)),
+			POLL_POINTER => self.sys_poll_pointer(param),
+			POINTER_STATE => self.sys_pointer_state(param),
+			QUEUE_SAMPLES => self.sys_queue_samples(param),
+			_ => {
+				self.last_error = error;
+				return None;
+			},
+		};
+		return Some(result);
+	}
+
+	// multi-word arguments are passed as a pointer to consecutive words
+	// syscalls are checked like the instructions of the program but read
+	// without the side effects of device registers, None if the access
+	// faulted, which stops the syscall
+	fn syscall_arg(&mut self, pointer: location, index: storage) -> Option<storage> {
+		return self.peek_memory(pointer.wrapping_add(index));
+	}
+
+	// records the error and returns the value that should go on the bus
+	fn syscall_failed(&mut self, error: SyscallError, result: storage) -> storage {
+		self.last_error = error;
+		return result;
+	}
+
+	// turns a Result into the OK / ERROR value for the bus
+	fn syscall_status(&mut self, result: Result<(), SyscallError>) -> storage {
+		match result {
+			Ok(()) => {
+				return SYSCALL_OK;
+			},
+			Err(error) => {
+				return self.syscall_failed(error, SYSCALL_ERROR);
+			},
+		}
+	}
+
+	// changes to output buffers change the picture
+	fn buffer_status(&mut self, result: Result<(), SyscallError>) -> storage {
+		self.framebuffer.mark_dirty();
+		return self.syscall_status(result);
+	}
+
+	// returns the id of the new buffer
+	fn sys_create_buffer(&mut self) -> storage {
+		return self.buffers.create();
+	}
+
+	// bus: pointer to [buffer id, head address]
+	fn sys_set_buffer_head(&mut self, pointer: location) -> Option<storage> {
+		let id = self.syscall_arg(pointer, 0)?;
+		let head = self.syscall_arg(pointer, 1)?;
+		let mapped = self.mapped_size();
+		let result = self.buffers.set_head(id, head, mapped);
+		return Some(self.buffer_status(result));
+	}
+
+	// bus: pointer to [buffer id, length]
+	fn sys_set_buffer_length(&mut self, pointer: location) -> Option<storage> {
+		let id = self.syscall_arg(pointer, 0)?;
+		let length = self.syscall_arg(pointer, 1)?;
+		let mapped = self.mapped_size();
+		let result = self.buffers.set_length(id, length, mapped);
+		return Some(self.buffer_status(result));
+	}
+
+	// bus: pointer to [buffer id, buffer type]
+	fn sys_set_buffer_type(&mut self, pointer: location) -> Option<storage> {
+		let id = self.syscall_arg(pointer, 0)?;
+		let kind = self.syscall_arg(pointer, 1)?;
+		let result = self.buffers.set_type(id, kind);
+		return Some(self.buffer_status(result));
+	}
+
+	// bus: buffer id
+	fn sys_delete_buffer(&mut self, id: storage) -> storage {
+		let result = self.buffers.delete(id);
+		return self.buffer_status(result);
+	}
+
+	// zero terminated, one character per word
+	// Ok(None) if there is no terminator within max_length characters
+	fn syscall_string(&mut self, address: location, max_length: usize) -> Option<Option<Vec<storage>>> {
+		let mut characters = Vec::new();
+		loop {
+			let character = self.peek_memory(address.wrapping_add(characters.len() as storage))?;
+			if character == 0 {
+				return Some(Some(characters));
+			}
+			if characters.len() == max_length {
+				return Some(None);
+			}
+			characters.push(character);
+		}
+	}
+
+	// count words, one byte each, starting at the address
+	fn copy_bytes_in(&mut self, address: location, count: storage) -> Option<Vec<u8>> {
+		return (0 .. count).map(|i| self.peek_memory(address.wrapping_add(i)).map(|word| word as u8)).collect();
+	}
+
+	fn copy_bytes_out(&mut self, address: location, bytes: &[u8]) -> Option<()> {
+		for (i, &byte) in bytes.iter().enumerate() {
+			self.write_memory(address.wrapping_add(i as storage), byte as storage)?;
+		}
+		return Some(());
+	}
+
+	// turns a Result into the value for the bus, FILE_ERROR if it failed
+	fn file_status(&mut self, result: Result<storage, SyscallError>) -> storage {
+		match result {
+			Ok(value) => {
+				return value;
+			},
+			Err(error) => {
+				return self.syscall_failed(error, FILE_ERROR);
+			},
+		}
+	}
+
+	// bus: pointer to [filename address, flags]
+	// flags are FILE_READ | FILE_WRITE with FILE_CREATE, FILE_TRUNCATE or FILE_APPEND
+	// returns the handle, or 0 if the file couldn't be opened
+	fn sys_open_file(&mut self, pointer: location) -> Option<storage> {
+		let address = self.syscall_arg(pointer, 0)?;
+		let flags = self.syscall_arg(pointer, 1)?;
+		let name = match self.syscall_string(address, MAX_FILENAME_LENGTH)? {
+			Some(name) => name.iter().map(|&c| c as u8 as char).collect::<String>(),
+			None => return Some(self.syscall_failed(SyscallError::FileNotFound, 0)),
+		};
+		match self.files.open(&name, flags) {
+			Ok(handle) => {
+				return Some(handle);
+			},
+			Err(error) => {
+				return Some(self.syscall_failed(error, 0));
+			},
+		}
+	}
+
+	// bus: pointer to [handle, address, count]
+	// returns the number of bytes read, 0 at the end of the file
+	fn sys_read_file(&mut self, pointer: location) -> Option<storage> {
+		let handle = self.syscall_arg(pointer, 0)?;
+		let address = self.syscall_arg(pointer, 1)?;
+		let count = self.syscall_arg(pointer, 2)?;
+		let bytes = match self.files.peek(handle, (count as usize).min(MAX_FILE_SIZE)) {
+			Ok(bytes) => bytes,
+			Err(error) => return Some(self.syscall_failed(error, FILE_ERROR)),
+		};
+		// the position only moves once the bytes are in memory
+		self.copy_bytes_out(address, &bytes)?;
+		if let Err(error) = self.files.advance(handle, bytes.len()) {
+			return Some(self.syscall_failed(error, FILE_ERROR));
+		}
+		return Some(bytes.len() as storage);
+	}
+
+	// bus: pointer to [handle, address, count]
+	// returns the number of bytes written
+	fn sys_write_file(&mut self, pointer: location) -> Option<storage> {
+		let handle = self.syscall_arg(pointer, 0)?;
+		let address = self.syscall_arg(pointer, 1)?;
+		let count = self.syscall_arg(pointer, 2)?;
+		if count as usize > MAX_FILE_SIZE {
+			return Some(self.syscall_failed(SyscallError::FileTooLarge, FILE_ERROR));
+		}
+		let bytes = self.copy_bytes_in(address, count)?;
+		let result = self.files.write(handle, &bytes).map(|()| count);
+		return Some(self.file_status(result));
+	}
+
+	// bus: pointer to [handle, signed offset, SEEK_START / SEEK_CURRENT / SEEK_END]
+	// returns the new position
+	fn sys_seek_file(&mut self, pointer: location) -> Option<storage> {
+		let handle = self.syscall_arg(pointer, 0)?;
+		let offset = signed(self.syscall_arg(pointer, 1)?);
+		let whence = self.syscall_arg(pointer, 2)?;
+		let result = self.files.seek(handle, offset, whence).map(|position| position as storage);
+		return Some(self.file_status(result));
+	}
+
+	// bus: handle
+	fn sys_close_file(&mut self, handle: storage) -> storage {
+		let result = self.files.close(handle);
+		return self.syscall_status(result);
+	}
+
+	// bus: pointer to [index, address, length]
+	// writes the name of the file at the index, in name order, zero terminated
+	// returns the length of the name, FILE_ERROR past the last file
+	fn sys_list_files(&mut self, pointer: location) -> Option<storage> {
+		let index = self.syscall_arg(pointer, 0)?;
+		let address = self.syscall_arg(pointer, 1)?;
+		let length = self.syscall_arg(pointer, 2)?;
+		if length == 0 {
+			return Some(self.syscall_failed(SyscallError::InvalidSize, FILE_ERROR));
+		}
+		let name = match self.files.name_at(index as usize) {
+			Some(name) => name.as_bytes().to_vec(),
+			None => return Some(self.syscall_failed(SyscallError::FileNotFound, FILE_ERROR)),
+		};
+		let count = name.len().min(length as usize - 1);
+		self.copy_bytes_out(address, &name[.. count])?;
+		self.write_memory(address.wrapping_add(count as storage), 0)?;
+		return Some(count as storage);
+	}
+
+	// bus: milliseconds
+	// the processor sleeps until the clock reached the wake up time, with an
+	// instruction driven clock the time passes at once instead
+	fn sys_sleep(&mut self, milliseconds: storage) -> storage {
+		if milliseconds == 0 {
+			return SYSCALL_OK;
+		}
+		if self.clock.is_instruction_driven() {
+			self.advance_clock(milliseconds as u64);
+			return SYSCALL_OK;
+		}
+		let wake_at = self.clock.ticks().saturating_add(milliseconds as u64);
+		self.clock.sleep_until(wake_at);
+		self.status = ProcessorStatus::Sleeping;
+		return SYSCALL_OK;
+	}
+
+	// returns the low 32 bits of the clock, in milliseconds
+	fn sys_get_time(&mut self) -> storage {
+		return self.clock.ticks() as storage;
+	}
+
+	// bus: pointer to [timer, period in milliseconds], a period of 0 turns it off
+	fn sys_set_timer(&mut self, pointer: location) -> Option<storage> {
+		let timer = self.syscall_arg(pointer, 0)?;
+		let period = self.syscall_arg(pointer, 1)?;
+		if !self.clock.set_timer(timer as usize, period as u64) {
+			return Some(self.syscall_failed(SyscallError::UnknownTimer, SYSCALL_ERROR));
+		}
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: timer
+	// returns how often the period passed since the last call
+	fn sys_timer_fired(&mut self, timer: storage) -> storage {
+		match self.clock.take_fired(timer as usize) {
+			Some(fired) => {
+				return fired.min(storage::MAX as u64) as storage;
+			},
+			None => {
+				return self.syscall_failed(SyscallError::UnknownTimer, 0);
+			},
+		}
+	}
+
+	// bus: bound
+	// returns a number from 0 up to the bound, any number for a bound of 0
+	fn sys_random(&mut self, bound: storage) -> storage {
+		return match self.mmio.device::<Random>() {
+			Some(random) => random.below(bound),
+			None => 0,
+		};
+	}
+
+	// bus: seed
+	fn sys_seed_random(&mut self, seed: storage) -> storage {
+		if let Some(random) = self.mmio.device::<Random>() {
+			random.set_seed(seed);
+		}
+		return SYSCALL_OK;
+	}
+
+	fn terminal_write(&mut self, bytes: &[u8]) -> Result<(), SyscallError> {
+		let terminal = match self.mmio.device::<Terminal>() {
+			Some(terminal) => terminal,
+			None => return Err(SyscallError::OutputFull),
+		};
+		for &byte in bytes.iter() {
+			if !terminal.write_byte(byte) {
+				return Err(SyscallError::OutputFull);
+			}
+		}
+		return Ok(());
+	}
+
+	// bus: character, only the low byte is written
+	fn sys_write_char(&mut self, character: storage) -> storage {
+		let result = self.terminal_write(&[character as u8]);
+		return self.syscall_status(result);
+	}
+
+	// bus: address of a zero terminated string, one byte per word
+	fn sys_write_string(&mut self, address: location) -> Option<storage> {
+		let bytes: Vec<u8> = match self.syscall_string(address, MAX_TERMINAL_OUTPUT)? {
+			Some(string) => string.iter().map(|&c| c as u8).collect(),
+			None => return Some(self.syscall_failed(SyscallError::OutputFull, SYSCALL_ERROR)),
+		};
+		let result = self.terminal_write(&bytes);
+		return Some(self.syscall_status(result));
+	}
+
+	// bus: pointer to [buffer address, buffer length]
+	// the line is written without its newline and zero terminated, what
+	// doesn't fit is dropped
+	// returns the length of the line, or END_OF_INPUT
+	// with no line to read the processor waits for input and runs the
+	// syscall again once there is one
+	fn sys_read_line(&mut self, pointer: location) -> Option<storage> {
+		let address = self.syscall_arg(pointer, 0)?;
+		let length = self.syscall_arg(pointer, 1)?;
+		if length == 0 {
+			return Some(self.syscall_failed(SyscallError::InvalidSize, 0));
+		}
+		let line = match self.mmio.device::<Terminal>() {
+			Some(terminal) if terminal.has_ended() => return Some(END_OF_INPUT),
+			Some(terminal) => terminal.read_line(),
+			None => return Some(END_OF_INPUT),
+		};
+		let line = match line {
+			Some(line) => line,
+			None => {
+				self.status = ProcessorStatus::WaitingForInput;
+				self.dontMoveParamPointer();
+				// the bus has to hold the pointer again when the syscall is retried
+				return Some(pointer);
+			},
+		};
+		let count = line.len().min(length as usize - 1);
+		self.copy_bytes_out(address, &line[.. count])?;
+		self.write_memory(address.wrapping_add(count as storage), 0)?;
+		return Some(count as storage);
+	}
+
+	// bus: number of words to allocate
+	// returns the address of the new allocation, or 0 if it failed
+	fn sys_allocate(&mut self, size: storage) -> storage {
+		match self.heap.allocate(size, &mut self.regions) {
+			Ok(address) => {
+				// reused space may still hold data from a previous allocation
+				for offset in 0 .. size {
+					self._set_memory_loc(address + offset, 0);
+				}
+				return address;
+			},
+			Err(error) => {
+				return self.syscall_failed(error, 0);
+			},
+		}
+	}
+
+	// bus: address returned by Allocate
+	// buffers over the freed memory are deleted, the host would otherwise
+	// keep using memory the next allocation hands out
+	fn sys_free(&mut self, address: location) -> storage {
+		match self.heap.free(address) {
+			Ok(size) => {
+				if self.buffers.release(address, size) {
+					self.framebuffer.mark_dirty();
+				}
+				return SYSCALL_OK;
+			},
+			Err(error) => {
+				return self.syscall_failed(error, SYSCALL_ERROR);
+			},
+		}
+	}
+
+	// bus: pointer to [start, length, permissions]
+	// permissions are PERM_READ | PERM_WRITE | PERM_EXECUTE, 0 makes a guard region
+	// the permissions the image declares can be taken away but never given back
+	fn sys_protect(&mut self, pointer: location) -> Option<storage> {
+		let start = self.syscall_arg(pointer, 0)?;
+		let length = self.syscall_arg(pointer, 1)?;
+		let permissions = self.syscall_arg(pointer, 2)?;
+		if length == 0 {
+			return Some(self.syscall_failed(SyscallError::InvalidSize, SYSCALL_ERROR));
+		}
+		if !self.protection.set(start, length, permissions) {
+			return Some(self.syscall_failed(SyscallError::TooManyRanges, SYSCALL_ERROR));
+		}
+		return Some(SYSCALL_OK);
+	}
+
+	// reads count signed drawing arguments and finds the screen to draw them
+	// on, Err if the syscall should fail instead
+	fn drawing_args(&mut self, pointer: location, count: storage) -> Option<Result<(Vec<i32>, Screen), SyscallError>> {
+		let mut args = Vec::new();
+		for i in 0 .. count {
+			args.push(signed(self.syscall_arg(pointer, i)?));
+		}
+		if !in_range(&args) {
+			return Some(Err(SyscallError::InvalidRange));
+		}
+		return Some(self.screen().map(|screen| (args, screen)));
+	}
+
+	// bus: pointer to [x, y, width, height, color]
+	fn sys_fill_rect(&mut self, pointer: location) -> Option<storage> {
+		let color = self.syscall_arg(pointer, 4)?;
+		let (a, screen) = match self.drawing_args(pointer, 4)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		self.fill_rect(&screen, a[0], a[1], a[2], a[3], color)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [x0, y0, x1, y1, color]
+	fn sys_draw_line(&mut self, pointer: location) -> Option<storage> {
+		let color = self.syscall_arg(pointer, 4)?;
+		let (a, screen) = match self.drawing_args(pointer, 4)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		self.draw_line(&screen, a[0], a[1], a[2], a[3], color)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [center x, center y, radius, color, filled]
+	fn sys_draw_circle(&mut self, pointer: location) -> Option<storage> {
+		let color = self.syscall_arg(pointer, 3)?;
+		let filled = self.syscall_arg(pointer, 4)? != 0;
+		let (a, screen) = match self.drawing_args(pointer, 3)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		self.draw_circle(&screen, a[0], a[1], a[2], color, filled)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [x, y, sprite address, width, height, transparent color]
+	fn sys_blit(&mut self, pointer: location) -> Option<storage> {
+		let address = self.syscall_arg(pointer, 2)?;
+		let transparent = self.syscall_arg(pointer, 5)?;
+		let width = signed(self.syscall_arg(pointer, 3)?);
+		let height = signed(self.syscall_arg(pointer, 4)?);
+		if !in_range(&[width, height]) {
+			return Some(self.syscall_failed(SyscallError::InvalidRange, SYSCALL_ERROR));
+		}
+		let (a, screen) = match self.drawing_args(pointer, 2)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		let sprite = Sprite {
+			address,
+			width,
+			height,
+			transparent,
+		};
+		self.blit(&screen, a[0], a[1], &sprite)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [dx, dy, fill color]
+	fn sys_scroll(&mut self, pointer: location) -> Option<storage> {
+		let fill = self.syscall_arg(pointer, 2)?;
+		let (a, screen) = match self.drawing_args(pointer, 2)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		self.scroll(&screen, a[0], a[1], fill)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [x, y, color, string address]
+	// the string is zero terminated, one character per word, '\n' starts a new line
+	fn sys_draw_text(&mut self, pointer: location) -> Option<storage> {
+		let color = self.syscall_arg(pointer, 2)?;
+		let address = self.syscall_arg(pointer, 3)?;
+		let (a, screen) = match self.drawing_args(pointer, 2)? {
+			Ok(args) => args,
+			Err(error) => return Some(self.syscall_failed(error, SYSCALL_ERROR)),
+		};
+		let text = match self.syscall_string(address, MAX_TEXT_LENGTH)? {
+			Some(text) => text,
+			None => return Some(self.syscall_failed(SyscallError::InvalidSize, SYSCALL_ERROR)),
+		};
+		self.draw_text(&screen, a[0], a[1], &text, color)?;
+		return Some(SYSCALL_OK);
+	}
+
+	// shows the frame and waits for the host to start the next one
+	fn sys_present(&mut self) -> storage {
+		self.composite_tiles();
+		self.present();
+		self.status = ProcessorStatus::WaitingForFrame;
+		return SYSCALL_OK;
+	}
+
+	// returns the next key event, key code | KEY_EVENT_DOWN for a press,
+	// or 0 if there is none
+	fn sys_poll_key(&mut self) -> storage {
+		return match self.mmio.device::<Keyboard>() {
+			Some(keyboard) => keyboard.next_event(),
+			None => 0,
+		};
+	}
+
+	// bus: key code
+	// returns 1 if the key is held, 0 if not
+	fn sys_key_state(&mut self, code: storage) -> storage {
+		return match self.mmio.device::<Keyboard>() {
+			Some(keyboard) => keyboard.is_held(code) as storage,
+			None => 0,
+		};
+	}
+
+	// bus: pointer to 4 words the next event is written to, [kind, x, y, data]
+	// returns 1 if there was an event, 0 if not
+	fn sys_poll_pointer(&mut self, pointer: location) -> Option<storage> {
+		let event = match self.mmio.device::<Pointer>().and_then(|p| p.next_event()) {
+			Some(event) => event,
+			None => return Some(0),
+		};
+		let words: [storage; POINTER_EVENT_WORDS as usize] = [event.kind, event.x as storage, event.y as storage, event.data];
+		for (i, &word) in words.iter().enumerate() {
+			self.write_memory(pointer.wrapping_add(i as storage), word)?;
+		}
+		return Some(1);
+	}
+
+	// bus: pointer to 3 words the state is written to, [x, y, buttons]
+	fn sys_pointer_state(&mut self, pointer: location) -> Option<storage> {
+		let (x, y, buttons) = match self.mmio.device::<Pointer>() {
+			Some(device) => {
+				let (x, y) = device.position();
+				(x, y, device.buttons())
+			},
+			None => (0, 0, 0),
+		};
+		let words = [x as storage, y as storage, buttons];
+		for (i, &word) in words.iter().enumerate() {
+			self.write_memory(pointer.wrapping_add(i as storage), word)?;
+		}
+		return Some(SYSCALL_OK);
+	}
+
+	// bus: pointer to [address, count] of signed 16 bit samples, one per word
+	// returns how many samples fit in the PCM ring buffer
+	fn sys_queue_samples(&mut self, pointer: location) -> Option<storage> {
+		let address = self.syscall_arg(pointer, 0)?;
+		let count = self.syscall_arg(pointer, 1)?;
+		let free = match self.mmio.device::<Audio>() {
+			Some(audio) => audio.pcm_free() as storage,
+			None => 0,
+		};
+		let samples: Vec<i16> = (0 .. count.min(free))
+			.map(|i| self.peek_memory(address.wrapping_add(i)).map(|word| word as i16))
+			.collect::<Option<Vec<i16>>>()?;
+		if let Some(audio) = self.mmio.device::<Audio>() {
+			for &sample in samples.iter() {
+				audio.queue_sample(sample);
+			}
+		}
+		return Some(samples.len() as storage);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use clock::CLOCK_INSTRUCTIONS;
+	use opcodes::HALT;
+	use testing::Machine;
+
+	#[test]
+	fn last_error_is_reset_by_a_syscall_that_succeeds() {
+		let mut machine = Machine::new("
+.data
+var pointer number 0
+.text
+LoadImmmediateToBus 4
+Syscall 40
+SaveValueInBusToLocation pointer
+Syscall 41
+LoadValueAtAddressIntoBus pointer
+Syscall 41
+Pause
+LoadImmmediateToBus 4
+Syscall 40
+");
+		machine.run();
+		assert_eq!(machine.processor.last_error, SyscallError::DoubleFree);
+		machine.run();
+		assert_eq!(machine.processor.last_error, SyscallError::None);
+		// the freed space is handed out again
+		let pointer = machine.word("pointer", 0);
+		assert_eq!(machine.processor.bus, pointer);
+	}
+
+	#[test]
+	fn timers_fire_while_sleeping() {
+		let mut machine = Machine::new("
+.data
+var vectors array ticked
+var timer array 0 4
+var ticks number 0
+.text
+SetInterruptVectorTable vectors
+LoadImmmediateToBus timer
+Syscall 22
+EnableInterrupts
+LoadImmmediateToBus 10
+Syscall 20
+Pause
+@ticked
+LoadImmmediateToBus 1
+SaveValueInBusToLocation ticks
+ReturnFromInterrupt
+");
+		machine.processor.clock.set_mode(CLOCK_INSTRUCTIONS, 1000);
+		machine.run();
+		assert_eq!(machine.processor.clock.ticks(), 10);
+		assert_eq!(machine.word("ticks", 0), 1);
+		assert_eq!(machine.processor.clock.take_fired(0), Some(2));
+	}
+
+	fn read_file_machine(destination: &str) -> Machine {
+		let mut machine = Machine::new(&format!("
+.protect .text rx
+.data
+var name string \"data\"
+var open array name 1
+var read array 1 {} 2
+var buffer array 0 0
+.text
+LoadImmmediateToBus open
+Syscall 9
+LoadImmmediateToBus read
+Syscall 10
+@code
+Halt
+", destination));
+		machine.processor.files.insert("data", b"abc".to_vec()).unwrap();
+		machine.run();
+		return machine;
+	}
+
+	#[test]
+	fn reads_move_the_position_once_stored() {
+		let mut machine = read_file_machine("buffer");
+		assert_eq!(machine.processor.bus, 2);
+		assert_eq!((machine.word("buffer", 0), machine.word("buffer", 1)), ('a' as storage, 'b' as storage));
+		assert_eq!(machine.processor.files.peek(1, 3), Ok(b"c".to_vec()));
+	}
+
+	#[test]
+	fn reads_that_fault_leave_the_position() {
+		let mut machine = read_file_machine("code");
+		assert!(matches!(machine.processor.status, ProcessorStatus::Faulted));
+		assert_eq!(machine.processor.peek(machine.address("code")), HALT);
+		assert_eq!(machine.processor.files.peek(1, 3), Ok(b"abc".to_vec()));
+	}
+}
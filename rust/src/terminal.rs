@@ -0,0 +1,244 @@
+// Terminal device, a stdin / stdout style stream of bytes.
+//
+// Output is collected until the host reads it with
+// r_GetTerminalOutputPointer and r_GetTerminalOutputLength and empties it
+// with r_ClearTerminalOutput.
+// Input is pushed by the host a byte at a time with r_PushTerminalInput.
+// The ReadLine syscall waits in the WaitingForInput status until a whole
+// line has arrived, the input buffer is full or the host closed the input.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use super::{storage, location};
+use mmio::{Device, MMIO_BASE};
+
+pub const TERMINAL_BASE: location = MMIO_BASE + 0x4000;
+
+// registers
+// writing puts the low byte of the value on the output
+pub const TERMINAL_OUT: storage = 0;
+// read only, number of input bytes that haven't been read
+pub const TERMINAL_AVAILABLE: storage = 1;
+// reading takes the next input byte, 0 if there is none
+pub const TERMINAL_IN: storage = 2;
+// read only, 1 once the host closed the input and it was all read
+pub const TERMINAL_ENDED: storage = 3;
+
+pub const TERMINAL_SIZE: storage = 4;
+
+// output the host hasn't taken yet is capped, so a program printing in a
+// loop can't use up all of the memory
+pub const MAX_TERMINAL_OUTPUT: usize = 1 << 16;
+pub const MAX_TERMINAL_INPUT: usize = 1 << 16;
+
+// ReadLine returns this at the end of the input
+pub const END_OF_INPUT: storage = 0xFFFF_FFFF;
+
+const NEWLINE: u8 = b'\n';
+
+pub struct Terminal {
+	output: Vec<u8>,
+	input: VecDeque<u8>,
+	closed: bool,
+}
+
+impl Terminal {
+	pub fn new() -> Terminal {
+		let output = Vec::new();
+		let input = VecDeque::new();
+		let closed = false;
+		Terminal {
+			output,
+			input,
+			closed,
+		}
+	}
+
+	// returns false if the output is full
+	pub fn write_byte(&mut self, byte: u8) -> bool {
+		if self.output.len() == MAX_TERMINAL_OUTPUT {
+			return false;
+		}
+		self.output.push(byte);
+		return true;
+	}
+
+	pub fn output(&self) -> &[u8] {
+		return &self.output;
+	}
+
+	pub fn clear_output(&mut self) {
+		self.output.clear();
+	}
+
+	// returns false if the input was closed or is full
+	pub fn push_input(&mut self, byte: u8) -> bool {
+		if self.closed || self.input.len() == MAX_TERMINAL_INPUT {
+			return false;
+		}
+		self.input.push_back(byte);
+		return true;
+	}
+
+	pub fn close_input(&mut self) {
+		self.closed = true;
+	}
+
+	// a full buffer counts as a line, otherwise nothing more could be pushed
+	// and a program waiting for the newline would wait forever
+	pub fn has_line(&self) -> bool {
+		if self.input.len() == MAX_TERMINAL_INPUT {
+			return true;
+		}
+		return self.input.contains(&NEWLINE) || (self.closed && !self.input.is_empty());
+	}
+
+	pub fn has_ended(&self) -> bool {
+		return self.closed && self.input.is_empty();
+	}
+
+	// the next line without its newline, None if it hasn't fully arrived
+	// a full buffer without a newline is returned as a partial line
+	pub fn read_line(&mut self) -> Option<Vec<u8>> {
+		if !self.has_line() {
+			return None;
+		}
+		let mut line = Vec::new();
+		while let Some(byte) = self.input.pop_front() {
+			if byte == NEWLINE {
+				break;
+			}
+			line.push(byte);
+		}
+		return Some(line);
+	}
+}
+
+impl Device for Terminal {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			TERMINAL_AVAILABLE => {
+				return self.input.len() as storage;
+			},
+			TERMINAL_IN => {
+				return self.input.front().copied().unwrap_or(0) as storage;
+			},
+			TERMINAL_ENDED => {
+				return self.has_ended() as storage;
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+
+	fn read(&mut self, offset: storage) -> storage {
+		if offset == TERMINAL_IN {
+			return self.input.pop_front().unwrap_or(0) as storage;
+		}
+		return self.peek(offset);
+	}
+
+	fn write(&mut self, offset: storage, value: storage) {
+		if offset == TERMINAL_OUT {
+			self.write_byte(value as u8);
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ProcessorStatus;
+	use testing::Machine;
+
+	fn push(terminal: &mut Terminal, text: &str) {
+		for &byte in text.as_bytes() {
+			assert!(terminal.push_input(byte));
+		}
+	}
+
+	#[test]
+	fn lines_wait_for_their_newline() {
+		let mut terminal = Terminal::new();
+		push(&mut terminal, "ab");
+		assert_eq!(terminal.read_line(), None);
+		push(&mut terminal, "c\nd\n");
+		assert_eq!(terminal.read_line(), Some(b"abc".to_vec()));
+		assert_eq!(terminal.read_line(), Some(b"d".to_vec()));
+		assert_eq!(terminal.read_line(), None);
+		assert!(!terminal.has_ended());
+	}
+
+	#[test]
+	fn a_full_buffer_is_a_partial_line() {
+		let mut terminal = Terminal::new();
+		for _ in 0 .. MAX_TERMINAL_INPUT {
+			assert!(terminal.push_input(b'a'));
+		}
+		assert!(!terminal.push_input(b'\n'));
+		assert!(terminal.has_line());
+		assert_eq!(terminal.read_line().map(|line| line.len()), Some(MAX_TERMINAL_INPUT));
+		// there is room again for the rest of the line
+		push(&mut terminal, "b\n");
+		assert_eq!(terminal.read_line(), Some(b"b".to_vec()));
+	}
+
+	#[test]
+	fn closed_input_ends_after_the_last_line() {
+		let mut terminal = Terminal::new();
+		push(&mut terminal, "last");
+		terminal.close_input();
+		assert!(!terminal.push_input(b'x'));
+		assert!(!terminal.has_ended());
+		assert_eq!(terminal.read_line(), Some(b"last".to_vec()));
+		assert!(terminal.has_ended());
+		assert_eq!(terminal.peek(TERMINAL_ENDED), 1);
+	}
+
+	fn read_line_machine() -> Machine {
+		return Machine::new("
+.data
+var text array 0 0 0 0
+var args array text 4
+.text
+LoadImmmediateToBus args
+Syscall 33
+Pause
+LoadImmmediateToBus args
+Syscall 33
+");
+	}
+
+	#[test]
+	fn read_line_waits_and_is_retried() {
+		let mut machine = read_line_machine();
+		machine.run();
+		assert!(matches!(machine.processor.status, ProcessorStatus::WaitingForInput));
+
+		// half a line doesn't wake the program
+		push(machine.processor.mmio.device::<Terminal>().unwrap(), "hello");
+		machine.processor.wake_on_input();
+		assert!(matches!(machine.processor.status, ProcessorStatus::WaitingForInput));
+
+		push(machine.processor.mmio.device::<Terminal>().unwrap(), "\n");
+		machine.processor.wake_on_input();
+		assert!(matches!(machine.processor.status, ProcessorStatus::Paused));
+		machine.run();
+		// cut to the buffer and zero terminated
+		assert_eq!(machine.processor.bus, 3);
+		assert_eq!((0 .. 4).map(|i| machine.word("text", i)).collect::<Vec<_>>(), vec![104, 101, 108, 0]);
+	}
+
+	#[test]
+	fn read_line_returns_end_of_input() {
+		let mut machine = read_line_machine();
+		machine.processor.mmio.device::<Terminal>().unwrap().close_input();
+		machine.run();
+		assert_eq!(machine.processor.bus, END_OF_INPUT);
+	}
+}
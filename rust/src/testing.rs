@@ -0,0 +1,60 @@
+// Small programs for the tests of the processor and its devices, assembled
+// and loaded the way dsl-run loads them.
+
+use super::{Processor, ProcessorStatus, storage, location};
+use assembler::{assemble, Assembly};
+
+// stops programs that never halt
+const MAX_STEPS: u64 = 100_000;
+
+pub struct Machine {
+	pub processor: Processor,
+	pub assembly: Assembly,
+}
+
+impl Machine {
+	pub fn new(source: &str) -> Machine {
+		let assembly = match assemble(source) {
+			Ok(assembly) => assembly,
+			Err(errors) => panic!("{}", errors[0]),
+		};
+		let mut processor = Processor::new();
+		processor.load_image(&assembly.image);
+		processor.protect_image(&assembly.protections);
+		processor.set_step_budget(MAX_STEPS);
+		Machine {
+			processor,
+			assembly,
+		}
+	}
+
+	// steps until the program stops, pauses or waits for the host
+	pub fn run(&mut self) {
+		self.processor.status = ProcessorStatus::Running;
+		while let ProcessorStatus::Running = self.processor.status {
+			self.processor.step();
+		}
+	}
+
+	pub fn address(&self, name: &str) -> location {
+		return self.assembly.symbol(name).unwrap_or_else(|| panic!("no symbol {}", name)).address;
+	}
+
+	// puts the values at the address and makes a buffer of the type over them
+	pub fn buffer(&mut self, address: location, values: &[storage], kind: storage) {
+		let processor = &mut self.processor;
+		for (i, &value) in values.iter().enumerate() {
+			processor._set_memory_loc(address + i as location, value);
+		}
+		let id = processor.buffers.create();
+		let mapped = processor.mapped_size();
+		processor.buffers.set_head(id, address, mapped).unwrap();
+		processor.buffers.set_length(id, values.len() as storage, mapped).unwrap();
+		processor.buffers.set_type(id, kind).unwrap();
+	}
+
+	pub fn word(&mut self, name: &str, offset: storage) -> storage {
+		let address = self.address(name);
+		return self.processor.peek(address + offset);
+	}
+}
@@ -0,0 +1,214 @@
+// Character cell "text mode" device.
+//
+// A grid of cells mapped at TEXT_MODE_BASE. Each cell is one word:
+//   bits 0 - 7   character, 0 leaves the cell empty
+//   bits 8 - 15  foreground palette index
+//   bits 16 - 23 background palette index
+//   bit 24       draw the background, otherwise it is transparent
+// The grid is drawn over the OUTPUT_SCREEN buffer when the framebuffer is
+// rendered, it never changes VM memory. The visible size registers depend
+// on the screen size, which the device can't see, so the processor answers
+// them, see text_mode_register.
+
+use std::any::Any;
+use super::{Processor, storage, location};
+use mmio::{Device, MMIO_BASE};
+use font::{CELL_WIDTH, CELL_HEIGHT};
+
+pub const TEXT_MODE_BASE: location = MMIO_BASE;
+
+pub const TEXT_COLUMNS: usize = 64;
+pub const TEXT_ROWS: usize = 32;
+
+// registers, the cells start at TEXT_CELLS
+pub const TEXT_ENABLED: storage = 0;
+// read only, how many cells fit on the current screen
+pub const TEXT_VISIBLE_COLUMNS: storage = 1;
+pub const TEXT_VISIBLE_ROWS: storage = 2;
+pub const TEXT_CELLS: storage = 16;
+
+pub const TEXT_MODE_SIZE: storage = TEXT_CELLS + (TEXT_COLUMNS * TEXT_ROWS) as storage;
+
+pub const CELL_OPAQUE: storage = 1 << 24;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cell {
+	pub character: storage,
+	pub foreground: storage,
+	pub background: Option<storage>,
+}
+
+impl Cell {
+	fn decode(value: storage) -> Cell {
+		let background = if value & CELL_OPAQUE != 0 {
+			Some((value >> 16) & 0xFF)
+		}
+		else {
+			None
+		};
+		Cell {
+			character: value & 0xFF,
+			foreground: (value >> 8) & 0xFF,
+			background,
+		}
+	}
+}
+
+pub struct TextMode {
+	enabled: bool,
+	cells: Vec<storage>,
+	dirty: bool,
+}
+
+impl TextMode {
+	pub fn new() -> TextMode {
+		let enabled = false;
+		let cells = vec![0; TEXT_COLUMNS * TEXT_ROWS];
+		let dirty = false;
+		TextMode {
+			enabled,
+			cells,
+			dirty,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		return self.enabled;
+	}
+
+	pub fn cell(&self, column: usize, row: usize) -> Option<Cell> {
+		if column >= TEXT_COLUMNS || row >= TEXT_ROWS {
+			return None;
+		}
+		return Some(Cell::decode(self.cells[row * TEXT_COLUMNS + column]));
+	}
+
+	// true once after anything that changes the picture was written
+	pub fn take_dirty(&mut self) -> bool {
+		let dirty = self.dirty;
+		self.dirty = false;
+		return dirty;
+	}
+}
+
+// how many cells fit on a screen of the size in pixels
+pub fn visible_columns(width: usize) -> usize {
+	return (width / CELL_WIDTH).min(TEXT_COLUMNS);
+}
+
+pub fn visible_rows(height: usize) -> usize {
+	return (height / CELL_HEIGHT).min(TEXT_ROWS);
+}
+
+impl Processor {
+	// TEXT_VISIBLE_COLUMNS and TEXT_VISIBLE_ROWS for the screen as it is now,
+	// None for any other address
+	pub fn text_mode_register(&self, location: location) -> Option<storage> {
+		// checked before the screen size is looked up, this is on every access
+		let offset = location.wrapping_sub(TEXT_MODE_BASE);
+		if offset != TEXT_VISIBLE_COLUMNS && offset != TEXT_VISIBLE_ROWS {
+			return None;
+		}
+		let (width, height) = self.screen_size();
+		if offset == TEXT_VISIBLE_COLUMNS {
+			return Some(visible_columns(width) as storage);
+		}
+		return Some(visible_rows(height) as storage);
+	}
+}
+
+impl Device for TextMode {
+	fn peek(&self, offset: storage) -> storage {
+		match offset {
+			TEXT_ENABLED => {
+				return self.enabled as storage;
+			},
+			_ if offset >= TEXT_CELLS => {
+				return self.cells[(offset - TEXT_CELLS) as usize];
+			},
+			_ => {
+				return 0;
+			},
+		}
+	}
+
+	fn write(&mut self, offset: storage, value: storage) {
+		if offset == TEXT_ENABLED {
+			self.enabled = value != 0;
+			self.dirty = true;
+		}
+		else if offset >= TEXT_CELLS {
+			self.cells[(offset - TEXT_CELLS) as usize] = value;
+			self.dirty = self.dirty || self.enabled;
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use buffers::{BUFFER_OUTPUT_PALETTE, BUFFER_OUTPUT_SCREEN, BUFFER_OUTPUT_SCREEN_SIZE};
+	use font;
+	use framebuffer::{DEFAULT_WIDTH, DEFAULT_HEIGHT};
+	use testing::Machine;
+
+	#[test]
+	fn visible_size_follows_the_screen_before_any_render() {
+		let columns = TEXT_MODE_BASE + TEXT_VISIBLE_COLUMNS;
+		let rows = TEXT_MODE_BASE + TEXT_VISIBLE_ROWS;
+		let mut machine = Machine::new(&format!(".text\nLoadValueAtAddressIntoBus {}", columns));
+		assert_eq!(machine.processor.peek(columns), (DEFAULT_WIDTH / CELL_WIDTH) as storage);
+		assert_eq!(machine.processor.peek(rows), (DEFAULT_HEIGHT / CELL_HEIGHT) as storage);
+		// the screen size changes and is read back without a render
+		machine.buffer(100, &[CELL_WIDTH as storage * 10, 1000], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.run();
+		assert_eq!(machine.processor.bus, 10);
+		assert_eq!(machine.processor.peek(rows), TEXT_ROWS as storage);
+	}
+
+	#[test]
+	fn cells_are_drawn_over_the_screen() {
+		let (width, height) = (2 * CELL_WIDTH, CELL_HEIGHT);
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(100, &[width as storage, height as storage], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(110, &[10, 10, 10, 255, 255, 255, 9, 9, 9], BUFFER_OUTPUT_PALETTE);
+		machine.buffer(200, &vec![0; width * height], BUFFER_OUTPUT_SCREEN);
+		let processor = &mut machine.processor;
+		processor.write_memory(TEXT_MODE_BASE + TEXT_ENABLED, 1).unwrap();
+		// "!" in color 1 on color 2, then "A" in color 1 over the screen
+		processor.write_memory(TEXT_MODE_BASE + TEXT_CELLS, '!' as storage | 1 << 8 | 2 << 16 | CELL_OPAQUE).unwrap();
+		processor.write_memory(TEXT_MODE_BASE + TEXT_CELLS + 1, 'A' as storage | 1 << 8).unwrap();
+		assert!(processor.render_framebuffer());
+
+		// rgba
+		let pixels: Vec<&[u8]> = processor.framebuffer.pixels().chunks(4).collect();
+		for y in 0 .. height {
+			for x in 0 .. width {
+				let (character, background) = if x < CELL_WIDTH { ('!', [9, 9, 9, 255]) } else { ('A', [10, 10, 10, 255]) };
+				let expected = if font::is_set(character as storage, x % CELL_WIDTH, y) { [255, 255, 255, 255] } else { background };
+				assert_eq!(pixels[y * width + x], expected, "pixel {}, {}", x, y);
+			}
+		}
+	}
+
+	#[test]
+	fn disabled_text_mode_draws_nothing() {
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(100, &[CELL_WIDTH as storage, CELL_HEIGHT as storage], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(110, &[10, 10, 10, 255, 255, 255], BUFFER_OUTPUT_PALETTE);
+		machine.buffer(200, &[0; CELL_WIDTH * CELL_HEIGHT], BUFFER_OUTPUT_SCREEN);
+		let processor = &mut machine.processor;
+		processor.write_memory(TEXT_MODE_BASE + TEXT_CELLS, '#' as storage | 1 << 8 | CELL_OPAQUE).unwrap();
+		processor.render_framebuffer();
+		assert!(processor.framebuffer.pixels().chunks(4).all(|pixel| pixel == [10, 10, 10, 255]));
+		assert_eq!(processor.mmio.device::<TextMode>().unwrap().cell(0, 0).unwrap(), Cell {
+			character: '#' as storage,
+			foreground: 1,
+			background: Some(0),
+		});
+	}
+}
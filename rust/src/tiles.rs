@@ -0,0 +1,424 @@
+// Tile map and sprite device.
+//
+// The tileset and the tile map live in VM memory, the device only holds
+// where they are, the scroll offsets and the sprite attribute table. While
+// it is enabled the device composites everything into the OUTPUT_SCREEN
+// buffer at the start of every frame and before every Present, so a program
+// only has to move sprites and scroll instead of drawing pixels itself.
+//
+// Tiles are TILE_SIZE * TILE_SIZE color indices stored row by row, one tile
+// after the other. The map is map width * map height tile indices stored row
+// by row and wraps around when it is scrolled past its edge.
+
+use std::any::Any;
+use super::{Processor, storage, location};
+use mmio::{Device, MMIO_BASE};
+use drawing::signed;
+
+pub const TILES_BASE: location = MMIO_BASE + 0x1000;
+
+pub const TILE_SIZE: i32 = 8;
+pub const MAX_SPRITES: usize = 64;
+
+// registers, the sprite table starts at TILES_SPRITES
+pub const TILES_ENABLED: storage = 0;
+pub const TILES_TILESET: storage = 1;
+pub const TILES_TILE_COUNT: storage = 2;
+pub const TILES_MAP: storage = 3;
+pub const TILES_MAP_WIDTH: storage = 4;
+pub const TILES_MAP_HEIGHT: storage = 5;
+// signed, in pixels
+pub const TILES_SCROLL_X: storage = 6;
+pub const TILES_SCROLL_Y: storage = 7;
+// pixels of this color are see through, in tiles and sprites
+pub const TILES_TRANSPARENT: storage = 8;
+// drawn wherever nothing else is
+pub const TILES_BACKDROP: storage = 9;
+pub const TILES_SPRITES: storage = 16;
+
+// every sprite is [x, y, tile, flags], x and y are signed
+pub const SPRITE_WORDS: storage = 4;
+
+pub const SPRITE_VISIBLE: storage = 1;
+pub const SPRITE_FLIP_X: storage = 2;
+pub const SPRITE_FLIP_Y: storage = 4;
+// only shows through transparent pixels of the tile map
+pub const SPRITE_BEHIND: storage = 8;
+
+const REGISTER_COUNT: usize = TILES_SPRITES as usize;
+
+pub const TILES_SIZE: storage = TILES_SPRITES + MAX_SPRITES as storage * SPRITE_WORDS;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteAttributes {
+	pub x: i32,
+	pub y: i32,
+	pub tile: storage,
+	pub flags: storage,
+}
+
+impl SpriteAttributes {
+	fn has(&self, flag: storage) -> bool {
+		return self.flags & flag != 0;
+	}
+}
+
+pub struct TileEngine {
+	registers: [storage; REGISTER_COUNT],
+	sprites: Vec<storage>,
+}
+
+impl TileEngine {
+	pub fn new() -> TileEngine {
+		let registers = [0; REGISTER_COUNT];
+		let sprites = vec![0; MAX_SPRITES * SPRITE_WORDS as usize];
+		TileEngine {
+			registers,
+			sprites,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		return self.registers[TILES_ENABLED as usize] != 0;
+	}
+
+	pub fn register(&self, register: storage) -> storage {
+		return self.registers[register as usize];
+	}
+
+	pub fn sprite(&self, index: usize) -> SpriteAttributes {
+		let words = &self.sprites[index * SPRITE_WORDS as usize ..];
+		SpriteAttributes {
+			x: signed(words[0]),
+			y: signed(words[1]),
+			tile: words[2],
+			flags: words[3],
+		}
+	}
+}
+
+impl Device for TileEngine {
+	fn peek(&self, offset: storage) -> storage {
+		if offset >= TILES_SPRITES {
+			return self.sprites[(offset - TILES_SPRITES) as usize];
+		}
+		return self.registers[offset as usize];
+	}
+
+	fn write(&mut self, offset: storage, value: storage) {
+		if offset >= TILES_SPRITES {
+			self.sprites[(offset - TILES_SPRITES) as usize] = value;
+		}
+		else {
+			self.registers[offset as usize] = value;
+		}
+	}
+
+	fn as_any(&mut self) -> &mut dyn Any {
+		return self;
+	}
+}
+
+// everything the compositor needs, copied out of the device so VM memory
+// can be read while compositing
+struct Layers {
+	tileset: location,
+	tile_count: storage,
+	map: location,
+	map_width: i32,
+	map_height: i32,
+	scroll_x: i32,
+	scroll_y: i32,
+	transparent: storage,
+	backdrop: storage,
+	sprites: Vec<SpriteAttributes>,
+}
+
+impl Processor {
+	fn tile_layers(&mut self) -> Option<Layers> {
+		let tiles = self.mmio.device::<TileEngine>()?;
+		if !tiles.is_enabled() {
+			return None;
+		}
+		// sprites earlier in the table are drawn on top, so they are drawn last
+		let sprites = (0 .. MAX_SPRITES).rev()
+			.map(|i| tiles.sprite(i))
+			.filter(|s| s.has(SPRITE_VISIBLE))
+			.collect();
+		return Some(Layers {
+			tileset: tiles.register(TILES_TILESET),
+			tile_count: tiles.register(TILES_TILE_COUNT),
+			map: tiles.register(TILES_MAP),
+			map_width: signed(tiles.register(TILES_MAP_WIDTH)).max(0),
+			map_height: signed(tiles.register(TILES_MAP_HEIGHT)).max(0),
+			scroll_x: signed(tiles.register(TILES_SCROLL_X)),
+			scroll_y: signed(tiles.register(TILES_SCROLL_Y)),
+			transparent: tiles.register(TILES_TRANSPARENT),
+			backdrop: tiles.register(TILES_BACKDROP),
+			sprites,
+		});
+	}
+
+	// color of the pixel (x, y) of a tile, None if the tile doesn't exist
+	fn tile_pixel(&mut self, layers: &Layers, tile: storage, x: i32, y: i32) -> Option<storage> {
+		if tile >= layers.tile_count {
+			return None;
+		}
+		let offset = tile as u64 * (TILE_SIZE * TILE_SIZE) as u64 + (y * TILE_SIZE + x) as u64;
+		return Some(self.peek(layers.tileset.wrapping_add(offset as location)));
+	}
+
+	// the tile map pixel under the screen pixel (x, y)
+	fn map_pixel(&mut self, layers: &Layers, x: i32, y: i32) -> Option<storage> {
+		if layers.map_width == 0 || layers.map_height == 0 {
+			return None;
+		}
+		let map_x = (x as i64 + layers.scroll_x as i64).rem_euclid(layers.map_width as i64 * TILE_SIZE as i64) as i32;
+		let map_y = (y as i64 + layers.scroll_y as i64).rem_euclid(layers.map_height as i64 * TILE_SIZE as i64) as i32;
+		let index = (map_y / TILE_SIZE) as u64 * layers.map_width as u64 + (map_x / TILE_SIZE) as u64;
+		let tile = self.peek(layers.map.wrapping_add(index as location));
+		return self.tile_pixel(layers, tile, map_x % TILE_SIZE, map_y % TILE_SIZE);
+	}
+
+	// draws the tile map and the sprites into the screen buffer, the device
+	// writes memory directly so image protection doesn't apply
+	pub fn composite_tiles(&mut self) {
+		let layers = match self.tile_layers() {
+			Some(layers) => layers,
+			None => return,
+		};
+		let screen = match self.screen() {
+			Ok(screen) => screen,
+			Err(_) => return,
+		};
+
+		// None where the map is transparent, so sprites behind it show through
+		let mut background = Vec::with_capacity((screen.width * screen.height) as usize);
+		for y in 0 .. screen.height {
+			for x in 0 .. screen.width {
+				let color = self.map_pixel(&layers, x, y).filter(|&c| c != layers.transparent);
+				background.push(color);
+			}
+		}
+		let mut pixels: Vec<storage> = background.iter().map(|c| c.unwrap_or(layers.backdrop)).collect();
+
+		for sprite in layers.sprites.iter() {
+			for sy in 0 .. TILE_SIZE {
+				for sx in 0 .. TILE_SIZE {
+					let (x, y) = (sprite.x.saturating_add(sx), sprite.y.saturating_add(sy));
+					if x < 0 || y < 0 || x >= screen.width || y >= screen.height {
+						continue;
+					}
+					let i = (y * screen.width + x) as usize;
+					if sprite.has(SPRITE_BEHIND) && background[i].is_some() {
+						continue;
+					}
+					let tx = if sprite.has(SPRITE_FLIP_X) { TILE_SIZE - 1 - sx } else { sx };
+					let ty = if sprite.has(SPRITE_FLIP_Y) { TILE_SIZE - 1 - sy } else { sy };
+					match self.tile_pixel(&layers, sprite.tile, tx, ty) {
+						Some(color) if color != layers.transparent => pixels[i] = color,
+						_ => {},
+					}
+				}
+			}
+		}
+
+		for y in 0 .. screen.height {
+			for x in 0 .. screen.width {
+				if let Some(address) = screen.address(x, y) {
+					self._set_memory_loc(address, pixels[(y * screen.width + x) as usize]);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use testing::Machine;
+	use buffers::{BUFFER_OUTPUT_SCREEN, BUFFER_OUTPUT_SCREEN_SIZE};
+
+	const SCREEN: location = 120;
+	const TILESET: location = 200;
+	const MAP: location = 300;
+
+	#[test]
+	fn tiles_are_composited_in_the_frame_step() {
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(100, &[2, 2], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(SCREEN, &[0, 0, 0, 0], BUFFER_OUTPUT_SCREEN);
+		for offset in 0 .. (TILE_SIZE * TILE_SIZE) as location {
+			machine.processor._set_memory_loc(TILESET + offset, 3);
+		}
+		machine.processor._set_memory_loc(MAP, 0);
+		let registers = [(TILES_TILESET, TILESET), (TILES_TILE_COUNT, 1), (TILES_MAP, MAP), (TILES_MAP_WIDTH, 1), (TILES_MAP_HEIGHT, 1), (TILES_ENABLED, 1)];
+		for &(register, value) in registers.iter() {
+			machine.processor._set_memory_loc(TILES_BASE + register as location, value);
+		}
+
+		// rendering only shows the screen buffer, it doesn't change it
+		machine.processor.render_framebuffer();
+		assert_eq!(machine.processor.peek(SCREEN), 0);
+
+		machine.processor.next_frame();
+		assert_eq!((0 .. 4).map(|i| machine.processor.peek(SCREEN + i)).collect::<Vec<_>>(), vec![3; 4]);
+		assert!(machine.processor.render_framebuffer());
+	}
+
+	const TRANSPARENT: storage = 15;
+	const BACKDROP: storage = 9;
+	// 100 plus the offset of the pixel, except the see through top left one
+	const NUMBERED: storage = 3;
+
+	fn set(machine: &mut Machine, register: storage, value: storage) {
+		machine.processor._set_memory_loc(TILES_BASE + register, value);
+	}
+
+	// an 8 by 8 screen over a one row map, the tileset has the clear tile 0,
+	// the solid tiles 1 and 2 and the NUMBERED tile
+	fn engine(map: &[storage]) -> Machine {
+		let mut machine = Machine::new(".text\nHalt");
+		machine.buffer(100, &[TILE_SIZE as storage, TILE_SIZE as storage], BUFFER_OUTPUT_SCREEN_SIZE);
+		machine.buffer(SCREEN, &[0; (TILE_SIZE * TILE_SIZE) as usize], BUFFER_OUTPUT_SCREEN);
+		let pixels = (TILE_SIZE * TILE_SIZE) as location;
+		for offset in 0 .. pixels {
+			machine.processor._set_memory_loc(TILESET + offset, TRANSPARENT);
+			machine.processor._set_memory_loc(TILESET + pixels + offset, 1);
+			machine.processor._set_memory_loc(TILESET + 2 * pixels + offset, 2);
+			machine.processor._set_memory_loc(TILESET + NUMBERED * pixels + offset, 100 + offset);
+		}
+		machine.processor._set_memory_loc(TILESET + NUMBERED * pixels, TRANSPARENT);
+		for (i, &tile) in map.iter().enumerate() {
+			machine.processor._set_memory_loc(MAP + i as location, tile);
+		}
+		let registers = [
+			(TILES_TILESET, TILESET), (TILES_TILE_COUNT, 4), (TILES_MAP, MAP),
+			(TILES_MAP_WIDTH, map.len() as storage), (TILES_MAP_HEIGHT, 1),
+			(TILES_TRANSPARENT, TRANSPARENT), (TILES_BACKDROP, BACKDROP), (TILES_ENABLED, 1),
+		];
+		for &(register, value) in registers.iter() {
+			set(&mut machine, register, value);
+		}
+		return machine;
+	}
+
+	fn sprite(machine: &mut Machine, index: storage, x: i32, y: i32, tile: storage, flags: storage) {
+		let words = [x as storage, y as storage, tile, flags];
+		for (i, &word) in words.iter().enumerate() {
+			set(machine, TILES_SPRITES + index * SPRITE_WORDS + i as storage, word);
+		}
+	}
+
+	fn pixel(machine: &Machine, x: i32, y: i32) -> storage {
+		return machine.processor.peek(SCREEN + (y * TILE_SIZE + x) as location);
+	}
+
+	fn row(machine: &Machine, y: i32) -> Vec<storage> {
+		return (0 .. TILE_SIZE).map(|x| pixel(machine, x, y)).collect();
+	}
+
+	// the NUMBERED tile's color at (x, y)
+	fn numbered(x: i32, y: i32) -> storage {
+		if x == 0 && y == 0 {
+			return TRANSPARENT;
+		}
+		return 100 + (y * TILE_SIZE + x) as storage;
+	}
+
+	#[test]
+	fn the_map_wraps_when_scrolled() {
+		let mut machine = engine(&[1, 2]);
+		set(&mut machine, TILES_SCROLL_X, 4);
+		machine.processor.composite_tiles();
+		assert_eq!(row(&machine, 0), vec![1, 1, 1, 1, 2, 2, 2, 2]);
+
+		set(&mut machine, TILES_SCROLL_X, -4i32 as storage);
+		set(&mut machine, TILES_SCROLL_Y, -3i32 as storage);
+		machine.processor.composite_tiles();
+		assert_eq!(row(&machine, 0), vec![2, 2, 2, 2, 1, 1, 1, 1]);
+		assert_eq!(row(&machine, 7), row(&machine, 0));
+
+		// a whole turn around the map is no scroll at all
+		set(&mut machine, TILES_SCROLL_X, -(2 * TILE_SIZE) as storage);
+		machine.processor.composite_tiles();
+		assert_eq!(row(&machine, 3), vec![1; TILE_SIZE as usize]);
+	}
+
+	#[test]
+	fn transparent_pixels_show_the_backdrop() {
+		let mut machine = engine(&[NUMBERED]);
+		machine.processor.composite_tiles();
+		assert_eq!(pixel(&machine, 0, 0), BACKDROP);
+		assert_eq!(pixel(&machine, 1, 0), numbered(1, 0));
+		assert_eq!(pixel(&machine, 7, 7), numbered(7, 7));
+
+		// tiles past the tile count are see through too
+		let mut missing = engine(&[4]);
+		missing.processor.composite_tiles();
+		assert_eq!(row(&missing, 0), vec![BACKDROP; TILE_SIZE as usize]);
+	}
+
+	#[test]
+	fn sprites_flip() {
+		for &flags in [0, SPRITE_FLIP_X, SPRITE_FLIP_Y, SPRITE_FLIP_X | SPRITE_FLIP_Y].iter() {
+			let mut machine = engine(&[1]);
+			sprite(&mut machine, 0, 0, 0, NUMBERED, SPRITE_VISIBLE | flags);
+			machine.processor.composite_tiles();
+			for y in 0 .. TILE_SIZE {
+				for x in 0 .. TILE_SIZE {
+					let tx = if flags & SPRITE_FLIP_X != 0 { TILE_SIZE - 1 - x } else { x };
+					let ty = if flags & SPRITE_FLIP_Y != 0 { TILE_SIZE - 1 - y } else { y };
+					// the see through pixel shows the map
+					let expected = if (tx, ty) == (0, 0) { 1 } else { numbered(tx, ty) };
+					assert_eq!(pixel(&machine, x, y), expected, "flags {} pixel {}, {}", flags, x, y);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn sprites_clip_at_the_edges() {
+		let mut machine = engine(&[1]);
+		sprite(&mut machine, 0, -4, -4, NUMBERED, SPRITE_VISIBLE);
+		sprite(&mut machine, 1, 6, i32::MAX, 2, SPRITE_VISIBLE);
+		machine.processor.composite_tiles();
+		assert_eq!(pixel(&machine, 0, 0), numbered(4, 4));
+		assert_eq!(pixel(&machine, 3, 3), numbered(7, 7));
+		assert_eq!(pixel(&machine, 4, 4), 1);
+		assert_eq!(row(&machine, 7), vec![1; TILE_SIZE as usize]);
+	}
+
+	#[test]
+	fn earlier_sprites_are_on_top() {
+		let mut machine = engine(&[1]);
+		sprite(&mut machine, 0, 0, 0, 2, SPRITE_VISIBLE);
+		sprite(&mut machine, 1, 4, 0, NUMBERED, SPRITE_VISIBLE);
+		// not visible, it would cover everything
+		sprite(&mut machine, 2, 0, 0, 1, 0);
+		machine.processor.composite_tiles();
+		assert_eq!(row(&machine, 1), vec![2, 2, 2, 2, 2, 2, 2, 2]);
+
+		sprite(&mut machine, 0, 0, 0, NUMBERED, SPRITE_VISIBLE);
+		sprite(&mut machine, 1, 0, 0, 2, SPRITE_VISIBLE);
+		machine.processor.composite_tiles();
+		assert_eq!(pixel(&machine, 3, 1), numbered(3, 1));
+		// the top sprite's see through pixel shows the sprite under it
+		assert_eq!(pixel(&machine, 0, 0), 2);
+	}
+
+	#[test]
+	fn sprites_behind_the_map_show_through_its_see_through_pixels() {
+		let mut machine = engine(&[NUMBERED]);
+		sprite(&mut machine, 0, 0, 0, 2, SPRITE_VISIBLE | SPRITE_BEHIND);
+		machine.processor.composite_tiles();
+		assert_eq!(pixel(&machine, 0, 0), 2);
+		assert_eq!(pixel(&machine, 1, 0), numbered(1, 0));
+
+		// in front of the backdrop everywhere
+		let mut clear = engine(&[0]);
+		sprite(&mut clear, 0, 0, 0, 2, SPRITE_VISIBLE | SPRITE_BEHIND);
+		clear.processor.composite_tiles();
+		assert_eq!(row(&clear, 5), vec![2; TILE_SIZE as usize]);
+	}
+}
@@ -0,0 +1,39 @@
+// dsl/hello.dsla is dsl/hello.dsl written in assembly, both have to leave
+// the same screen behind.
+
+extern crate dsl_wasm;
+
+use std::fs;
+use std::io;
+use dsl_wasm::native::{Runner, ExitReason, assemble_file, link_objects};
+use dsl_wasm::listing::Listing;
+
+const HELLO_LISTING: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../dsl/hello.dsl");
+const HELLO_ASSEMBLY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../dsl/hello.dsla");
+
+// SCREEN and SCREEN_SIZE of hello.dsla
+const SCREEN: u32 = 2049;
+const SCREEN_SIZE: u32 = 1024;
+
+fn screen(runner: &mut Runner) -> Vec<u32> {
+	let mut output = Vec::new();
+	let exit = runner.run(&mut io::empty(), &mut output).unwrap();
+	assert_eq!((exit.reason, exit.code), (ExitReason::Halt, 0));
+	return (SCREEN .. SCREEN + SCREEN_SIZE).map(|address| runner.read_memory(address)).collect();
+}
+
+#[test]
+fn hello_in_assembly_draws_the_same_screen() {
+	let mut listing = Runner::new();
+	listing.load_listing(Listing::parse(&fs::read_to_string(HELLO_LISTING).unwrap()).unwrap());
+	let expected = screen(&mut listing);
+
+	let object = assemble_file(HELLO_ASSEMBLY).unwrap();
+	let mut assembly = Runner::new();
+	assembly.load_assembly(link_objects(&[object]).unwrap());
+	let actual = screen(&mut assembly);
+
+	assert_eq!(actual, expected);
+	assert_eq!(actual[0], 1);
+	assert!(actual[1 ..].iter().all(|&pixel| pixel == 0));
+}
@@ -0,0 +1,303 @@
+// Runs each routine of the standard library on the processor, linked with a
+// small program the way dsl-run --stdlib links it.
+
+extern crate dsl_wasm;
+
+use std::io;
+use dsl_wasm::assembler::{assemble_object, Symbol};
+use dsl_wasm::native::{Runner, ExitReason};
+use dsl_wasm::object::link;
+use dsl_wasm::stdlib;
+
+// more than any of the programs here need
+const STEP_LIMIT: u64 = 1_000_000;
+
+struct Program {
+	runner: Runner,
+	symbols: Vec<Symbol>,
+}
+
+impl Program {
+	// the source is everything after the include of the header
+	fn run(source: &str) -> Program {
+		let source = format!(".include \"std-header.dsla\"\n{}", source);
+		let mut read_file = |name: &str| stdlib::file(name).map(str::to_string).ok_or(format!("no file {}", name));
+		let program = match assemble_object("test.dsla", &source, &mut read_file) {
+			Ok(object) => object,
+			Err(errors) => panic!("{}", errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")),
+		};
+		let assembly = match link(&[program, stdlib::object()]) {
+			Ok(assembly) => assembly,
+			Err(errors) => panic!("{}", errors[0]),
+		};
+
+		let symbols = assembly.symbols.clone();
+		let mut runner = Runner::new();
+		runner.load_assembly(assembly);
+		runner.set_step_limit(STEP_LIMIT);
+		let mut output = Vec::new();
+		let exit = runner.run(&mut io::empty(), &mut output).unwrap();
+		assert_eq!(exit.reason, ExitReason::Halt, "stopped at {} with code {}", exit.address, exit.code);
+		return Program {
+			runner,
+			symbols,
+		};
+	}
+
+	fn address(&self, name: &str) -> u32 {
+		return self.symbols.iter().find(|symbol| symbol.name == name).unwrap_or_else(|| panic!("no symbol {}", name)).address;
+	}
+
+	fn word(&mut self, name: &str, offset: u32) -> u32 {
+		let address = self.address(name);
+		return self.runner.read_memory(address + offset);
+	}
+
+	fn words(&mut self, name: &str, count: u32) -> Vec<u32> {
+		return (0 .. count).map(|offset| self.word(name, offset)).collect();
+	}
+
+	fn number(&mut self, name: &str) -> i32 {
+		return self.word(name, 0) as i32;
+	}
+
+	// up to the 0
+	fn string(&mut self, name: &str) -> String {
+		let mut string = String::new();
+		let mut offset = 0;
+		loop {
+			match self.word(name, offset) {
+				0 => return string,
+				c => string.push(::std::char::from_u32(c).unwrap()),
+			}
+			offset += 1;
+		}
+	}
+}
+
+// calls the routine with the arguments and returns result and result2
+fn call(routine: &str, args: &[i32]) -> (i32, i32) {
+	let mut source = String::from(".text\n");
+	for (index, arg) in args.iter().enumerate() {
+		source.push_str(&format!("loadi arg{} {}\n", index, arg));
+	}
+	source.push_str(&format!("call {}\n", routine));
+	let mut program = Program::run(&source);
+	return (program.number("result"), program.number("result2"));
+}
+
+#[test]
+fn call_returns_after_the_call() {
+	let mut program = Program::run("
+.data
+var after number 0
+.text
+loadi arg0 -5
+call abs
+loadi after 1
+");
+	assert_eq!(program.number("after"), 1);
+	assert_eq!(program.number("result"), 5);
+}
+
+#[test]
+fn memcpy_copies_words() {
+	let mut program = Program::run("
+.data
+var source array 1 2 3 4
+var dest array 9 9 9 9 9
+.text
+loadi arg0 dest
+loadi arg1 source
+loadi arg2 4
+call memcpy
+");
+	assert_eq!(program.words("dest", 5), vec![1, 2, 3, 4, 9]);
+}
+
+#[test]
+fn memset_sets_words() {
+	let mut program = Program::run("
+.data
+var dest array 1 1 1 1
+.text
+loadi arg0 dest + 1
+loadi arg1 7
+loadi arg2 2
+call memset
+");
+	assert_eq!(program.words("dest", 4), vec![1, 7, 7, 1]);
+}
+
+#[test]
+fn strlen_counts_characters() {
+	let mut program = Program::run("
+.data
+var text string \"hello\"
+var terminator number 0
+var empty number 0
+var lengths array 0 0
+.text
+loadi arg0 text
+call strlen
+move lengths result
+loadi arg0 empty
+call strlen
+move lengths+1 result
+");
+	assert_eq!(program.words("lengths", 2), vec![5, 0]);
+}
+
+#[test]
+fn strcpy_copies_the_terminator() {
+	let mut program = Program::run("
+.data
+var text string \"dsl\"
+var terminator number 0
+var dest array 9 9 9 9 9
+.text
+loadi arg0 dest
+loadi arg1 text
+call strcpy
+");
+	assert_eq!(program.number("result"), 3);
+	assert_eq!(program.string("dest"), "dsl");
+	assert_eq!(program.word("dest", 4), 9);
+}
+
+#[test]
+fn strcmp_orders_strings() {
+	let mut program = Program::run("
+.data
+var apple string \"apple\"
+var apple_end number 0
+var apples string \"apples\"
+var apples_end number 0
+var pear string \"pear\"
+var pear_end number 0
+var results array 0 0 0 0
+.text
+loadi arg0 apple
+loadi arg1 apple
+call strcmp
+move results result
+loadi arg0 apple
+loadi arg1 pear
+call strcmp
+move results+1 result
+loadi arg0 pear
+loadi arg1 apple
+call strcmp
+move results+2 result
+loadi arg0 apples
+loadi arg1 apple
+call strcmp
+move results+3 result
+");
+	let results: Vec<i32> = program.words("results", 4).iter().map(|&word| word as i32).collect();
+	assert_eq!(results, vec![0, -1, 1, 1]);
+}
+
+#[test]
+fn format_int_writes_decimal() {
+	let cases: &[(i32, &str)] = &[(0, "0"), (7, "7"), (-42, "-42"), (1234567890, "1234567890"), (i32::MAX, "2147483647"), (i32::MIN, "-2147483648")];
+	for &(value, text) in cases.iter() {
+		let mut program = Program::run(&format!("
+.data
+var dest array 0 0 0 0 0 0 0 0 0 0 0 0
+.text
+loadi arg0 {}
+loadi arg1 dest
+call format_int
+", value));
+		assert_eq!(program.string("dest"), text);
+		assert_eq!(program.number("result"), text.len() as i32);
+	}
+}
+
+#[test]
+fn parse_int_reads_decimal() {
+	let cases: &[(&str, i32)] = &[("0", 0), ("42", 42), ("-17", -17), ("123abc", 123), ("x", 0), ("2147483647", i32::MAX), ("-2147483648", i32::MIN)];
+	for &(text, value) in cases.iter() {
+		let mut program = Program::run(&format!("
+.data
+var text string \"{}\"
+var terminator number 0
+.text
+loadi arg0 text
+call parse_int
+", text));
+		assert_eq!(program.number("result"), value, "parsing {}", text);
+	}
+}
+
+#[test]
+fn abs_min_max() {
+	assert_eq!(call("abs", &[-3]).0, 3);
+	assert_eq!(call("abs", &[3]).0, 3);
+	assert_eq!(call("min", &[4, -2]).0, -2);
+	assert_eq!(call("min", &[-2, 4]).0, -2);
+	assert_eq!(call("max", &[4, -2]).0, 4);
+	assert_eq!(call("max", &[-2, 4]).0, 4);
+}
+
+#[test]
+fn divide_rounds_towards_zero() {
+	assert_eq!(call("divide", &[17, 5]), (3, 2));
+	assert_eq!(call("divide", &[-17, 5]), (-3, -2));
+	assert_eq!(call("divide", &[17, -5]), (-3, 2));
+	assert_eq!(call("divide", &[9, 0]), (0, 9));
+	assert_eq!(call("divide", &[i32::MIN, -1]), (i32::MIN, 0));
+}
+
+#[test]
+fn pow_multiplies() {
+	assert_eq!(call("pow", &[3, 4]).0, 81);
+	assert_eq!(call("pow", &[-2, 3]).0, -8);
+	assert_eq!(call("pow", &[5, 0]).0, 1);
+}
+
+#[test]
+fn sqrt_rounds_down() {
+	let cases: &[(i32, i32)] = &[(0, 0), (1, 1), (2, 1), (3, 1), (4, 2), (15, 3), (16, 4), (99, 9), (1000000, 1000), (i32::MAX, 46340), (-4, 0)];
+	for &(value, root) in cases.iter() {
+		assert_eq!(call("sqrt", &[value]).0, root, "sqrt of {}", value);
+	}
+}
+
+#[test]
+fn screen_and_pixels() {
+	let mut program = Program::run("
+.data
+var palette array 0 0 0 255 255 255
+var screen array 0 0 0 0 0 0 0 0 0 0 0 0
+var statuses array 9 9
+.text
+loadi arg0 screen
+loadi arg1 4
+loadi arg2 3
+call screen_init
+move statuses result
+loadi arg0 palette
+loadi arg1 2
+call palette_init
+move statuses+1 result
+loadi arg0 2
+call fill_screen
+loadi arg0 1
+loadi arg1 2
+loadi arg2 1
+call plot
+// outside of the screen
+loadi arg0 4
+loadi arg1 0
+loadi arg2 1
+call plot
+loadi arg0 0
+loadi arg1 -1
+loadi arg2 1
+call plot
+");
+	assert_eq!(program.words("statuses", 2), vec![0, 0]);
+	assert_eq!(program.words("screen", 12), vec![2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 2, 2]);
+}
@@ -16,7 +16,7 @@ echo "Cleaning \dist ..."
 rm -rf ../dist/*
 
 # Compile to wasm
-cargo +nightly build --target wasm32-unknown-unknown --release
+cargo +nightly build --lib --target wasm32-unknown-unknown --release
 
 # Move to dist
 cp "target/wasm32-unknown-unknown/release/$wasmFilename" "../dist"